@@ -1,9 +1,11 @@
 use axum::{
+    response::sse::{Event, KeepAlive, Sse},
     routing::{get, post},
     Router,
 };
 use tower_http::cors::{Any, CorsLayer};
 use serde::Deserialize;
+use std::collections::HashMap;
 
 use crate::commands::character_binding::{
     delete_reference_image, get_all_tags, get_references_by_type, get_reference_images, search_reference_images, CharacterBinding, CHARACTER_BINDINGS,
@@ -11,13 +13,30 @@ use crate::commands::character_binding::{
 };
 use crate::commands::prompt_parser::parse_prompt_internal;
 use crate::commands::prompt_parser::ParsedPrompt;
+use crate::commands::batch::{BatchFormat, BatchManifest};
+use crate::commands::generation_queue::JobPriority;
+use crate::commands::pdf_export::{PdfExportOptions, PdfExportReport};
+use crate::commands::epub_export::{EpubExportOptions, EpubExportReport};
+use crate::commands::gallery::{self, GalleryItem, GalleryPage, GalleryQuery};
+use crate::commands::history::{self, HistoryEntry, HistoryFilter, HistoryPage};
+use crate::commands::text_overlay::{TextOverlayOptions, TextOverlayResult};
+use crate::commands::image_metadata::ImportedImage;
+use crate::commands::image_files::data_dir_path;
+use crate::commands::project::{
+    self, PageGenerationResult, PageUpdate, Project, ProjectSettings, ProjectSummary,
+};
 use crate::commands::image_generator::{
-    ApiConfig, CharacterBindingInfo, GenerationConfig, ImageGenerationParams, ImageGenerationResult,
+    ApiConfig, CharacterBindingInfo, GenerationConfig, GenerationEvent, ImageGenerationParams,
+    ImageGenerationResult,
 };
+use crate::commands::providers::{list_image_providers, ProviderInfo, RetryPolicy};
+use crate::error::{AppError, AppResult, ErrorCode};
 
 #[derive(Debug, Deserialize)]
 pub struct ImageQuery {
     path: String,
+    /// Longest side in px; serves a cached thumbnail instead of the original.
+    size: Option<u32>,
 }
 
 pub async fn api_get_image(
@@ -40,10 +59,29 @@ pub async fn api_get_image(
         return Err(axum::http::StatusCode::NOT_FOUND);
     }
     
+    // Thumbnails fall back to the original when the file cannot be decoded
+    let thumbnail = match query.size {
+        Some(size) => {
+            let source = std::path::PathBuf::from(&path);
+            tokio::task::spawn_blocking(move || crate::commands::thumbnails::thumbnail(&source, size))
+                .await
+                .ok()
+                .and_then(|r| r.map_err(|e| eprintln!("api_get_image: thumbnail failed: {}", e)).ok())
+        }
+        None => None,
+    };
+    let (path, path_lower) = match thumbnail {
+        Some(thumb) => {
+            let thumb = thumb.to_string_lossy().to_string();
+            let lower = thumb.to_lowercase();
+            (thumb, lower)
+        }
+        None => (path, query.path.to_lowercase()),
+    };
+    
     match std::fs::read(&path) {
         Ok(data) => {
             eprintln!("api_get_image: success, format size: {}", data.len());
-            let path_lower = query.path.to_lowercase();
             let mime_type = if path_lower.ends_with(".png") {
                 "image/png"
             } else if path_lower.ends_with(".jpg") || path_lower.ends_with(".jpeg") {
@@ -124,8 +162,8 @@ pub struct DeleteReferenceBody {
 
 pub async fn api_delete_reference_image_handler(
     axum::Json(body): axum::Json<DeleteReferenceBody>
-) -> Result<impl axum::response::IntoResponse, axum::http::StatusCode> {
-    let result = delete_reference_image(body.character_name);
+) -> AppResult<axum::Json<bool>> {
+    let result = delete_reference_image(body.character_name)?;
     Ok(axum::Json(result))
 }
 
@@ -165,30 +203,30 @@ pub struct UnbindBody {
     character_name: String,
 }
 
-async fn api_parse_prompt(axum::Json(body): axum::Json<ParseBody>) -> Result<axum::Json<ParsedPrompt>, (axum::http::StatusCode, String)> {
+async fn api_parse_prompt(axum::Json(body): axum::Json<ParseBody>) -> AppResult<axum::Json<ParsedPrompt>> {
     parse_prompt_internal(&body.prompt)
         .map(axum::Json)
         .map_err(|e| {
             eprintln!("api_parse_prompt error: {}", e);
-            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e)
+            e
         })
 }
 
-async fn api_get_all_bindings() -> Result<axum::Json<Vec<CharacterBinding>>, String> {
-    let bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
+async fn api_get_all_bindings() -> AppResult<axum::Json<Vec<CharacterBinding>>> {
+    let bindings = CHARACTER_BINDINGS.lock()?;
     let result: Vec<CharacterBinding> = bindings.values().cloned().collect();
     Ok(axum::Json(result))
 }
 
 async fn api_get_bindings_for_prompt(
     axum::Json(body): axum::Json<BindingsQuery>,
-) -> Result<axum::Json<Vec<CharacterBinding>>, String> {
+) -> AppResult<axum::Json<Vec<CharacterBinding>>> {
     let characters: Vec<String> = body
         .characters
         .map(|c| c.split(',').map(|s| s.to_string()).collect())
         .unwrap_or_default();
     
-    let bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
+    let bindings = CHARACTER_BINDINGS.lock()?;
     let result: Vec<CharacterBinding> = characters
         .iter()
         .filter_map(|name| bindings.get(name).cloned())
@@ -198,45 +236,52 @@ async fn api_get_bindings_for_prompt(
 
 async fn api_save_reference_image(
     axum::Json(body): axum::Json<SaveImageBody>,
-) -> Result<axum::Json<CharacterBinding>, String> {
+) -> AppResult<axum::Json<CharacterBinding>> {
     use crate::commands::character_binding::save_reference_image;
     let result = save_reference_image(
         body.character_name,
         body.image_data,
         body.image_type,
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(axum::Json(result))
 }
 
 async fn api_bind_character_reference(
     axum::Json(body): axum::Json<BindBody>,
-) -> Result<axum::Json<CharacterBinding>, String> {
+) -> AppResult<axum::Json<CharacterBinding>> {
     use crate::commands::character_binding::bind_character_reference;
     let result = bind_character_reference(
         body.character_name,
         body.reference_image_path.unwrap_or_default(),
         body.image_type,
-    ).map_err(|e| e.to_string())?;
+    )?;
     Ok(axum::Json(result))
 }
 
 async fn api_unbind_character(
     axum::Json(body): axum::Json<UnbindBody>,
-) -> Result<axum::Json<bool>, String> {
+) -> AppResult<axum::Json<bool>> {
     use crate::commands::character_binding::unbind_character;
-    let result = unbind_character(body.character_name).unwrap_or(false);
+    let result = unbind_character(body.character_name)?;
     Ok(axum::Json(result))
 }
 
+/// Generation params from HTTP clients, accepting both camelCase and snake_case keys.
 #[derive(Debug, Deserialize)]
 pub struct GenerateImageBody {
+    #[serde(default = "default_model")]
     model: String,
+    #[serde(default)]
     prompt: String,
-    #[serde(alias = "characterBindings", alias = "character_bindings")]
+    #[serde(default, alias = "characterBindings", alias = "character_bindings")]
     character_bindings: Vec<CharacterBindingInfoBody>,
+    #[serde(default = "default_dimension")]
     width: u32,
+    #[serde(default = "default_dimension")]
     height: u32,
+    #[serde(default = "default_count")]
     count: u32,
+    #[serde(default = "default_quality")]
     quality: String,
     size: Option<String>,
     #[serde(alias = "sequentialImageGeneration", alias = "sequential_image_generation")]
@@ -245,6 +290,29 @@ pub struct GenerateImageBody {
     response_format: Option<String>,
     watermark: Option<bool>,
     images: Option<Vec<String>>,
+    #[serde(alias = "negativePrompt", alias = "negative_prompt")]
+    negative_prompt: Option<String>,
+    seed: Option<i64>,
+    #[serde(alias = "aspectRatio", alias = "aspect_ratio")]
+    aspect_ratio: Option<String>,
+    #[serde(default)]
+    priority: JobPriority,
+}
+
+fn default_model() -> String {
+    "seedream".to_string()
+}
+
+fn default_dimension() -> u32 {
+    1024
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+fn default_quality() -> String {
+    "standard".to_string()
 }
 
 #[derive(Debug, Deserialize)]
@@ -257,64 +325,260 @@ struct CharacterBindingInfoBody {
     image_type: Option<String>,
 }
 
+impl From<GenerateImageBody> for ImageGenerationParams {
+    fn from(body: GenerateImageBody) -> Self {
+        ImageGenerationParams {
+            model: body.model,
+            prompt: body.prompt,
+            character_bindings: body
+                .character_bindings
+                .into_iter()
+                .map(|b| CharacterBindingInfo {
+                    character_name: b.character_name,
+                    reference_image_path: b.reference_image_path,
+                    image_type: b.image_type.unwrap_or_else(|| "人物".to_string()),
+                })
+                .collect(),
+            width: body.width,
+            height: body.height,
+            count: body.count,
+            quality: body.quality,
+            size: body.size,
+            sequential_image_generation: body.sequential_image_generation,
+            response_format: body.response_format,
+            watermark: body.watermark,
+            images: body.images,
+            negative_prompt: body.negative_prompt,
+            seed: body.seed,
+            aspect_ratio: body.aspect_ratio,
+            priority: body.priority,
+        }
+    }
+}
+
+/// Failed generations keep the `ImageGenerationResult` body; the status follows its `error_code`.
 async fn api_generate_image(
-    axum::Json(body): axum::Json<serde_json::Value>,
-) -> Result<axum::Json<crate::commands::image_generator::ImageGenerationResult>, String> {
-    use crate::commands::image_generator::{generate_image, CharacterBindingInfo, ImageGenerationParams, ImageGenerationResult};
+    axum::Json(body): axum::Json<GenerateImageBody>,
+) -> AppResult<(axum::http::StatusCode, axum::Json<ImageGenerationResult>)> {
+    use crate::commands::image_generator::generate_image_and_wait;
     
-    let model = body.get("model").and_then(|v| v.as_str()).unwrap_or("seedream").to_string();
-    let prompt = body.get("prompt").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    let width = body.get("width").and_then(|v| v.as_u64()).unwrap_or(1024) as u32;
-    let height = body.get("height").and_then(|v| v.as_u64()).unwrap_or(1024) as u32;
-    let count = body.get("count").and_then(|v| v.as_u64()).unwrap_or(1) as u32;
-    let quality = body.get("quality").and_then(|v| v.as_str()).unwrap_or("standard").to_string();
+    let params = ImageGenerationParams::from(body);
     
-    let character_bindings: Vec<CharacterBindingInfo> = if let Some(arr) = body.get("characterBindings").or_else(|| body.get("character_bindings")).and_then(|v| v.as_array()) {
-        arr.iter().filter_map(|b| {
-            let character_name = b.get("characterName").or_else(|| b.get("character_name"))?.as_str()?.to_string();
-            let reference_image_path = b.get("referenceImagePath").or_else(|| b.get("reference_image_path")).and_then(|v| v.as_str()).map(|s| s.to_string());
-            let image_type = b.get("imageType").or_else(|| b.get("image_type")).and_then(|v| v.as_str()).unwrap_or("人物").to_string();
-            Some(CharacterBindingInfo { character_name, reference_image_path, image_type })
-        }).collect()
-    } else {
-        vec![]
-    };
+    let result = generate_image_and_wait(params).await.map_err(|e| {
+        eprintln!("generate_image error: {}", e);
+        e
+    })?;
+    eprintln!("generate_image result: {:?}", result);
     
-    let params = ImageGenerationParams {
-        model,
-        prompt,
-        character_bindings,
-        width,
-        height,
-        count,
-        quality,
-        size: body.get("size").and_then(|v| v.as_str()).map(|s| s.to_string()),
-        sequential_image_generation: body.get("sequentialImageGeneration").or_else(|| body.get("sequential_image_generation")).and_then(|v| v.as_str()).map(|s| s.to_string()),
-        response_format: body.get("responseFormat").or_else(|| body.get("response_format")).and_then(|v| v.as_str()).map(|s| s.to_string()),
-        watermark: body.get("watermark").and_then(|v| v.as_bool()),
-        images: body.get("images").and_then(|v| v.as_array()).map(|arr| {
-            arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect()
-        }),
+    Ok((result_status(&result), axum::Json(result)))
+}
+
+/// A failed generation answers with the status of its error code, like other API errors.
+fn result_status(result: &ImageGenerationResult) -> axum::http::StatusCode {
+    match (result.success, result.error_code) {
+        (true, _) => axum::http::StatusCode::OK,
+        (false, Some(code)) => code.status(),
+        (false, None) => ErrorCode::Internal.status(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelBody {
+    #[serde(alias = "taskId")]
+    task_id: String,
+}
+
+async fn api_cancel_generation(
+    axum::Json(body): axum::Json<CancelBody>,
+) -> AppResult<axum::Json<bool>> {
+    crate::commands::image_generator::cancel_generation(body.task_id).map(axum::Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryBody {
+    id: String,
+}
+
+async fn api_list_history(
+    axum::Json(filter): axum::Json<HistoryFilter>,
+) -> AppResult<axum::Json<HistoryPage>> {
+    Ok(axum::Json(history::list_history(Some(filter))?))
+}
+
+async fn api_get_history_entry(
+    axum::Json(body): axum::Json<HistoryBody>,
+) -> AppResult<axum::Json<HistoryEntry>> {
+    Ok(axum::Json(history::get_history_entry(body.id)?))
+}
+
+async fn api_delete_history_entry(
+    axum::Json(body): axum::Json<HistoryBody>,
+) -> AppResult<axum::Json<bool>> {
+    Ok(axum::Json(history::delete_history_entry(body.id)?))
+}
+
+/// Runs a past generation again and waits for it, like `/api/generate`.
+async fn api_rerun_generation(
+    axum::Json(body): axum::Json<HistoryBody>,
+) -> AppResult<(axum::http::StatusCode, axum::Json<ImageGenerationResult>)> {
+    use crate::commands::image_generator::generate_image_and_wait;
+    let params = history::rerun_params(body.id)?;
+    let result = generate_image_and_wait(params).await?;
+    Ok((result_status(&result), axum::Json(result)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GalleryBody {
+    id: Option<String>,
+    #[serde(default)]
+    ids: Vec<String>,
+    favorite: Option<bool>,
+    rating: Option<u8>,
+    tags: Option<Vec<String>>,
+}
+
+async fn api_list_gallery(
+    axum::Json(query): axum::Json<GalleryQuery>,
+) -> AppResult<axum::Json<GalleryPage>> {
+    Ok(axum::Json(gallery::list_gallery(Some(query))?))
+}
+
+async fn api_list_gallery_tags() -> AppResult<axum::Json<Vec<String>>> {
+    Ok(axum::Json(gallery::list_gallery_tags()?))
+}
+
+async fn api_set_gallery_favorite(
+    axum::Json(body): axum::Json<GalleryBody>,
+) -> AppResult<axum::Json<GalleryItem>> {
+    let id = required(body.id, "id")?;
+    Ok(axum::Json(gallery::set_gallery_favorite(id, required(body.favorite, "favorite")?)?))
+}
+
+async fn api_set_gallery_rating(
+    axum::Json(body): axum::Json<GalleryBody>,
+) -> AppResult<axum::Json<GalleryItem>> {
+    let id = required(body.id, "id")?;
+    Ok(axum::Json(gallery::set_gallery_rating(id, required(body.rating, "rating")?)?))
+}
+
+async fn api_set_gallery_tags(
+    axum::Json(body): axum::Json<GalleryBody>,
+) -> AppResult<axum::Json<GalleryItem>> {
+    let id = required(body.id, "id")?;
+    Ok(axum::Json(gallery::set_gallery_tags(id, required(body.tags, "tags")?)?))
+}
+
+async fn api_trash_gallery_items(
+    axum::Json(body): axum::Json<GalleryBody>,
+) -> AppResult<axum::Json<usize>> {
+    Ok(axum::Json(gallery::trash_gallery_items(body.ids)?))
+}
+
+async fn api_restore_gallery_items(
+    axum::Json(body): axum::Json<GalleryBody>,
+) -> AppResult<axum::Json<usize>> {
+    Ok(axum::Json(gallery::restore_gallery_items(body.ids)?))
+}
+
+async fn api_empty_gallery_trash() -> AppResult<axum::Json<usize>> {
+    Ok(axum::Json(gallery::empty_gallery_trash()?))
+}
+
+/// State of one `/api/generate/stream` response.
+struct GenerationStream {
+    events: tokio::sync::broadcast::Receiver<GenerationEvent>,
+    task_id: String,
+    /// Final result waiting to be sent as the `result` event.
+    pending: Option<ImageGenerationResult>,
+    finished: bool,
+}
+
+/// Same body as `/api/generate`, answered as Server-Sent Events.
+///
+/// Emits one `progress` event per stage change (`GenerationEvent` without its result),
+/// then a single `result` event carrying the `ImageGenerationResult`, then closes.
+async fn api_generate_image_stream(
+    axum::Json(body): axum::Json<GenerateImageBody>,
+) -> Sse<impl futures_util::Stream<Item = Result<Event, axum::Error>>> {
+    use crate::commands::image_generator::{
+        generate_image, get_generation_progress, subscribe_generation_events,
     };
-    
-    let result = match generate_image(params).await {
-        Ok(r) => r,
+
+    let params = ImageGenerationParams::from(body);
+    // Subscribe before the task exists so the `queued` event is not missed
+    let events = subscribe_generation_events();
+
+    let state = match generate_image(params).await {
+        Ok(started) => GenerationStream {
+            events,
+            task_id: started.task_id,
+            pending: None,
+            finished: false,
+        },
         Err(e) => {
             eprintln!("generate_image error: {}", e);
-            ImageGenerationResult {
-                success: false,
-                images: vec![],
-                error: Some(e),
+            GenerationStream {
+                events,
                 task_id: String::new(),
+                pending: Some(ImageGenerationResult {
+                    success: false,
+                    images: vec![],
+                    outputs: vec![],
+                    error: Some(e.message),
+                    task_id: String::new(),
+                    field_errors: e.field_errors,
+                    effective_size: None,
+                    aspect_ratio: None,
+                    seed: None,
+                    error_code: Some(e.code),
+                }),
+                finished: false,
             }
         }
     };
-    eprintln!("generate_image result: {:?}", result);
-    Ok(axum::Json(result))
+
+    let stream = futures_util::stream::unfold(state, |mut state| async move {
+        if let Some(result) = state.pending.take() {
+            state.finished = true;
+            return Some((Event::default().event("result").json_data(&result), state));
+        }
+        if state.finished {
+            return None;
+        }
+
+        loop {
+            match state.events.recv().await {
+                Ok(event) if event.task_id == state.task_id => {
+                    state.pending = event.result.clone();
+                    let progress = GenerationEvent { result: None, ..event };
+                    return Some((Event::default().event("progress").json_data(&progress), state));
+                }
+                Ok(_) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    eprintln!("generation stream lagged, skipped {} events", skipped);
+                    // The skipped events may include the final one; check the task directly
+                    match get_generation_progress(state.task_id.clone()) {
+                        Ok(progress) => {
+                            if let Some(result) = progress.result {
+                                state.finished = true;
+                                return Some((Event::default().event("result").json_data(&result), state));
+                            }
+                        }
+                        Err(_) => return None,
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ApiConfigBody {
+    #[serde(default)]
+    providers: HashMap<String, ModelConfigBody>,
     seedream: Option<ModelConfigBody>,
     #[serde(alias = "bananaPro")]
     banana_pro: Option<ModelConfigBody>,
@@ -326,64 +590,95 @@ pub struct ModelConfigBody {
     base_url: Option<String>,
     #[serde(alias = "apiKey", alias = "api_key")]
     api_key: Option<String>,
+    options: Option<HashMap<String, serde_json::Value>>,
+    retry: Option<RetryPolicy>,
+    #[serde(alias = "maxConcurrency", alias = "max_concurrency")]
+    max_concurrency: Option<u32>,
+}
+
+/// Serializes an `ApiConfig` for HTTP clients. The `seedream` / `bananaPro`
+/// keys are kept alongside `providers` for clients written against the old shape.
+fn api_config_to_json(config: &ApiConfig) -> serde_json::Value {
+    let providers: serde_json::Map<String, serde_json::Value> = config
+        .providers
+        .iter()
+        .map(|(id, c)| {
+            (
+                id.clone(),
+                serde_json::json!({
+                    "baseUrl": c.base_url,
+                    "apiKey": c.api_key,
+                    "options": c.options,
+                    "retry": c.retry,
+                    "maxConcurrency": c.max_concurrency,
+                }),
+            )
+        })
+        .collect();
+    let mut json = serde_json::json!({ "providers": providers });
+    if let Some(c) = config.get("seedream") {
+        json["seedream"] = serde_json::json!({ "baseUrl": c.base_url, "apiKey": c.api_key });
+    }
+    if let Some(c) = config.get("banana_pro") {
+        json["bananaPro"] = serde_json::json!({ "baseUrl": c.base_url, "apiKey": c.api_key });
+    }
+    json
 }
 
 async fn api_save_config(
     axum::Json(body): axum::Json<ApiConfigBody>,
-) -> Result<axum::Json<bool>, String> {
-    use crate::commands::image_generator::{save_api_config, get_default_api_config, ApiConfig, ModelConfig};
+) -> AppResult<axum::Json<bool>> {
+    use crate::commands::image_generator::{current_api_config, get_default_api_config, save_api_config};
     
-    let default_config = get_default_api_config();
+    // Providers missing from the body keep their saved settings
+    let mut config = get_default_api_config();
+    if let Some(saved) = current_api_config()? {
+        config.providers.extend(saved.providers);
+    }
     
-    let config = ApiConfig {
-        seedream: ModelConfig {
-            base_url: body.seedream.as_ref().and_then(|c| c.base_url.clone()).unwrap_or(default_config.seedream.base_url),
-            api_key: body.seedream.as_ref().and_then(|c| c.api_key.clone()).unwrap_or(default_config.seedream.api_key),
-        },
-        banana_pro: ModelConfig {
-            base_url: body.banana_pro.as_ref().and_then(|c| c.base_url.clone()).unwrap_or(default_config.banana_pro.base_url),
-            api_key: body.banana_pro.as_ref().and_then(|c| c.api_key.clone()).unwrap_or(default_config.banana_pro.api_key),
-        },
-    };
-    let result = save_api_config(config).unwrap_or(false);
+    let mut bodies = body.providers;
+    if let Some(c) = body.seedream {
+        bodies.entry("seedream".to_string()).or_insert(c);
+    }
+    if let Some(c) = body.banana_pro {
+        bodies.entry("banana_pro".to_string()).or_insert(c);
+    }
+    
+    for (id, c) in bodies {
+        let Some(entry) = config.providers.get_mut(&id) else {
+            return Err(AppError::new(ErrorCode::UnsupportedModel, format!("不支持的模型: {}", id)));
+        };
+        if let Some(base_url) = c.base_url {
+            entry.base_url = base_url;
+        }
+        if let Some(api_key) = c.api_key {
+            entry.api_key = api_key;
+        }
+        if let Some(options) = c.options {
+            entry.options.extend(options);
+        }
+        if let Some(retry) = c.retry {
+            entry.retry = retry;
+        }
+        if c.max_concurrency.is_some() {
+            entry.max_concurrency = c.max_concurrency;
+        }
+    }
+    let result = save_api_config(config)?;
     Ok(axum::Json(result))
 }
 
-async fn api_get_default_config(
-) -> Result<axum::Json<serde_json::Value>, String> {
+async fn api_get_default_config() -> axum::Json<serde_json::Value> {
     use crate::commands::image_generator::get_default_api_config;
     let config = get_default_api_config();
-    let json = serde_json::json!({
-        "seedream": {
-            "baseUrl": config.seedream.base_url,
-            "apiKey": config.seedream.api_key,
-        },
-        "bananaPro": {
-            "baseUrl": config.banana_pro.base_url,
-            "apiKey": config.banana_pro.api_key,
-        }
-    });
-    Ok(axum::Json(json))
+    axum::Json(api_config_to_json(&config))
 }
 
-async fn api_load_config(
-) -> Result<axum::Json<serde_json::Value>, String> {
+async fn api_load_config() -> AppResult<axum::Json<serde_json::Value>> {
     use crate::commands::image_generator::load_api_config;
     
     match load_api_config() {
-        Ok(config) => {
-            let json = serde_json::json!({
-                "seedream": {
-                    "baseUrl": config.seedream.base_url,
-                    "apiKey": config.seedream.api_key,
-                },
-                "bananaPro": {
-                    "baseUrl": config.banana_pro.base_url,
-                    "apiKey": config.banana_pro.api_key,
-                }
-            });
-            Ok(axum::Json(json))
-        }
+        Ok(config) => Ok(axum::Json(api_config_to_json(&config))),
         Err(e) => {
             eprintln!("Failed to load config: {}", e);
             Err(e)
@@ -391,6 +686,10 @@ async fn api_load_config(
     }
 }
 
+async fn api_list_providers() -> axum::Json<Vec<ProviderInfo>> {
+    axum::Json(list_image_providers())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GenerationConfigBody {
     model: String,
@@ -406,7 +705,7 @@ pub struct GenerationConfigBody {
 
 async fn api_save_generation_config(
     axum::Json(body): axum::Json<GenerationConfigBody>,
-) -> axum::Json<bool> {
+) -> AppResult<axum::Json<bool>> {
     use crate::commands::image_generator::save_generation_config;
     let config = GenerationConfig {
         model: body.model,
@@ -419,36 +718,21 @@ async fn api_save_generation_config(
         response_format: body.response_format,
         watermark: body.watermark,
     };
-    let result = save_generation_config(config);
-    match result {
-        Ok(r) => axum::Json(r),
-        Err(e) => {
-            eprintln!("save_generation_config error: {}", e);
-            axum::Json(false)
-        }
-    }
+    save_generation_config(config).map(axum::Json).map_err(|e| {
+        eprintln!("save_generation_config error: {}", e);
+        e
+    })
 }
 
-async fn api_load_generation_config() -> Result<axum::Json<crate::commands::image_generator::GenerationConfig>, String> {
-    use crate::commands::image_generator::{load_generation_config, GenerationConfig};
-    let result = load_generation_config().unwrap_or(GenerationConfig {
-        model: "seedream".to_string(),
-        width: 1,
-        height: 1,
-        count: 1,
-        quality: "standard".to_string(),
-        size: Some("1024x1024".to_string()),
-        sequential_image_generation: Some("disabled".to_string()),
-        response_format: Some("url".to_string()),
-        watermark: Some(false),
-    });
-    Ok(axum::Json(result))
+async fn api_load_generation_config() -> axum::Json<GenerationConfig> {
+    use crate::commands::image_generator::{get_default_generation_config, load_generation_config};
+    let result = load_generation_config().unwrap_or_else(|_| get_default_generation_config());
+    axum::Json(result)
 }
 
-async fn api_get_default_generation_config() -> Result<axum::Json<crate::commands::image_generator::GenerationConfig>, String> {
+async fn api_get_default_generation_config() -> axum::Json<GenerationConfig> {
     use crate::commands::image_generator::get_default_generation_config;
-    let result = get_default_generation_config();
-    Ok(axum::Json(result))
+    axum::Json(get_default_generation_config())
 }
 
 #[derive(Debug, Deserialize)]
@@ -462,16 +746,244 @@ pub struct TestConnectionBody {
 
 async fn api_test_connection(
     axum::Json(body): axum::Json<TestConnectionBody>,
-) -> Result<axum::Json<bool>, String> {
+) -> AppResult<axum::Json<bool>> {
     use crate::commands::image_generator::test_api_connection;
     let result = test_api_connection(
         body.model,
         body.base_url,
         body.api_key,
-    ).await.unwrap_or(false);
+    ).await?;
     Ok(axum::Json(result))
 }
 
+/// Either a local `filePath` or the file's `content`; the format comes from the extension,
+/// `format`, or the content itself.
+#[derive(Debug, Deserialize)]
+pub struct BatchBody {
+    #[serde(alias = "filePath", alias = "file_path")]
+    file_path: Option<String>,
+    content: Option<String>,
+    format: Option<BatchFormat>,
+    #[serde(alias = "outputDir", alias = "output_dir")]
+    output_dir: Option<String>,
+}
+
+async fn api_run_batch(
+    axum::Json(body): axum::Json<BatchBody>,
+) -> AppResult<axum::Json<BatchManifest>> {
+    use crate::commands::batch::{run_batch_content, run_batch_file};
+    if let Some(dir) = &body.output_dir {
+        data_dir_path(dir, "outputDir")?;
+    }
+    let manifest = match (body.file_path, body.content) {
+        (Some(file_path), _) => {
+            data_dir_path(&file_path, "filePath")?;
+            run_batch_file(&file_path, body.output_dir).await?
+        }
+        (None, Some(content)) => run_batch_content(&content, body.format, body.output_dir).await?,
+        (None, None) => return Err(AppError::invalid_input("需要提供filePath或content")),
+    };
+    Ok(axum::Json(manifest))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectBody {
+    #[serde(alias = "projectId", alias = "project_id")]
+    project_id: Option<String>,
+    name: Option<String>,
+    settings: Option<ProjectSettings>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageBody {
+    #[serde(alias = "projectId", alias = "project_id")]
+    project_id: String,
+    #[serde(alias = "pageId", alias = "page_id")]
+    page_id: Option<String>,
+    #[serde(alias = "pageIds", alias = "page_ids", default)]
+    page_ids: Vec<String>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    prompt: String,
+    index: Option<usize>,
+    #[serde(default)]
+    update: PageUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectBindBody {
+    #[serde(alias = "projectId", alias = "project_id")]
+    project_id: String,
+    #[serde(alias = "characterName", alias = "character_name")]
+    character_name: String,
+    #[serde(alias = "referenceImagePath", alias = "reference_image_path")]
+    reference_image_path: Option<String>,
+    #[serde(alias = "imageType", alias = "image_type")]
+    image_type: Option<String>,
+}
+
+fn required<T>(value: Option<T>, field: &str) -> AppResult<T> {
+    value.ok_or_else(|| AppError::invalid_input(format!("缺少参数: {}", field)))
+}
+
+async fn api_list_projects() -> AppResult<axum::Json<Vec<ProjectSummary>>> {
+    Ok(axum::Json(project::list_projects()?))
+}
+
+async fn api_create_project(
+    axum::Json(body): axum::Json<ProjectBody>,
+) -> AppResult<axum::Json<Project>> {
+    Ok(axum::Json(project::create_project(required(body.name, "name")?)?))
+}
+
+async fn api_get_project(
+    axum::Json(body): axum::Json<ProjectBody>,
+) -> AppResult<axum::Json<Project>> {
+    Ok(axum::Json(project::get_project(required(body.project_id, "projectId")?)?))
+}
+
+async fn api_rename_project(
+    axum::Json(body): axum::Json<ProjectBody>,
+) -> AppResult<axum::Json<Project>> {
+    let project_id = required(body.project_id, "projectId")?;
+    Ok(axum::Json(project::rename_project(project_id, required(body.name, "name")?)?))
+}
+
+async fn api_update_project_settings(
+    axum::Json(body): axum::Json<ProjectBody>,
+) -> AppResult<axum::Json<Project>> {
+    let project_id = required(body.project_id, "projectId")?;
+    Ok(axum::Json(project::update_project_settings(
+        project_id,
+        required(body.settings, "settings")?,
+    )?))
+}
+
+async fn api_delete_project(
+    axum::Json(body): axum::Json<ProjectBody>,
+) -> AppResult<axum::Json<bool>> {
+    Ok(axum::Json(project::delete_project(required(body.project_id, "projectId")?)?))
+}
+
+async fn api_add_page(axum::Json(body): axum::Json<PageBody>) -> AppResult<axum::Json<Project>> {
+    Ok(axum::Json(project::add_page(body.project_id, body.text, body.prompt, body.index)?))
+}
+
+async fn api_update_page(axum::Json(body): axum::Json<PageBody>) -> AppResult<axum::Json<Project>> {
+    let page_id = required(body.page_id, "pageId")?;
+    Ok(axum::Json(project::update_page(body.project_id, page_id, body.update)?))
+}
+
+async fn api_delete_page(axum::Json(body): axum::Json<PageBody>) -> AppResult<axum::Json<Project>> {
+    let page_id = required(body.page_id, "pageId")?;
+    Ok(axum::Json(project::delete_page(body.project_id, page_id)?))
+}
+
+async fn api_reorder_pages(axum::Json(body): axum::Json<PageBody>) -> AppResult<axum::Json<Project>> {
+    Ok(axum::Json(project::reorder_pages(body.project_id, body.page_ids)?))
+}
+
+async fn api_generate_page(
+    axum::Json(body): axum::Json<PageBody>,
+) -> AppResult<axum::Json<PageGenerationResult>> {
+    let page_id = required(body.page_id, "pageId")?;
+    Ok(axum::Json(project::generate_page(body.project_id, page_id).await?))
+}
+
+async fn api_bind_project_character(
+    axum::Json(body): axum::Json<ProjectBindBody>,
+) -> AppResult<axum::Json<Project>> {
+    let path = required(body.reference_image_path, "referenceImagePath")?;
+    Ok(axum::Json(project::bind_project_character(
+        body.project_id,
+        body.character_name,
+        path,
+        body.image_type,
+    )?))
+}
+
+async fn api_unbind_project_character(
+    axum::Json(body): axum::Json<ProjectBindBody>,
+) -> AppResult<axum::Json<Project>> {
+    Ok(axum::Json(project::unbind_project_character(body.project_id, body.character_name)?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PdfExportBody {
+    #[serde(alias = "projectId", alias = "project_id")]
+    project_id: String,
+    #[serde(alias = "outputPath", alias = "output_path")]
+    output_path: Option<String>,
+    options: Option<PdfExportOptions>,
+}
+
+async fn api_export_project_pdf(
+    axum::Json(body): axum::Json<PdfExportBody>,
+) -> AppResult<axum::Json<PdfExportReport>> {
+    use crate::commands::pdf_export::export_project_pdf;
+    if let Some(path) = &body.output_path {
+        data_dir_path(path, "outputPath")?;
+    }
+    let report = export_project_pdf(body.project_id, body.output_path, body.options).await?;
+    Ok(axum::Json(report))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EpubExportBody {
+    #[serde(alias = "projectId", alias = "project_id")]
+    project_id: String,
+    #[serde(alias = "outputPath", alias = "output_path")]
+    output_path: Option<String>,
+    options: Option<EpubExportOptions>,
+}
+
+async fn api_export_project_epub(
+    axum::Json(body): axum::Json<EpubExportBody>,
+) -> AppResult<axum::Json<EpubExportReport>> {
+    use crate::commands::epub_export::export_project_epub;
+    if let Some(path) = &body.output_path {
+        data_dir_path(path, "outputPath")?;
+    }
+    let report = export_project_epub(body.project_id, body.output_path, body.options).await?;
+    Ok(axum::Json(report))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TextOverlayBody {
+    image: String,
+    text: String,
+    options: Option<TextOverlayOptions>,
+    #[serde(alias = "outputPath", alias = "output_path")]
+    output_path: Option<String>,
+}
+
+async fn api_overlay_text(
+    axum::Json(body): axum::Json<TextOverlayBody>,
+) -> AppResult<axum::Json<TextOverlayResult>> {
+    use crate::commands::text_overlay::overlay_text;
+    if !body.image.starts_with("data:") && !body.image.starts_with("http") {
+        data_dir_path(&body.image, "image")?;
+    }
+    if let Some(path) = &body.output_path {
+        data_dir_path(path, "outputPath")?;
+    }
+    let result = overlay_text(body.image, body.text, body.options, body.output_path).await?;
+    Ok(axum::Json(result))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportMetadataBody {
+    image: String,
+}
+
+async fn api_import_image_metadata(
+    axum::Json(body): axum::Json<ImportMetadataBody>,
+) -> AppResult<axum::Json<ImportedImage>> {
+    use crate::commands::image_metadata::import_image_metadata;
+    Ok(axum::Json(import_image_metadata(body.image).await?))
+}
+
 pub fn create_api_router() -> Router {
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -486,10 +998,43 @@ pub fn create_api_router() -> Router {
         .route("/api/bind", post(api_bind_character_reference))
         .route("/api/unbind", post(api_unbind_character))
         .route("/api/generate", post(api_generate_image))
+        .route("/api/generate/stream", post(api_generate_image_stream))
+        .route("/api/generate/cancel", post(api_cancel_generation))
+        .route("/api/batch", post(api_run_batch))
+        .route("/api/history", post(api_list_history))
+        .route("/api/history/get", post(api_get_history_entry))
+        .route("/api/history/delete", post(api_delete_history_entry))
+        .route("/api/history/rerun", post(api_rerun_generation))
+        .route("/api/gallery", post(api_list_gallery))
+        .route("/api/gallery/tags", get(api_list_gallery_tags))
+        .route("/api/gallery/favorite", post(api_set_gallery_favorite))
+        .route("/api/gallery/rating", post(api_set_gallery_rating))
+        .route("/api/gallery/tags/set", post(api_set_gallery_tags))
+        .route("/api/gallery/trash", post(api_trash_gallery_items))
+        .route("/api/gallery/restore", post(api_restore_gallery_items))
+        .route("/api/gallery/trash/empty", post(api_empty_gallery_trash))
+        .route("/api/projects", get(api_list_projects))
+        .route("/api/projects/create", post(api_create_project))
+        .route("/api/projects/get", post(api_get_project))
+        .route("/api/projects/rename", post(api_rename_project))
+        .route("/api/projects/settings", post(api_update_project_settings))
+        .route("/api/projects/delete", post(api_delete_project))
+        .route("/api/projects/pages/add", post(api_add_page))
+        .route("/api/projects/pages/update", post(api_update_page))
+        .route("/api/projects/pages/delete", post(api_delete_page))
+        .route("/api/projects/pages/reorder", post(api_reorder_pages))
+        .route("/api/projects/pages/generate", post(api_generate_page))
+        .route("/api/projects/bind", post(api_bind_project_character))
+        .route("/api/projects/unbind", post(api_unbind_project_character))
+        .route("/api/projects/export/pdf", post(api_export_project_pdf))
+        .route("/api/projects/export/epub", post(api_export_project_epub))
+        .route("/api/overlay-text", post(api_overlay_text))
+        .route("/api/image-metadata/import", post(api_import_image_metadata))
         .route("/api/image", get(api_get_image))
         .route("/api/config/save", post(api_save_config))
         .route("/api/config/load", get(api_load_config))
         .route("/api/config/default", get(api_get_default_config))
+        .route("/api/providers", get(api_list_providers))
         .route("/api/test-connection", post(api_test_connection))
         .route("/api/generation-config/save", post(api_save_generation_config))
         .route("/api/generation-config/load", get(api_load_generation_config))
@@ -0,0 +1,419 @@
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::character_binding::get_bindings_for_prompt;
+use super::generation_queue::JobPriority;
+use super::image_files::{fetch_image_bytes, image_extension};
+use super::image_generator::{
+    self, generate_image_and_wait, CharacterBindingInfo, GenerationConfig, ImageGenerationParams,
+};
+use super::prompt_parser::parse_prompt_internal;
+use super::providers::capabilities::parse_size;
+use super::providers::PROVIDER_REGISTRY;
+use crate::error::{AppError, AppResult, ErrorCode};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchFormat {
+    Csv,
+    Jsonl,
+}
+
+impl BatchFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "csv" => Some(BatchFormat::Csv),
+            "jsonl" | "ndjson" => Some(BatchFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    /// JSONL rows are objects, so a leading `{` is enough to tell the two apart.
+    fn sniff(content: &str) -> Self {
+        if content.trim_start().starts_with('{') {
+            BatchFormat::Jsonl
+        } else {
+            BatchFormat::Csv
+        }
+    }
+}
+
+/// One prompt of a batch file. Missing fields fall back to the saved generation config.
+///
+/// In CSV the bindings column holds `name=path` entries separated by `;`; a bare `name`
+/// uses the reference image bound to that character.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchRow {
+    pub prompt: String,
+    pub model: Option<String>,
+    pub size: Option<String>,
+    pub count: Option<u32>,
+    #[serde(default, alias = "characterBindings")]
+    pub character_bindings: Vec<CharacterBindingInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    /// 1-based data row in the source file (the CSV header does not count).
+    pub row: usize,
+    pub prompt: String,
+    pub model: Option<String>,
+    #[serde(alias = "taskId")]
+    pub task_id: Option<String>,
+    pub success: bool,
+    /// Saved images, relative to the output folder.
+    pub files: Vec<String>,
+    pub error: Option<String>,
+    #[serde(alias = "errorCode")]
+    pub error_code: Option<ErrorCode>,
+}
+
+/// Written as `manifest.json` next to the images and returned to the caller.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchManifest {
+    pub source: String,
+    #[serde(alias = "outputDir")]
+    pub output_dir: String,
+    #[serde(alias = "startedAt")]
+    pub started_at: String,
+    #[serde(alias = "finishedAt")]
+    pub finished_at: String,
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub items: Vec<BatchItem>,
+}
+
+/// Parses a batch file into rows. Rows that cannot be read are returned as errors in place,
+/// so one typo does not sink the whole book.
+pub fn parse_batch(content: &str, format: BatchFormat) -> AppResult<Vec<AppResult<BatchRow>>> {
+    // Spreadsheet exports often start with a UTF-8 BOM
+    let content = content.trim_start_matches('\u{feff}');
+    match format {
+        BatchFormat::Jsonl => Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str::<BatchRow>(line)
+                    .map_err(|e| AppError::invalid_input(format!("无法解析该行: {}", e)))
+            })
+            .collect()),
+        BatchFormat::Csv => parse_csv(content),
+    }
+}
+
+fn parse_csv(content: &str) -> AppResult<Vec<AppResult<BatchRow>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| AppError::invalid_input(format!("CSV表头无效: {}", e)))?
+        .iter()
+        .map(|h| h.to_ascii_lowercase())
+        .collect();
+    if !headers.iter().any(|h| h == "prompt") {
+        return Err(AppError::invalid_input("CSV缺少prompt列"));
+    }
+
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| AppError::invalid_input(format!("无法解析该行: {}", e)))?;
+            let mut row = serde_json::Map::new();
+            for (header, value) in headers.iter().zip(record.iter()) {
+                if value.is_empty() {
+                    continue;
+                }
+                let value = match header.as_str() {
+                    "count" => value
+                        .parse::<u32>()
+                        .map(serde_json::Value::from)
+                        .map_err(|_| AppError::invalid_input(format!("count无效: {}", value)))?,
+                    "character_bindings" | "characterbindings" | "characters" => {
+                        serde_json::to_value(parse_binding_list(value))?
+                    }
+                    _ => serde_json::Value::from(value),
+                };
+                let key = match header.as_str() {
+                    "characterbindings" | "characters" => "character_bindings",
+                    other => other,
+                };
+                row.insert(key.to_string(), value);
+            }
+            serde_json::from_value::<BatchRow>(serde_json::Value::Object(row))
+                .map_err(|e| AppError::invalid_input(format!("无法解析该行: {}", e)))
+        })
+        .collect())
+}
+
+/// `小明=/path/a.png; 小红` → one binding per entry.
+fn parse_binding_list(value: &str) -> Vec<CharacterBindingInfo> {
+    value
+        .split([';', '；'])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, path) = match entry.split_once('=') {
+                Some((name, path)) => (name.trim(), Some(path.trim().to_string())),
+                None => (entry, None),
+            };
+            CharacterBindingInfo {
+                character_name: name.trim_start_matches('@').to_string(),
+                reference_image_path: path.filter(|p| !p.is_empty()),
+                image_type: "人物".to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Builds generation params for a row. Characters mentioned as `@name` in the prompt, and
+/// explicit bindings without a path, pick up their saved reference images.
+pub fn row_to_params(row: &BatchRow, defaults: &GenerationConfig) -> AppResult<ImageGenerationParams> {
+    let parsed = parse_prompt_internal(&row.prompt)?;
+
+    let mut bindings = row.character_bindings.clone();
+    for character in &parsed.characters {
+        if !bindings.iter().any(|b| b.character_name == character.name) {
+            bindings.push(CharacterBindingInfo {
+                character_name: character.name.clone(),
+                reference_image_path: None,
+                image_type: "人物".to_string(),
+            });
+        }
+    }
+    let missing: Vec<String> = bindings
+        .iter()
+        .filter(|b| b.reference_image_path.is_none())
+        .map(|b| b.character_name.clone())
+        .collect();
+    for saved in get_bindings_for_prompt(missing) {
+        if let Some(binding) = bindings.iter_mut().find(|b| b.character_name == saved.character_name) {
+            binding.reference_image_path = saved.reference_image_path;
+            binding.image_type = saved.image_type;
+        }
+    }
+    bindings.retain(|b| b.reference_image_path.is_some());
+
+    let mut params = defaults.to_params(row.prompt.clone(), bindings, JobPriority::Batch);
+    if let Some(model) = row.model.as_ref().filter(|m| **m != params.model) {
+        // The default size belongs to the default model and may not exist on this one.
+        params.model = model.clone();
+        params.size = None;
+    }
+    if let Some(size) = &row.size {
+        let named = PROVIDER_REGISTRY
+            .get(&params.model)
+            .is_some_and(|p| p.capabilities().sizes.iter().any(|s| s.eq_ignore_ascii_case(size)));
+        match parse_size(size) {
+            Some((width, height)) => (params.width, params.height) = (width, height),
+            None if named => {}
+            None => return Err(AppError::invalid_input(format!("尺寸无效: {}", size))),
+        }
+        params.size = Some(size.clone());
+    }
+    if let Some(count) = row.count {
+        params.count = count;
+    }
+    Ok(params)
+}
+
+/// Generates every row through the queue at batch priority and saves the images as
+/// `<row>_<n>.<ext>` in `output_dir`, along with `manifest.json`.
+pub async fn run_batch(
+    content: &str,
+    format: BatchFormat,
+    output_dir: &Path,
+    source: &str,
+) -> AppResult<BatchManifest> {
+    let started_at = chrono::Local::now().to_rfc3339();
+    let rows = parse_batch(content, format)?;
+    if rows.is_empty() {
+        return Err(AppError::invalid_input("批量文件中没有任何提示词"));
+    }
+    fs::create_dir_all(output_dir)?;
+
+    let defaults = image_generator::current_generation_config();
+    let items = join_all(
+        rows.into_iter()
+            .enumerate()
+            .map(|(index, row)| run_row(index + 1, row, &defaults, output_dir)),
+    )
+    .await;
+
+    let succeeded = items.iter().filter(|i| i.success).count();
+    let manifest = BatchManifest {
+        source: source.to_string(),
+        output_dir: output_dir.to_string_lossy().to_string(),
+        started_at,
+        finished_at: chrono::Local::now().to_rfc3339(),
+        total: items.len(),
+        succeeded,
+        failed: items.len() - succeeded,
+        items,
+    };
+    fs::write(
+        output_dir.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    Ok(manifest)
+}
+
+async fn run_row(
+    index: usize,
+    row: AppResult<BatchRow>,
+    defaults: &GenerationConfig,
+    output_dir: &Path,
+) -> BatchItem {
+    let mut item = BatchItem {
+        row: index,
+        prompt: String::new(),
+        model: None,
+        task_id: None,
+        success: false,
+        files: vec![],
+        error: None,
+        error_code: None,
+    };
+    let fail = |mut item: BatchItem, e: AppError| {
+        item.error = Some(e.message);
+        item.error_code = Some(e.code);
+        item
+    };
+
+    let row = match row {
+        Ok(row) => row,
+        Err(e) => return fail(item, e),
+    };
+    item.prompt = row.prompt.clone();
+
+    let params = match row_to_params(&row, defaults) {
+        Ok(params) => params,
+        Err(e) => return fail(item, e),
+    };
+    item.model = Some(params.model.clone());
+
+    let result = match generate_image_and_wait(params).await {
+        Ok(result) => result,
+        Err(e) => return fail(item, e),
+    };
+    item.task_id = Some(result.task_id.clone());
+    if !result.success {
+        item.error = result.error;
+        item.error_code = result.error_code;
+        return item;
+    }
+
+    for (n, url) in result.image_sources().into_iter().enumerate() {
+        let saved = match fetch_image_bytes(url).await {
+            Ok(bytes) => {
+                let name = format!("{:03}_{}.{}", index, n + 1, image_extension(&bytes));
+                fs::write(output_dir.join(&name), bytes).map(|_| name).map_err(AppError::from)
+            }
+            Err(e) => Err(e),
+        };
+        match saved {
+            Ok(name) => item.files.push(name),
+            Err(e) => return fail(item, e),
+        }
+    }
+    item.success = true;
+    item
+}
+
+fn default_output_dir() -> PathBuf {
+    image_generator::get_app_data_dir()
+        .join("batches")
+        .join(chrono::Local::now().format("%Y%m%d_%H%M%S").to_string())
+}
+
+/// Runs a batch file; the output folder defaults to `batches/<timestamp>` in the app data dir.
+pub async fn run_batch_file(file_path: &str, output_dir: Option<String>) -> AppResult<BatchManifest> {
+    let path = Path::new(file_path);
+    let content = fs::read_to_string(path)?;
+    let format = BatchFormat::from_path(path).unwrap_or_else(|| BatchFormat::sniff(&content));
+    let output_dir = output_dir.map(PathBuf::from).unwrap_or_else(default_output_dir);
+    run_batch(&content, format, &output_dir, file_path).await
+}
+
+/// Like `run_batch_file`, for content uploaded over HTTP instead of a local path.
+pub async fn run_batch_content(
+    content: &str,
+    format: Option<BatchFormat>,
+    output_dir: Option<String>,
+) -> AppResult<BatchManifest> {
+    let format = format.unwrap_or_else(|| BatchFormat::sniff(content));
+    let output_dir = output_dir.map(PathBuf::from).unwrap_or_else(default_output_dir);
+    run_batch(content, format, &output_dir, "upload").await
+}
+
+#[tauri::command]
+pub async fn run_batch_generation(
+    file_path: String,
+    output_dir: Option<String>,
+) -> AppResult<BatchManifest> {
+    run_batch_file(&file_path, output_dir).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_csv_and_jsonl_rows() {
+        let csv = "\u{feff}prompt,model,size,count,characters\n\
+                   @小明 在森林里,mock,640x480,2,小明=/tmp/a.png;小红\n\
+                   清晨的湖边,,,abc,\n";
+        let rows = parse_batch(csv, BatchFormat::sniff(csv)).unwrap();
+        assert_eq!(rows.len(), 2);
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.count, Some(2));
+        assert_eq!(first.character_bindings.len(), 2);
+        assert_eq!(first.character_bindings[0].reference_image_path.as_deref(), Some("/tmp/a.png"));
+        assert_eq!(rows[1].as_ref().unwrap_err().code, ErrorCode::InvalidInput);
+
+        let jsonl = "{\"prompt\": \"小狐狸\", \"size\": \"512x512\"}\n\n{\"model\": \"mock\"}\n";
+        let rows = parse_batch(jsonl, BatchFormat::sniff(jsonl)).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].is_ok() && rows[1].is_err());
+
+        let params = row_to_params(rows[0].as_ref().unwrap(), &image_generator::get_default_generation_config()).unwrap();
+        assert_eq!((params.width, params.height), (512, 512));
+        assert_eq!(params.priority, JobPriority::Batch);
+
+        let defaults = image_generator::get_default_generation_config();
+        let jsonl = "{\"prompt\": \"小狐狸\", \"model\": \"banana_pro\"}\n\
+                     {\"prompt\": \"小狐狸\", \"model\": \"banana_pro\", \"size\": \"2K\"}\n\
+                     {\"prompt\": \"小狐狸\", \"model\": \"mock\", \"size\": \"2K\"}\n";
+        let rows = parse_batch(jsonl, BatchFormat::Jsonl).unwrap();
+        let params = row_to_params(rows[0].as_ref().unwrap(), &defaults).unwrap();
+        assert_eq!(params.size, None);
+        let params = row_to_params(rows[1].as_ref().unwrap(), &defaults).unwrap();
+        assert_eq!(params.size.as_deref(), Some("2K"));
+        assert!(row_to_params(rows[2].as_ref().unwrap(), &defaults).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch_writes_images_and_manifest() {
+        let out_dir = std::env::temp_dir().join(format!("huiben_batch_{}", std::process::id()));
+        let jsonl = "{\"prompt\": \"小狐狸\", \"model\": \"mock\", \"size\": \"64x64\", \"count\": 2}\n\
+                     {\"prompt\": \"  \", \"model\": \"mock\"}\n";
+
+        let manifest = run_batch(jsonl, BatchFormat::Jsonl, &out_dir, "test").await.unwrap();
+        assert_eq!((manifest.total, manifest.succeeded, manifest.failed), (2, 1, 1));
+        assert_eq!(manifest.items[0].files, vec!["001_1.png", "001_2.png"]);
+        assert!(out_dir.join("001_2.png").exists());
+        assert!(manifest.items[1].error.is_some());
+
+        let saved: BatchManifest =
+            serde_json::from_str(&fs::read_to_string(out_dir.join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(saved.items.len(), 2);
+        fs::remove_dir_all(&out_dir).ok();
+    }
+}
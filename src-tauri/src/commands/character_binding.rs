@@ -5,6 +5,9 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+use super::thumbnails;
+use crate::error::{AppError, AppResult, ErrorCode};
+
 pub static CHARACTER_BINDINGS: Lazy<std::sync::Mutex<HashMap<String, CharacterBinding>>> =
     Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
 
@@ -61,9 +64,9 @@ pub fn save_reference_image(
     character_name: String,
     image_data: String,
     image_type: String,
-) -> Result<CharacterBinding, String> {
+) -> AppResult<CharacterBinding> {
     if character_name.is_empty() {
-        return Err("角色名称不能为空".to_string());
+        return Err(AppError::invalid_input("角色名称不能为空"));
     }
 
     let storage_dir = get_storage_dir();
@@ -73,18 +76,19 @@ pub fn save_reference_image(
     let image_bytes = if image_data.contains(',') {
         let parts: Vec<&str> = image_data.split(',').collect();
         if parts.len() != 2 {
-            return Err("无效的图片数据格式".to_string());
+            return Err(AppError::invalid_input("无效的图片数据格式"));
         }
         general_purpose::STANDARD
             .decode(parts[1])
-            .map_err(|e| format!("图片解码失败: {}", e))?
+            .map_err(|e| AppError::invalid_input(format!("图片解码失败: {}", e)))?
     } else {
         general_purpose::STANDARD
             .decode(&image_data)
-            .map_err(|e| format!("图片解码失败: {}", e))?
+            .map_err(|e| AppError::invalid_input(format!("图片解码失败: {}", e)))?
     };
 
-    fs::write(&file_path, &image_bytes).map_err(|e| format!("保存图片失败: {}", e))?;
+    fs::write(&file_path, &image_bytes)
+        .map_err(|e| AppError::new(ErrorCode::Io, format!("保存图片失败: {}", e)))?;
 
     let binding = CharacterBinding {
         character_name: character_name.clone(),
@@ -95,8 +99,8 @@ pub fn save_reference_image(
         tags: Vec::new(),
     };
 
-    let mut bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
-    bindings.insert(character_name, binding.clone());
+    let mut bindings = CHARACTER_BINDINGS.lock()?;
+    replace_binding(&mut bindings, binding.clone());
 
     save_bindings_to_file(&bindings)?;
 
@@ -108,14 +112,14 @@ pub fn bind_character_reference(
     character_name: String,
     reference_image_path: String,
     image_type: String,
-) -> Result<CharacterBinding, String> {
+) -> AppResult<CharacterBinding> {
     if character_name.is_empty() {
-        return Err("角色名称不能为空".to_string());
+        return Err(AppError::invalid_input("角色名称不能为空"));
     }
 
     let path = PathBuf::from(&reference_image_path);
     if !path.exists() {
-        return Err("参考图文件不存在".to_string());
+        return Err(AppError::not_found("参考图文件不存在"));
     }
 
     let binding = CharacterBinding {
@@ -127,8 +131,8 @@ pub fn bind_character_reference(
         tags: Vec::new(),
     };
 
-    let mut bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
-    bindings.insert(character_name, binding.clone());
+    let mut bindings = CHARACTER_BINDINGS.lock()?;
+    replace_binding(&mut bindings, binding.clone());
 
     save_bindings_to_file(&bindings)?;
 
@@ -136,12 +140,14 @@ pub fn bind_character_reference(
 }
 
 #[tauri::command]
-pub fn unbind_character(character_name: String) -> Result<bool, String> {
-    let mut bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
+pub fn unbind_character(character_name: String) -> AppResult<bool> {
+    let mut bindings = CHARACTER_BINDINGS.lock()?;
 
     if let Some(binding) = bindings.get_mut(&character_name) {
         binding.bound = false;
-        binding.reference_image_path = None;
+        if let Some(path) = binding.reference_image_path.take() {
+            thumbnails::invalidate(&path);
+        }
         save_bindings_to_file(&bindings)?;
         return Ok(true);
     }
@@ -171,12 +177,13 @@ pub fn get_bindings_for_prompt(characters: Vec<String>) -> Vec<CharacterBinding>
 }
 
 #[tauri::command]
-pub fn delete_reference_image(character_name: String) -> Result<bool, String> {
-    let mut bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
+pub fn delete_reference_image(character_name: String) -> AppResult<bool> {
+    let mut bindings = CHARACTER_BINDINGS.lock()?;
 
     if let Some(binding) = bindings.get(&character_name) {
         if let Some(ref path) = binding.reference_image_path {
             let _ = fs::remove_file(path);
+            thumbnails::invalidate(path);
         }
     }
 
@@ -186,6 +193,14 @@ pub fn delete_reference_image(character_name: String) -> Result<bool, String> {
     Ok(true)
 }
 
+/// Stores `binding`, dropping the cached thumbnails of the reference image it replaces.
+fn replace_binding(bindings: &mut HashMap<String, CharacterBinding>, binding: CharacterBinding) {
+    let previous = bindings.insert(binding.character_name.clone(), binding);
+    if let Some(old_path) = previous.and_then(|b| b.reference_image_path) {
+        thumbnails::invalidate(&old_path);
+    }
+}
+
 fn chrono_timestamp() -> String {
     use std::time::{SystemTime, UNIX_EPOCH};
     let duration = SystemTime::now()
@@ -202,7 +217,7 @@ fn chrono_now() -> String {
     format!("{}", duration.as_secs())
 }
 
-fn save_bindings_to_file(bindings: &HashMap<String, CharacterBinding>) -> Result<(), String> {
+fn save_bindings_to_file(bindings: &HashMap<String, CharacterBinding>) -> AppResult<()> {
     let app_data = std::env::var("APPDATA")
         .or_else(|_| std::env::var("HOME"))
         .unwrap_or_else(|_| ".".to_string());
@@ -212,17 +227,17 @@ fn save_bindings_to_file(bindings: &HashMap<String, CharacterBinding>) -> Result
         .join("config");
 
     if !config_dir.exists() {
-        fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&config_dir)?;
     }
 
     let config_path = config_dir.join("character_bindings.json");
-    let json = serde_json::to_string_pretty(bindings).map_err(|e| e.to_string())?;
-    fs::write(config_path, json).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(bindings)?;
+    fs::write(config_path, json)?;
 
     Ok(())
 }
 
-pub fn load_bindings_from_file() -> Result<(), String> {
+pub fn load_bindings_from_file() -> AppResult<()> {
     let app_data = std::env::var("APPDATA")
         .or_else(|_| std::env::var("HOME"))
         .unwrap_or_else(|_| ".".to_string());
@@ -236,12 +251,11 @@ pub fn load_bindings_from_file() -> Result<(), String> {
         return Ok(());
     }
 
-    let content = fs::read_to_string(&config_path).map_err(|e| e.to_string())?;
+    let content = fs::read_to_string(&config_path)?;
 
-    let loaded: HashMap<String, CharacterBinding> =
-        serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    let loaded: HashMap<String, CharacterBinding> = serde_json::from_str(&content)?;
 
-    let mut bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
+    let mut bindings = CHARACTER_BINDINGS.lock()?;
     *bindings = loaded;
 
     load_tags_from_file();
@@ -302,10 +316,10 @@ pub fn search_reference_images(keyword: String) -> Vec<CharacterBinding> {
 }
 
 #[tauri::command]
-pub fn add_tag_to_reference(character_name: String, tag: String) -> Result<bool, String> {
+pub fn add_tag_to_reference(character_name: String, tag: String) -> AppResult<bool> {
     let tags_to_save;
     {
-        let mut tags = REFERENCE_TAGS.lock().map_err(|e| e.to_string())?;
+        let mut tags = REFERENCE_TAGS.lock()?;
 
         let entry = tags.entry(character_name.clone()).or_insert_with(Vec::new);
         if !entry.contains(&tag) {
@@ -317,7 +331,7 @@ pub fn add_tag_to_reference(character_name: String, tag: String) -> Result<bool,
 
     save_tags_to_file(&tags_to_save)?;
 
-    let mut bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
+    let mut bindings = CHARACTER_BINDINGS.lock()?;
     if let Some(binding) = bindings.get_mut(&character_name) {
         binding.tags = tags_to_save
             .get(&character_name)
@@ -329,8 +343,8 @@ pub fn add_tag_to_reference(character_name: String, tag: String) -> Result<bool,
 }
 
 #[tauri::command]
-pub fn remove_tag_from_reference(character_name: String, tag: String) -> Result<bool, String> {
-    let mut tags = REFERENCE_TAGS.lock().map_err(|e| e.to_string())?;
+pub fn remove_tag_from_reference(character_name: String, tag: String) -> AppResult<bool> {
+    let mut tags = REFERENCE_TAGS.lock()?;
 
     if let Some(entry) = tags.get_mut(&character_name) {
         entry.retain(|t| t != &tag);
@@ -338,7 +352,7 @@ pub fn remove_tag_from_reference(character_name: String, tag: String) -> Result<
 
     save_tags_to_file(&tags)?;
 
-    let mut bindings = CHARACTER_BINDINGS.lock().map_err(|e| e.to_string())?;
+    let mut bindings = CHARACTER_BINDINGS.lock()?;
     if let Some(binding) = bindings.get_mut(&character_name) {
         if let Some(entry) = tags.get(&character_name) {
             binding.tags = entry.clone();
@@ -389,7 +403,7 @@ pub fn load_tags_from_file() {
     }
 }
 
-fn save_tags_to_file(tags: &HashMap<String, Vec<String>>) -> Result<(), String> {
+fn save_tags_to_file(tags: &HashMap<String, Vec<String>>) -> AppResult<()> {
     let app_data = std::env::var("APPDATA")
         .or_else(|_| std::env::var("HOME"))
         .unwrap_or_else(|_| ".".to_string());
@@ -399,12 +413,12 @@ fn save_tags_to_file(tags: &HashMap<String, Vec<String>>) -> Result<(), String>
         .join("config");
 
     if !config_dir.exists() {
-        fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&config_dir)?;
     }
 
     let config_path = config_dir.join("reference_tags.json");
-    let json = serde_json::to_string_pretty(tags).map_err(|e| e.to_string())?;
-    fs::write(config_path, json).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(tags)?;
+    fs::write(config_path, json)?;
 
     Ok(())
 }
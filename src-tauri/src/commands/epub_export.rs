@@ -0,0 +1,421 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::pdf_export::{cover_crop, load_page_image, ExportWarning};
+use super::project::{self, Project};
+use crate::error::{AppError, AppResult};
+
+const JPEG_QUALITY: u8 = 88;
+
+/// Fixed-layout viewport of every page, in CSS px.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EpubExportOptions {
+    pub width: u32,
+    /// Taken from the first page image's aspect ratio when empty.
+    pub height: Option<u32>,
+    /// Story text size in px.
+    #[serde(alias = "fontSize")]
+    pub font_size: u32,
+}
+
+impl Default for EpubExportOptions {
+    fn default() -> Self {
+        EpubExportOptions {
+            width: 1200,
+            height: None,
+            font_size: 36,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpubExportReport {
+    pub path: String,
+    pub pages: usize,
+    pub warnings: Vec<ExportWarning>,
+}
+
+fn epub_error(e: impl std::fmt::Display) -> AppError {
+    AppError::internal(format!("EPUB生成失败: {}", e))
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escapes story text and keeps its explicit line breaks.
+fn text_to_xhtml(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(xml_escape)
+        .collect::<Vec<_>>()
+        .join("<br/>")
+}
+
+/// Crops the image to the viewport and scales it to at most twice the viewport, which is
+/// plenty for high-density readers.
+fn page_jpeg(image: &DynamicImage, width: u32, height: u32) -> AppResult<Vec<u8>> {
+    let (x, y, w, h) = cover_crop(image.width(), image.height(), width as f32, height as f32);
+    let mut cropped = image.crop_imm(x, y, w, h);
+    if w > width * 2 {
+        cropped = cropped.resize_exact(width * 2, height * 2, FilterType::Lanczos3);
+    }
+    let pixels = cropped.to_rgb8();
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+        .encode(pixels.as_raw(), pixels.width(), pixels.height(), ColorType::Rgb8)
+        .map_err(epub_error)?;
+    Ok(jpeg)
+}
+
+fn xhtml_document(title: &str, language: &str, width: u32, height: u32, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{lang}" lang="{lang}">
+<head>
+<meta charset="UTF-8"/>
+<meta name="viewport" content="width={width}, height={height}"/>
+<title>{title}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        lang = xml_escape(language),
+        title = xml_escape(title),
+    )
+}
+
+fn stylesheet(width: u32, height: u32, font_size: u32) -> String {
+    format!(
+        "html, body {{ margin: 0; padding: 0; width: {width}px; height: {height}px; overflow: hidden; }}\n\
+         .page {{ position: relative; width: {width}px; height: {height}px; }}\n\
+         .page img {{ position: absolute; top: 0; left: 0; width: {width}px; height: {height}px; }}\n\
+         .text {{ position: absolute; left: 0; right: 0; bottom: 0; padding: {pad}px {pad2}px; \
+         background: rgba(255, 255, 255, 0.85); font-size: {font_size}px; line-height: 1.5; \
+         text-align: center; color: #222; }}\n\
+         .title {{ position: absolute; left: 0; right: 0; top: 8%; text-align: center; \
+         font-size: {title_size}px; font-weight: bold; color: #222; \
+         text-shadow: 0 0 8px #fff, 0 0 4px #fff; }}\n",
+        pad = font_size / 2,
+        pad2 = font_size,
+        title_size = font_size * 2,
+    )
+}
+
+fn container_xml() -> &'static str {
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#
+}
+
+struct EpubPage {
+    /// `page-001`, shared by the XHTML file and its image.
+    name: String,
+    has_image: bool,
+}
+
+fn content_opf(project: &Project, pages: &[EpubPage], cover_image: Option<&str>) -> String {
+    let settings = &project.settings;
+    let modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    let mut metadata = format!(
+        "    <dc:identifier id=\"book-id\">urn:huiben:{}</dc:identifier>\n\
+         \x20   <dc:title>{}</dc:title>\n\
+         \x20   <dc:language>{}</dc:language>\n\
+         \x20   <meta property=\"dcterms:modified\">{}</meta>\n\
+         \x20   <meta property=\"rendition:layout\">pre-paginated</meta>\n\
+         \x20   <meta property=\"rendition:orientation\">auto</meta>\n\
+         \x20   <meta property=\"rendition:spread\">none</meta>\n",
+        xml_escape(&project.id),
+        xml_escape(&project.name),
+        xml_escape(&settings.language),
+        modified,
+    );
+    if !settings.author.trim().is_empty() {
+        metadata.push_str(&format!("    <dc:creator>{}</dc:creator>\n", xml_escape(&settings.author)));
+    }
+    if !settings.description.trim().is_empty() {
+        metadata.push_str(&format!(
+            "    <dc:description>{}</dc:description>\n",
+            xml_escape(&settings.description)
+        ));
+    }
+
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+         \x20   <item id=\"css\" href=\"style.css\" media-type=\"text/css\"/>\n\
+         \x20   <item id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+    );
+    let mut spine = String::from("    <itemref idref=\"cover\"/>\n");
+    for page in pages {
+        manifest.push_str(&format!(
+            "    <item id=\"{0}\" href=\"{0}.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+            page.name
+        ));
+        if page.has_image {
+            let properties = if cover_image == Some(page.name.as_str()) {
+                " properties=\"cover-image\""
+            } else {
+                ""
+            };
+            manifest.push_str(&format!(
+                "    <item id=\"img-{0}\" href=\"images/{0}.jpg\" media-type=\"image/jpeg\"{1}/>\n",
+                page.name, properties
+            ));
+        }
+        spine.push_str(&format!("    <itemref idref=\"{}\"/>\n", page.name));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="{lang}" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{metadata}  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+        lang = xml_escape(&settings.language),
+    )
+}
+
+fn nav_xhtml(project: &Project, pages: &[EpubPage], width: u32, height: u32) -> String {
+    let mut items = String::from("<li><a href=\"cover.xhtml\">封面</a></li>\n");
+    for (index, (page, source)) in pages.iter().zip(&project.pages).enumerate() {
+        let label: String = source.text.trim().chars().take(20).collect();
+        let label = if label.is_empty() {
+            format!("第 {} 页", index + 1)
+        } else {
+            format!("第 {} 页 {}", index + 1, xml_escape(&label))
+        };
+        items.push_str(&format!("<li><a href=\"{}.xhtml\">{}</a></li>\n", page.name, label));
+    }
+    let body = format!("<nav epub:type=\"toc\" id=\"toc\">\n<h1>目录</h1>\n<ol>\n{}</ol>\n</nav>", items);
+    xhtml_document(&project.name, &project.settings.language, width, height, &body)
+}
+
+/// Builds a fixed-layout EPUB 3 for `project` and writes it to `output`.
+pub fn export_epub(
+    project: &Project,
+    output: PathBuf,
+    options: &EpubExportOptions,
+) -> AppResult<EpubExportReport> {
+    if project.pages.is_empty() {
+        return Err(AppError::invalid_input("项目还没有页面"));
+    }
+    if options.width < 100 || options.font_size == 0 {
+        return Err(AppError::invalid_input("页面宽度或字号无效"));
+    }
+
+    let mut images = Vec::with_capacity(project.pages.len());
+    for page in &project.pages {
+        images.push(load_page_image(page)?);
+    }
+    let width = options.width;
+    let height = match options.height {
+        Some(height) if height >= 100 => height,
+        Some(_) => return Err(AppError::invalid_input("页面高度无效")),
+        None => images
+            .iter()
+            .flatten()
+            .next()
+            .map(|img| (width as f32 * img.height() as f32 / img.width() as f32).round() as u32)
+            .unwrap_or(width),
+    };
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::File::create(&output)?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, data: &[u8], options: SimpleFileOptions| -> AppResult<()> {
+        zip.start_file(name, options).map_err(epub_error)?;
+        zip.write_all(data)?;
+        Ok(())
+    };
+
+    // Readers identify the format from this first, uncompressed entry
+    add("mimetype", b"application/epub+zip", stored)?;
+    add("META-INF/container.xml", container_xml().as_bytes(), deflated)?;
+    add("OEBPS/style.css", stylesheet(width, height, options.font_size).as_bytes(), deflated)?;
+
+    let language = &project.settings.language;
+    let mut pages = Vec::with_capacity(project.pages.len());
+    let mut warnings = Vec::new();
+    for (index, (page, image)) in project.pages.iter().zip(&images).enumerate() {
+        let name = format!("page-{:03}", index + 1);
+        let mut body = String::from("<div class=\"page\">\n");
+        match image {
+            Some(image) => {
+                // JPEG is already compressed; deflating it again only costs time
+                add(&format!("OEBPS/images/{}.jpg", name), &page_jpeg(image, width, height)?, stored)?;
+                body.push_str(&format!("<img src=\"images/{}.jpg\" alt=\"\"/>\n", name));
+            }
+            None => warnings.push(ExportWarning {
+                page: index + 1,
+                page_id: page.id.clone(),
+                message: "页面还没有选定图片，将只显示文字".to_string(),
+            }),
+        }
+        if !page.text.trim().is_empty() {
+            body.push_str(&format!("<div class=\"text\">{}</div>\n", text_to_xhtml(&page.text)));
+        }
+        body.push_str("</div>");
+        let title = format!("{} - {}", project.name, index + 1);
+        add(
+            &format!("OEBPS/{}.xhtml", name),
+            xhtml_document(&title, language, width, height, &body).as_bytes(),
+            deflated,
+        )?;
+        pages.push(EpubPage {
+            name,
+            has_image: image.is_some(),
+        });
+    }
+
+    // The cover reuses the first illustrated page with the title on top
+    let cover_image = pages.iter().find(|p| p.has_image).map(|p| p.name.clone());
+    let mut cover_body = String::from("<div class=\"page\">\n");
+    if let Some(name) = &cover_image {
+        cover_body.push_str(&format!("<img src=\"images/{}.jpg\" alt=\"\"/>\n", name));
+    }
+    cover_body.push_str(&format!("<div class=\"title\">{}</div>\n</div>", xml_escape(&project.name)));
+    add(
+        "OEBPS/cover.xhtml",
+        xhtml_document(&project.name, language, width, height, &cover_body).as_bytes(),
+        deflated,
+    )?;
+    add("OEBPS/nav.xhtml", nav_xhtml(project, &pages, width, height).as_bytes(), deflated)?;
+    add(
+        "OEBPS/content.opf",
+        content_opf(project, &pages, cover_image.as_deref()).as_bytes(),
+        deflated,
+    )?;
+
+    zip.finish().map_err(epub_error)?.flush()?;
+
+    Ok(EpubExportReport {
+        path: output.to_string_lossy().to_string(),
+        pages: project.pages.len(),
+        warnings,
+    })
+}
+
+/// Exports a project as a fixed-layout EPUB 3. Without `output_path` the file goes to the
+/// project's `exports` folder.
+#[tauri::command]
+pub async fn export_project_epub(
+    project_id: String,
+    output_path: Option<String>,
+    options: Option<EpubExportOptions>,
+) -> AppResult<EpubExportReport> {
+    tokio::task::spawn_blocking(move || {
+        let project = project::load_project(&project_id)?;
+        let output = match output_path {
+            Some(path) => PathBuf::from(path),
+            None => project::default_export_path(&project, "epub")?,
+        };
+        export_epub(&project, output, &options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("导出任务异常: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::project::{ProjectPage, ProjectSettings};
+    use std::io::Read;
+
+    #[test]
+    fn test_exports_fixed_layout_epub() {
+        let dir = std::env::temp_dir().join(format!("huiben_epub_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image_path = dir.join("page.png");
+        image::RgbImage::from_pixel(600, 400, image::Rgb([40, 120, 200]))
+            .save(&image_path)
+            .unwrap();
+
+        let page = |id: &str, text: &str, image: Option<&PathBuf>| ProjectPage {
+            id: id.to_string(),
+            text: text.to_string(),
+            prompt: String::new(),
+            params: None,
+            negative_prompt: None,
+            seed: None,
+            last_seed: None,
+            image: image.map(|p| p.to_string_lossy().to_string()),
+            images: Vec::new(),
+        };
+        let project = Project {
+            id: "proj_epub".to_string(),
+            name: "小狐狸 & 月亮".to_string(),
+            created_at: String::new(),
+            updated_at: String::new(),
+            pages: vec![
+                page("p1", "从前有一只<小狐狸>。\n它很想摸到月亮。", Some(&image_path)),
+                page("p2", "完", None),
+            ],
+            bindings: Default::default(),
+            settings: ProjectSettings {
+                author: "阿蓝".to_string(),
+                ..Default::default()
+            },
+        };
+
+        let output = dir.join("book.epub");
+        let report = export_epub(&project, output.clone(), &EpubExportOptions::default()).unwrap();
+        assert_eq!(report.pages, 2);
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].page, 2);
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&output).unwrap()).unwrap();
+        {
+            let first = archive.by_index(0).unwrap();
+            assert_eq!(first.name(), "mimetype");
+            assert_eq!(first.compression(), CompressionMethod::Stored);
+        }
+        let mut read = |name: &str| {
+            let mut content = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+            content
+        };
+
+        let opf = read("OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>小狐狸 &amp; 月亮</dc:title>"));
+        assert!(opf.contains("<dc:creator>阿蓝</dc:creator>"));
+        assert!(opf.contains("<dc:language>zh-CN</dc:language>"));
+        assert!(opf.contains("pre-paginated"));
+        assert!(opf.contains("href=\"images/page-001.jpg\" media-type=\"image/jpeg\" properties=\"cover-image\""));
+
+        // The viewport follows the 3:2 image
+        let page1 = read("OEBPS/page-001.xhtml");
+        assert!(page1.contains("width=1200, height=800"));
+        assert!(page1.contains("从前有一只&lt;小狐狸&gt;。<br/>它很想摸到月亮。"));
+        assert!(read("OEBPS/nav.xhtml").contains("epub:type=\"toc\""));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
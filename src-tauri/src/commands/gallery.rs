@@ -0,0 +1,464 @@
+use chrono::{DateTime, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::history::{self, HistoryEntry};
+use super::image_generator;
+use super::providers::FieldError;
+use super::thumbnails;
+use crate::error::{AppError, AppResult};
+
+/// Serialises read-modify-write cycles on `gallery.json`.
+static GALLERY_LOCK: Mutex<()> = Mutex::new(());
+
+const DEFAULT_PAGE_SIZE: usize = 60;
+const MAX_RATING: u8 = 5;
+/// Trashed images move into this subfolder next to where they were archived.
+const TRASH_DIR: &str = "trash";
+
+/// One archived output. The id is the file's content hash, so an image generated twice is
+/// listed once, with the metadata of its first generation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GalleryItem {
+    pub id: String,
+    pub path: String,
+    pub url: Option<String>,
+    pub task_id: String,
+    pub prompt: String,
+    pub model: String,
+    pub created_at: String,
+    /// Bound characters the image was generated with.
+    pub characters: Vec<String>,
+    pub size: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    /// 0 (unrated) to 5.
+    #[serde(default)]
+    pub rating: u8,
+    /// Set while the image sits in the trash.
+    #[serde(default)]
+    pub trashed_at: Option<String>,
+}
+
+/// Stored as `gallery.json`, oldest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GalleryIndex {
+    items: Vec<GalleryItem>,
+}
+
+/// All fields are optional and combined with AND.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GalleryQuery {
+    /// Case-insensitive text searched in the prompt.
+    pub search: Option<String>,
+    pub model: Option<String>,
+    pub character: Option<String>,
+    pub tag: Option<String>,
+    /// RFC 3339 timestamp or `YYYY-MM-DD` (local midnight).
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub favorite: Option<bool>,
+    #[serde(alias = "minRating")]
+    pub min_rating: Option<u8>,
+    /// List the trash instead of the gallery.
+    pub trashed: bool,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GalleryPage {
+    /// Items matching the query, before `limit`/`offset`.
+    pub total: usize,
+    /// Newest first.
+    pub items: Vec<GalleryItem>,
+}
+
+fn get_gallery_path() -> PathBuf {
+    image_generator::get_app_data_dir().join("gallery.json")
+}
+
+impl GalleryIndex {
+    /// Adds the archived outputs of a successful generation.
+    fn add_entry(&mut self, entry: &HistoryEntry) {
+        if !entry.success {
+            return;
+        }
+        for path in entry.outputs.iter().filter_map(|o| o.path.as_ref()) {
+            let Some(id) = Path::new(path).file_stem().map(|s| s.to_string_lossy().to_string()) else {
+                continue;
+            };
+            if self.items.iter().any(|item| item.id == id) {
+                continue;
+            }
+            let url = entry
+                .outputs
+                .iter()
+                .find(|o| o.path.as_ref() == Some(path))
+                .and_then(|o| o.url.clone());
+            self.items.push(GalleryItem {
+                id,
+                path: path.clone(),
+                url,
+                task_id: entry.id.clone(),
+                prompt: entry.prompt.clone(),
+                model: entry.model.clone(),
+                created_at: entry.created_at.clone(),
+                characters: entry.character_bindings.iter().map(|b| b.character_name.clone()).collect(),
+                size: entry.effective_size.clone(),
+                tags: Vec::new(),
+                favorite: false,
+                rating: 0,
+                trashed_at: None,
+            });
+        }
+    }
+
+    fn item_mut(&mut self, id: &str) -> AppResult<&mut GalleryItem> {
+        self.items
+            .iter_mut()
+            .find(|item| item.id == id)
+            .ok_or_else(|| AppError::not_found(format!("图片不存在: {}", id)))
+    }
+
+    /// Fails on the first unknown id, before anything is moved.
+    fn check_ids(&self, ids: &[String]) -> AppResult<()> {
+        match ids.iter().find(|id| !self.items.iter().any(|item| item.id == **id)) {
+            Some(id) => Err(AppError::not_found(format!("图片不存在: {}", id))),
+            None => Ok(()),
+        }
+    }
+
+    fn query(&self, query: &GalleryQuery) -> AppResult<GalleryPage> {
+        let since = query.since.as_deref().map(|s| history::parse_time(s, "since")).transpose()?;
+        let until = query.until.as_deref().map(|s| history::parse_time(s, "until")).transpose()?;
+        let matching: Vec<&GalleryItem> = self
+            .items
+            .iter()
+            .rev()
+            .filter(|item| query.matches(item, since, until))
+            .collect();
+        Ok(GalleryPage {
+            total: matching.len(),
+            items: matching
+                .into_iter()
+                .skip(query.offset)
+                .take(query.limit.unwrap_or(DEFAULT_PAGE_SIZE))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Moves the images into the trash folder; returns how many were moved.
+    fn trash(&mut self, ids: &[String]) -> AppResult<usize> {
+        self.check_ids(ids)?;
+        let mut moved = 0;
+        for id in ids {
+            let item = self.item_mut(id)?;
+            if item.trashed_at.is_some() {
+                continue;
+            }
+            let source = PathBuf::from(&item.path);
+            let trash_dir = source.parent().map(|p| p.join(TRASH_DIR)).unwrap_or_else(|| TRASH_DIR.into());
+            let target = trash_dir.join(source.file_name().unwrap_or_default());
+            // Thumbnails are keyed by path, so the ones made for the old location go now
+            thumbnails::invalidate(&item.path);
+            if source.exists() {
+                fs::create_dir_all(&trash_dir)?;
+                fs::rename(&source, &target)?;
+            }
+            item.path = target.to_string_lossy().to_string();
+            item.trashed_at = Some(Local::now().to_rfc3339());
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    /// Moves trashed images back to where they were archived.
+    fn restore(&mut self, ids: &[String]) -> AppResult<usize> {
+        self.check_ids(ids)?;
+        let mut restored = 0;
+        for id in ids {
+            let item = self.item_mut(id)?;
+            if item.trashed_at.is_none() {
+                continue;
+            }
+            let source = PathBuf::from(&item.path);
+            let target = match source.parent().and_then(Path::parent) {
+                Some(dir) => dir.join(source.file_name().unwrap_or_default()),
+                None => source.clone(),
+            };
+            thumbnails::invalidate(&item.path);
+            if source.exists() {
+                fs::rename(&source, &target)?;
+            }
+            item.path = target.to_string_lossy().to_string();
+            item.trashed_at = None;
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    /// Deletes every trashed image for good.
+    /// Items leave the index one at a time, so a failed delete keeps the rest listed.
+    fn empty_trash(&mut self) -> AppResult<usize> {
+        let mut deleted = 0;
+        while let Some(pos) = self.items.iter().position(|i| i.trashed_at.is_some()) {
+            let path = self.items[pos].path.clone();
+            thumbnails::invalidate(&path);
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            self.items.remove(pos);
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+}
+
+/// A filter value that was actually given, trimmed.
+fn given(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+impl GalleryQuery {
+    fn matches(
+        &self,
+        item: &GalleryItem,
+        since: Option<DateTime<FixedOffset>>,
+        until: Option<DateTime<FixedOffset>>,
+    ) -> bool {
+        if self.trashed != item.trashed_at.is_some() {
+            return false;
+        }
+        if let Some(search) = given(&self.search) {
+            if !item.prompt.to_lowercase().contains(&search.to_lowercase()) {
+                return false;
+            }
+        }
+        if given(&self.model).is_some_and(|m| m != item.model) {
+            return false;
+        }
+        if given(&self.character).is_some_and(|c| !item.characters.iter().any(|n| n == c.trim_start_matches('@'))) {
+            return false;
+        }
+        if given(&self.tag).is_some_and(|t| !item.tags.iter().any(|tag| tag == t)) {
+            return false;
+        }
+        if self.favorite.is_some_and(|f| f != item.favorite) {
+            return false;
+        }
+        if self.min_rating.is_some_and(|r| item.rating < r) {
+            return false;
+        }
+        if since.is_some() || until.is_some() {
+            let Ok(created) = DateTime::parse_from_rfc3339(&item.created_at) else {
+                return false;
+            };
+            if since.is_some_and(|t| created < t) || until.is_some_and(|t| created > t) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Without a `gallery.json` yet, the index is built from the generation history so earlier
+/// outputs show up.
+fn load_gallery(path: &Path) -> AppResult<GalleryIndex> {
+    if path.exists() {
+        return Ok(serde_json::from_str(&fs::read_to_string(path)?)?);
+    }
+    let mut index = GalleryIndex::default();
+    for entry in history::all_entries()? {
+        index.add_entry(&entry);
+    }
+    Ok(index)
+}
+
+/// Loads the index and runs `f` on it without saving.
+fn read_gallery<T>(f: impl FnOnce(&GalleryIndex) -> AppResult<T>) -> AppResult<T> {
+    let _guard = GALLERY_LOCK.lock()?;
+    f(&load_gallery(&get_gallery_path())?)
+}
+
+/// Loads the index, runs `f` on it and saves it. The index is saved even when `f` fails,
+/// since files it already moved must stay recorded.
+fn with_gallery<T>(f: impl FnOnce(&mut GalleryIndex) -> AppResult<T>) -> AppResult<T> {
+    let path = get_gallery_path();
+    let _guard = GALLERY_LOCK.lock()?;
+    let mut index = load_gallery(&path)?;
+    let result = f(&mut index);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&index)?)?;
+    result
+}
+
+/// Adds a finished generation's outputs; called when it is written to the history.
+pub(super) fn add_generation(entry: &HistoryEntry) {
+    if let Err(e) = with_gallery(|index| {
+        index.add_entry(entry);
+        Ok(())
+    }) {
+        eprintln!("Failed to update gallery: {}", e);
+    }
+}
+
+#[tauri::command]
+pub fn list_gallery(query: Option<GalleryQuery>) -> AppResult<GalleryPage> {
+    read_gallery(|index| index.query(&query.unwrap_or_default()))
+}
+
+#[tauri::command]
+pub fn set_gallery_favorite(id: String, favorite: bool) -> AppResult<GalleryItem> {
+    with_gallery(|index| {
+        let item = index.item_mut(&id)?;
+        item.favorite = favorite;
+        Ok(item.clone())
+    })
+}
+
+#[tauri::command]
+pub fn set_gallery_rating(id: String, rating: u8) -> AppResult<GalleryItem> {
+    if rating > MAX_RATING {
+        return Err(AppError::validation(vec![FieldError {
+            field: "rating".to_string(),
+            message: format!("评分应在 0 到 {} 之间", MAX_RATING),
+        }]));
+    }
+    with_gallery(|index| {
+        let item = index.item_mut(&id)?;
+        item.rating = rating;
+        Ok(item.clone())
+    })
+}
+
+#[tauri::command]
+pub fn set_gallery_tags(id: String, tags: Vec<String>) -> AppResult<GalleryItem> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.dedup();
+    with_gallery(|index| {
+        let item = index.item_mut(&id)?;
+        item.tags = tags;
+        Ok(item.clone())
+    })
+}
+
+/// Every tag in use, sorted.
+#[tauri::command]
+pub fn list_gallery_tags() -> AppResult<Vec<String>> {
+    read_gallery(|index| {
+        let mut tags: Vec<String> = index.items.iter().flat_map(|i| i.tags.clone()).collect();
+        tags.sort();
+        tags.dedup();
+        Ok(tags)
+    })
+}
+
+#[tauri::command]
+pub fn trash_gallery_items(ids: Vec<String>) -> AppResult<usize> {
+    with_gallery(|index| index.trash(&ids))
+}
+
+#[tauri::command]
+pub fn restore_gallery_items(ids: Vec<String>) -> AppResult<usize> {
+    with_gallery(|index| index.restore(&ids))
+}
+
+/// Permanently deletes everything in the trash.
+#[tauri::command]
+pub fn empty_gallery_trash() -> AppResult<usize> {
+    with_gallery(|index| index.empty_trash())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::image_files::ArchivedImage;
+
+    fn entry(id: &str, created_at: &str, prompt: &str, model: &str, path: &Path) -> HistoryEntry {
+        let mut entry: HistoryEntry = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "created_at": created_at,
+            "duration_ms": 10,
+            "model": model,
+            "prompt": prompt,
+            "segments": [],
+            "character_bindings": [{ "character_name": "小狐狸", "reference_image_path": null, "image_type": "character" }],
+            "params": {
+                "model": model, "prompt": prompt, "character_bindings": [], "width": 512, "height": 512,
+                "count": 1, "quality": "standard", "size": null, "sequential_image_generation": null,
+                "response_format": null, "watermark": null, "images": null, "negative_prompt": null,
+                "seed": null, "aspect_ratio": null
+            },
+            "success": true,
+            "error": null,
+            "error_code": null,
+            "effective_size": "512x512"
+        }))
+        .unwrap();
+        fs::write(path, prompt).unwrap();
+        entry.outputs = vec![ArchivedImage {
+            url: Some(format!("https://example.com/{}.png", id)),
+            path: Some(path.to_string_lossy().to_string()),
+        }];
+        entry
+    }
+
+    #[test]
+    fn test_queries_rates_and_trashes() {
+        let dir = std::env::temp_dir().join(format!("huiben_gallery_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let fox = dir.join("aaa.png");
+        let castle = dir.join("bbb.png");
+
+        let mut index = GalleryIndex::default();
+        index.add_entry(&entry("t1", "2026-10-01T09:00:00+08:00", "@小狐狸 in the forest", "mock", &fox));
+        index.add_entry(&entry("t2", "2026-10-08T09:00:00+08:00", "a castle at night", "seedream", &castle));
+        // The same file from another generation is not listed twice
+        index.add_entry(&entry("t3", "2026-10-09T09:00:00+08:00", "forest again", "mock", &fox));
+        assert_eq!(index.items.len(), 2);
+
+        index.item_mut("aaa").unwrap().rating = 4;
+        index.item_mut("bbb").unwrap().tags = vec!["封面".to_string()];
+
+        let ids = |page: GalleryPage| page.items.into_iter().map(|i| i.id).collect::<Vec<_>>();
+        let all = GalleryQuery::default();
+        assert_eq!(ids(index.query(&all).unwrap()), vec!["bbb", "aaa"]);
+        let search = GalleryQuery { search: Some("FOREST".into()), min_rating: Some(3), ..Default::default() };
+        assert_eq!(ids(index.query(&search).unwrap()), vec!["aaa"]);
+        let tagged = GalleryQuery { tag: Some("封面".into()), since: Some("2026-10-05".into()), ..Default::default() };
+        assert_eq!(ids(index.query(&tagged).unwrap()), vec!["bbb"]);
+
+        // An unknown id fails the whole call before any file moves
+        assert!(index.trash(&["aaa".to_string(), "zzz".to_string()]).is_err());
+        assert!(fox.exists());
+        assert_eq!(index.trash(&["aaa".to_string()]).unwrap(), 1);
+        assert!(!fox.exists());
+        assert!(dir.join(TRASH_DIR).join("aaa.png").exists());
+        assert_eq!(ids(index.query(&all).unwrap()), vec!["bbb"]);
+        let trash = GalleryQuery { trashed: true, ..Default::default() };
+        assert_eq!(ids(index.query(&trash).unwrap()), vec!["aaa"]);
+
+        assert_eq!(index.restore(&["aaa".to_string()]).unwrap(), 1);
+        assert!(fox.exists());
+        assert_eq!(index.trash(&["bbb".to_string()]).unwrap(), 1);
+        assert_eq!(index.empty_trash().unwrap(), 1);
+        assert_eq!(index.items.len(), 1);
+        assert!(!dir.join(TRASH_DIR).join("bbb.png").exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,310 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+use super::image_generator::{self, ImageGenerationParams, ImageGenerationResult};
+use crate::error::AppResult;
+
+static GENERATION_QUEUE: Lazy<Mutex<GenerationQueue>> =
+    Lazy::new(|| Mutex::new(GenerationQueue::default()));
+/// Version of the last snapshot written, so a slow writer never replaces a newer file.
+static PERSISTED_VERSION: Mutex<u64> = Mutex::new(0);
+
+/// Interactive jobs (a user clicking "generate") always start before queued batch jobs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobPriority {
+    #[default]
+    Interactive,
+    Batch,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QueuedJob {
+    task_id: String,
+    provider: String,
+    priority: JobPriority,
+    /// Enqueue order, for FIFO within a priority.
+    seq: u64,
+    /// Stored without inline reference images; a restored job keeps bindings, paths and URLs.
+    #[serde(serialize_with = "serialize_stored_params")]
+    params: ImageGenerationParams,
+    /// Resolves `generate_image_and_wait`; lost on restart, the task still runs and emits events.
+    #[serde(skip)]
+    done: Option<oneshot::Sender<ImageGenerationResult>>,
+    /// Last position published for this job, so unchanged positions are not re-sent.
+    #[serde(skip)]
+    reported_position: Option<u32>,
+}
+
+/// Waiting and running jobs. Persisted to `generation_queue.json` on every change so jobs
+/// survive a restart; jobs that were running when the app quit start again from the top.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GenerationQueue {
+    waiting: Vec<QueuedJob>,
+    running: Vec<QueuedJob>,
+    next_seq: u64,
+    /// Bumped for every snapshot taken by `pump`.
+    #[serde(skip)]
+    version: u64,
+}
+
+fn serialize_stored_params<S: Serializer>(
+    params: &ImageGenerationParams,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    params.for_storage(|_| None).serialize(serializer)
+}
+
+impl GenerationQueue {
+    fn push(&mut self, task_id: &str, params: ImageGenerationParams) -> &mut QueuedJob {
+        let job = QueuedJob {
+            task_id: task_id.to_string(),
+            provider: params.model.clone(),
+            priority: params.priority,
+            seq: self.next_seq,
+            params,
+            done: None,
+            reported_position: None,
+        };
+        self.next_seq += 1;
+
+        let index = self
+            .waiting
+            .partition_point(|j| (j.priority, j.seq) <= (job.priority, job.seq));
+        self.waiting.insert(index, job);
+        &mut self.waiting[index]
+    }
+
+    fn running_count(&self, provider: &str) -> usize {
+        self.running.iter().filter(|j| j.provider == provider).count()
+    }
+
+    /// Moves every job that fits under its provider's cap to `running`, in priority order, and
+    /// returns what is needed to start them.
+    fn take_startable(
+        &mut self,
+        limit: impl Fn(&str) -> usize,
+    ) -> Vec<(String, ImageGenerationParams, Option<oneshot::Sender<ImageGenerationResult>>)> {
+        let mut started = Vec::new();
+        let mut i = 0;
+        while i < self.waiting.len() {
+            let provider = &self.waiting[i].provider;
+            if self.running_count(provider) >= limit(provider).max(1) {
+                i += 1;
+                continue;
+            }
+            let mut job = self.waiting.remove(i);
+            started.push((job.task_id.clone(), job.params.clone(), job.done.take()));
+            self.running.push(job);
+        }
+        started
+    }
+
+    /// 1-based position of each waiting job among jobs for the same provider, for jobs whose
+    /// position changed since it was last reported.
+    fn changed_positions(&mut self) -> Vec<(String, u32)> {
+        let mut per_provider: HashMap<&str, u32> = HashMap::new();
+        let mut positions = Vec::new();
+        for job in &self.waiting {
+            let position = per_provider.entry(job.provider.as_str()).or_insert(0);
+            *position += 1;
+            positions.push(*position);
+        }
+
+        let mut changed = Vec::new();
+        for (job, position) in self.waiting.iter_mut().zip(positions) {
+            if job.reported_position != Some(position) {
+                job.reported_position = Some(position);
+                changed.push((job.task_id.clone(), position));
+            }
+        }
+        changed
+    }
+
+    fn remove(&mut self, task_id: &str) -> bool {
+        let before = self.waiting.len() + self.running.len();
+        self.waiting.retain(|j| j.task_id != task_id);
+        self.running.retain(|j| j.task_id != task_id);
+        before != self.waiting.len() + self.running.len()
+    }
+}
+
+fn get_queue_path() -> PathBuf {
+    image_generator::get_app_data_dir().join("generation_queue.json")
+}
+
+/// Takes a versioned JSON snapshot; cheap enough to do under the queue lock.
+fn snapshot(queue: &mut GenerationQueue) -> Option<(u64, String)> {
+    queue.version += 1;
+    match serde_json::to_string_pretty(queue) {
+        Ok(json) => Some((queue.version, json)),
+        Err(e) => {
+            eprintln!("Failed to serialize generation queue: {}", e);
+            None
+        }
+    }
+}
+
+/// Writes a snapshot unless a newer one was written already. Called without the queue lock.
+fn persist(version: u64, json: &str) {
+    let Ok(mut persisted) = PERSISTED_VERSION.lock() else {
+        return;
+    };
+    if version <= *persisted {
+        return;
+    }
+    match fs::write(get_queue_path(), json) {
+        Ok(()) => *persisted = version,
+        Err(e) => eprintln!("Failed to persist generation queue: {}", e),
+    }
+}
+
+/// Adds a registered task to the queue and starts it as soon as its provider has a free slot.
+/// The receiver yields the final result; it is dropped without a value if the task is cancelled.
+pub fn enqueue(
+    task_id: &str,
+    params: ImageGenerationParams,
+) -> AppResult<oneshot::Receiver<ImageGenerationResult>> {
+    let (tx, rx) = oneshot::channel();
+    {
+        let mut queue = GENERATION_QUEUE.lock()?;
+        queue.push(task_id, params).done = Some(tx);
+    }
+    pump();
+    Ok(rx)
+}
+
+/// Drops a waiting or running job and starts whatever can run next. Called when a job
+/// finishes or is cancelled; running jobs must be aborted separately.
+pub fn remove(task_id: &str) {
+    let removed = match GENERATION_QUEUE.lock() {
+        Ok(mut queue) => queue.remove(task_id),
+        Err(_) => false,
+    };
+    if removed {
+        pump();
+    }
+}
+
+/// Starts jobs that fit under the concurrency caps and reports queue positions of the rest.
+pub fn pump() {
+    let limits = image_generator::provider_concurrency_limits();
+    let (started, positions, snapshot) = {
+        let Ok(mut queue) = GENERATION_QUEUE.lock() else {
+            return;
+        };
+        let started = queue.take_startable(|provider| limits.get(provider).copied().unwrap_or(1));
+        let positions = queue.changed_positions();
+        (started, positions, snapshot(&mut queue))
+    };
+    if let Some((version, json)) = snapshot {
+        persist(version, &json);
+    }
+
+    for (task_id, params, done) in started {
+        image_generator::start_queued_task(task_id, params, done);
+    }
+    for (task_id, position) in positions {
+        image_generator::report_queue_position(&task_id, position);
+    }
+}
+
+/// Re-queues jobs left over from the previous run. Call once at startup inside the runtime.
+pub fn restore_queue() {
+    let path = get_queue_path();
+    let Ok(json) = fs::read_to_string(&path) else {
+        return;
+    };
+    let saved: GenerationQueue = match serde_json::from_str(&json) {
+        Ok(saved) => saved,
+        Err(e) => {
+            eprintln!("Discarding unreadable generation queue: {}", e);
+            return;
+        }
+    };
+
+    let mut jobs: Vec<QueuedJob> = saved.running.into_iter().chain(saved.waiting).collect();
+    jobs.sort_by_key(|j| (j.priority, j.seq));
+
+    jobs.retain(|j| image_generator::register_task(&j.task_id).is_ok());
+
+    if let Ok(mut queue) = GENERATION_QUEUE.lock() {
+        for job in jobs {
+            queue.push(&job.task_id, job.params);
+        }
+    }
+    pump();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(model: &str, priority: JobPriority) -> ImageGenerationParams {
+        ImageGenerationParams {
+            model: model.to_string(),
+            prompt: "小狐狸".to_string(),
+            character_bindings: vec![],
+            width: 512,
+            height: 512,
+            count: 1,
+            quality: "standard".to_string(),
+            size: None,
+            sequential_image_generation: None,
+            response_format: None,
+            watermark: None,
+            images: None,
+            negative_prompt: None,
+            seed: None,
+            aspect_ratio: None,
+            priority,
+        }
+    }
+
+    #[test]
+    fn test_caps_and_priorities() {
+        let mut queue = GenerationQueue::default();
+        queue.push("batch_1", params("seedream", JobPriority::Batch));
+        queue.push("batch_2", params("seedream", JobPriority::Batch));
+        queue.push("click", params("seedream", JobPriority::Interactive));
+        queue.push("local", params("sd_webui", JobPriority::Batch));
+
+        let limit = |provider: &str| if provider == "seedream" { 1 } else { 4 };
+        let started: Vec<String> = queue
+            .take_startable(limit)
+            .into_iter()
+            .map(|(id, _, _)| id)
+            .collect();
+        // The interactive job jumps the batch jobs; the other provider is not blocked
+        assert_eq!(started, vec!["click", "local"]);
+
+        assert_eq!(
+            queue.changed_positions(),
+            vec![("batch_1".to_string(), 1), ("batch_2".to_string(), 2)]
+        );
+        assert!(queue.changed_positions().is_empty());
+
+        queue.remove("click");
+        let started = queue.take_startable(limit);
+        assert_eq!(started[0].0, "batch_1");
+        assert_eq!(queue.changed_positions(), vec![("batch_2".to_string(), 1)]);
+
+        // Inline reference images are not written to disk
+        let mut with_images = params("seedream", JobPriority::Batch);
+        with_images.images = Some(vec![
+            "data:image/png;base64,AAAA".to_string(),
+            "https://example.com/ref.png".to_string(),
+        ]);
+        queue.push("refs", with_images);
+        let (_, json) = snapshot(&mut queue).unwrap();
+        let saved: GenerationQueue = serde_json::from_str(&json).unwrap();
+        let job = saved.waiting.iter().find(|j| j.task_id == "refs").unwrap();
+        assert_eq!(job.params.images, Some(vec!["https://example.com/ref.png".to_string()]));
+        assert!(!json.contains("base64"));
+    }
+}
@@ -0,0 +1,410 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::gallery;
+use super::generation_queue::JobPriority;
+use super::image_generator::{
+    self, CharacterBindingInfo, GenerationStarted, ImageGenerationParams, ImageGenerationResult,
+};
+use super::image_files::ArchivedImage;
+use super::prompt_parser::{self, PromptSegment};
+use crate::error::{AppError, AppResult, ErrorCode};
+
+/// Serialises appends and rewrites of `history.jsonl`.
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// One finished generation, stored as a line of `history.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The generation's task id.
+    pub id: String,
+    /// RFC 3339 time the generation started running.
+    pub created_at: String,
+    pub duration_ms: u64,
+    pub model: String,
+    pub prompt: String,
+    pub segments: Vec<PromptSegment>,
+    pub character_bindings: Vec<CharacterBindingInfo>,
+    /// Exactly what was sent, so the entry can be run again.
+    pub params: ImageGenerationParams,
+    pub success: bool,
+    /// Original URLs and archived copies; inline images are only kept as files.
+    #[serde(default)]
+    pub outputs: Vec<ArchivedImage>,
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+    pub effective_size: Option<String>,
+}
+
+/// All fields are optional and combined with AND.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryFilter {
+    /// Case-insensitive text searched in the prompt.
+    pub query: Option<String>,
+    pub model: Option<String>,
+    /// Only generations that used this bound character.
+    pub character: Option<String>,
+    pub success: Option<bool>,
+    /// RFC 3339 timestamp or `YYYY-MM-DD` (local midnight).
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryPage {
+    /// Entries matching the filter, before `limit`/`offset`.
+    pub total: usize,
+    /// Newest first.
+    pub entries: Vec<HistoryEntry>,
+}
+
+fn get_history_path() -> PathBuf {
+    image_generator::get_app_data_dir().join("history.jsonl")
+}
+
+pub(super) fn parse_time(value: &str, field: &str) -> AppResult<DateTime<FixedOffset>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time);
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .map(|time| time.fixed_offset())
+        .ok_or_else(|| AppError::invalid_input(format!("{} 时间格式无效: {}", field, value)))
+}
+
+impl HistoryFilter {
+    fn matches(
+        &self,
+        entry: &HistoryEntry,
+        since: Option<DateTime<FixedOffset>>,
+        until: Option<DateTime<FixedOffset>>,
+    ) -> bool {
+        if let Some(query) = self.query.as_deref().filter(|q| !q.trim().is_empty()) {
+            if !entry.prompt.to_lowercase().contains(&query.trim().to_lowercase()) {
+                return false;
+            }
+        }
+        if self.model.as_ref().is_some_and(|m| !m.is_empty() && *m != entry.model) {
+            return false;
+        }
+        if let Some(character) = self.character.as_deref().filter(|c| !c.is_empty()) {
+            let character = character.trim_start_matches('@');
+            if !entry.character_bindings.iter().any(|b| b.character_name == character) {
+                return false;
+            }
+        }
+        if self.success.is_some_and(|s| s != entry.success) {
+            return false;
+        }
+        if since.is_some() || until.is_some() {
+            let Ok(created) = DateTime::parse_from_rfc3339(&entry.created_at) else {
+                return false;
+            };
+            if since.is_some_and(|t| created < t) || until.is_some_and(|t| created > t) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn append_entry(path: &Path, entry: &HistoryEntry) -> AppResult<()> {
+    let line = serde_json::to_string(entry)?;
+    let _guard = HISTORY_LOCK.lock()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+    // Start on a fresh line after a write torn by a crash, or this entry is lost with it
+    let len = file.metadata()?.len();
+    if len > 0 {
+        let mut last = [0u8; 1];
+        file.seek(SeekFrom::Start(len - 1))?;
+        file.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            file.write_all(b"\n")?;
+        }
+    }
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+/// Entries in the order they were recorded. Lines that fail to parse (e.g. a write cut short
+/// by a crash) are skipped.
+fn read_entries(path: &Path) -> AppResult<Vec<HistoryEntry>> {
+    let _guard = HISTORY_LOCK.lock()?;
+    read_entries_locked(path)
+}
+
+/// Every recorded generation, oldest first.
+pub(super) fn all_entries() -> AppResult<Vec<HistoryEntry>> {
+    read_entries(&get_history_path())
+}
+
+fn read_entries_locked(path: &Path) -> AppResult<Vec<HistoryEntry>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+fn query_entries(path: &Path, filter: &HistoryFilter) -> AppResult<HistoryPage> {
+    let since = filter.since.as_deref().map(|s| parse_time(s, "since")).transpose()?;
+    let until = filter.until.as_deref().map(|s| parse_time(s, "until")).transpose()?;
+    let matching: Vec<HistoryEntry> = read_entries(path)?
+        .into_iter()
+        .rev()
+        .filter(|e| filter.matches(e, since, until))
+        .collect();
+    Ok(HistoryPage {
+        total: matching.len(),
+        entries: matching
+            .into_iter()
+            .skip(filter.offset)
+            .take(filter.limit.unwrap_or(DEFAULT_PAGE_SIZE))
+            .collect(),
+    })
+}
+
+/// Tracks a running generation and writes its history entry when it ends. Dropping it
+/// unfinished (the task was aborted by a cancel) records the generation as cancelled.
+pub(super) struct HistoryRecorder {
+    task_id: String,
+    params: ImageGenerationParams,
+    created_at: String,
+    started: Instant,
+    finished: bool,
+}
+
+/// Inline reference images are recorded by hash so entries stay small.
+fn params_for_history(params: &ImageGenerationParams) -> ImageGenerationParams {
+    params.for_storage(|image| {
+        let hex: String = Sha256::digest(image.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+        Some(format!("sha256:{}", hex))
+    })
+}
+
+impl HistoryRecorder {
+    pub(super) fn start(task_id: &str, params: &ImageGenerationParams) -> Self {
+        HistoryRecorder {
+            task_id: task_id.to_string(),
+            params: params.clone(),
+            created_at: Local::now().to_rfc3339(),
+            started: Instant::now(),
+            finished: false,
+        }
+    }
+
+    pub(super) fn finish(mut self, result: &ImageGenerationResult) {
+        self.finished = true;
+        self.write(result);
+    }
+
+    fn write(&self, result: &ImageGenerationResult) {
+        let segments = prompt_parser::parse_prompt_internal(&self.params.prompt)
+            .map(|p| p.segments)
+            .unwrap_or_default();
+        // Rerunning must reuse the seed that was generated for this run
+        let mut params = params_for_history(&self.params);
+        if result.seed.is_some() {
+            params.seed = result.seed;
+        }
+        let entry = HistoryEntry {
+            id: self.task_id.clone(),
+            created_at: self.created_at.clone(),
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            model: self.params.model.clone(),
+            prompt: self.params.prompt.clone(),
+            segments,
+            character_bindings: self.params.character_bindings.clone(),
+            params,
+            success: result.success,
+            outputs: result.outputs.clone(),
+            error: result.error.clone(),
+            error_code: result.error_code,
+            effective_size: result.effective_size.clone(),
+        };
+        // History is best effort; a full disk must not fail the generation itself
+        if let Err(e) = append_entry(&get_history_path(), &entry) {
+            eprintln!("Failed to record generation history: {}", e);
+        }
+        gallery::add_generation(&entry);
+    }
+}
+
+impl Drop for HistoryRecorder {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.write(&image_generator::cancelled_result(&self.task_id));
+        }
+    }
+}
+
+#[tauri::command]
+pub fn list_history(filter: Option<HistoryFilter>) -> AppResult<HistoryPage> {
+    query_entries(&get_history_path(), &filter.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn get_history_entry(id: String) -> AppResult<HistoryEntry> {
+    read_entries(&get_history_path())?
+        .into_iter()
+        .rev()
+        .find(|e| e.id == id)
+        .ok_or_else(|| AppError::not_found("历史记录不存在"))
+}
+
+#[tauri::command]
+pub fn delete_history_entry(id: String) -> AppResult<bool> {
+    let path = get_history_path();
+    // Held across read and rewrite so a generation finishing meanwhile is not lost
+    let _guard = HISTORY_LOCK.lock()?;
+    let entries = read_entries_locked(&path)?;
+    let before = entries.len();
+    let kept: Vec<String> = entries
+        .iter()
+        .filter(|e| e.id != id)
+        .map(serde_json::to_string)
+        .collect::<Result<_, _>>()?;
+    if kept.len() == before {
+        return Ok(false);
+    }
+
+    let mut content = kept.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    fs::write(&path, content)?;
+    Ok(true)
+}
+
+/// Parameters to run a past generation again, as an interactive job. Inline reference
+/// images were only kept as hashes and are left out.
+pub fn rerun_params(id: String) -> AppResult<ImageGenerationParams> {
+    let mut params = get_history_entry(id)?.params;
+    params.priority = JobPriority::Interactive;
+    if let Some(images) = params.images.as_mut() {
+        images.retain(|image| !image.starts_with("sha256:"));
+    }
+    Ok(params)
+}
+
+/// Queues a past generation again with the same prompt, bindings and parameters.
+#[tauri::command]
+pub async fn rerun_generation(id: String) -> AppResult<GenerationStarted> {
+    image_generator::generate_image(rerun_params(id)?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, created_at: &str, prompt: &str, success: bool) -> HistoryEntry {
+        let params = ImageGenerationParams {
+            model: "mock".to_string(),
+            prompt: prompt.to_string(),
+            character_bindings: vec![CharacterBindingInfo {
+                character_name: "小狐狸".to_string(),
+                reference_image_path: None,
+                image_type: "character".to_string(),
+            }],
+            width: 1024,
+            height: 1024,
+            count: 1,
+            quality: "standard".to_string(),
+            size: None,
+            sequential_image_generation: None,
+            response_format: None,
+            watermark: None,
+            images: None,
+            negative_prompt: None,
+            seed: None,
+            aspect_ratio: None,
+            priority: JobPriority::Batch,
+        };
+        HistoryEntry {
+            id: id.to_string(),
+            created_at: created_at.to_string(),
+            duration_ms: 1200,
+            model: params.model.clone(),
+            prompt: prompt.to_string(),
+            segments: vec![],
+            character_bindings: params.character_bindings.clone(),
+            params,
+            success,
+            outputs: vec![],
+            error: None,
+            error_code: None,
+            effective_size: None,
+        }
+    }
+
+    #[test]
+    fn test_records_and_filters_history() {
+        let path = std::env::temp_dir().join(format!("huiben_history_{}.jsonl", std::process::id()));
+        fs::remove_file(&path).ok();
+
+        append_entry(&path, &entry("t1", "2026-10-01T09:00:00+08:00", "@小狐狸 in the forest", true)).unwrap();
+        append_entry(&path, &entry("t2", "2026-10-08T09:00:00+08:00", "a castle at night", false)).unwrap();
+        append_entry(&path, &entry("t3", "2026-10-09T09:00:00+08:00", "@小狐狸 by the Forest lake", true)).unwrap();
+        // A torn line from a crash mid-write is ignored
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"id\":\"t4\"").unwrap();
+        append_entry(&path, &entry("t5", "2026-10-10T09:00:00+08:00", "a quiet meadow", true)).unwrap();
+
+        let all = query_entries(&path, &HistoryFilter::default()).unwrap();
+        assert_eq!(all.total, 4);
+        assert_eq!(all.entries[0].id, "t5");
+        assert_eq!(all.entries[1].id, "t3");
+
+        let forest = HistoryFilter {
+            query: Some("forest".to_string()),
+            since: Some("2026-10-05T00:00:00+08:00".to_string()),
+            ..Default::default()
+        };
+        let found = query_entries(&path, &forest).unwrap();
+        assert_eq!(found.entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["t3"]);
+
+        let failed = HistoryFilter {
+            success: Some(false),
+            character: Some("@小狐狸".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query_entries(&path, &failed).unwrap().entries[0].id, "t2");
+
+        let paged = HistoryFilter {
+            limit: Some(1),
+            offset: 1,
+            ..Default::default()
+        };
+        let page = query_entries(&path, &paged).unwrap();
+        assert_eq!((page.total, page.entries[0].id.as_str()), (4, "t3"));
+
+        let mut params = entry("t6", "2026-10-10T09:00:00+08:00", "", true).params;
+        params.images = Some(vec!["data:image/png;base64,AAAA".to_string(), "/tmp/ref.png".to_string()]);
+        let stored = params_for_history(&params).images.unwrap();
+        assert!(stored[0].starts_with("sha256:") && stored[0].len() == 71);
+        assert_eq!(stored[1], "/tmp/ref.png");
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,170 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::image_generator;
+use crate::error::{AppError, AppResult, ErrorCode};
+
+/// Local copy of one generated image, kept because provider URLs expire.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArchivedImage {
+    /// The http(s) URL the provider returned; `None` for inline `data:` images.
+    pub url: Option<String>,
+    /// `outputs/<sha256>.<ext>`; `None` when the download failed.
+    pub path: Option<String>,
+}
+
+/// Reads the bytes behind a generated image, which providers return either as a
+/// `data:image/...;base64,` URI or as an http(s) URL. Local file paths (archived
+/// outputs) are read directly.
+pub async fn fetch_image_bytes(image_url: &str) -> AppResult<Vec<u8>> {
+    if image_url.starts_with("data:image") {
+        let base64_data = image_url
+            .split(',')
+            .nth(1)
+            .ok_or_else(|| AppError::invalid_input("无效的图片数据格式"))?;
+        STANDARD
+            .decode(base64_data)
+            .map_err(|e| AppError::invalid_input(format!("图片解码失败: {}", e)))
+    } else if image_url.starts_with("http://") || image_url.starts_with("https://") {
+        let download_error =
+            |e: reqwest::Error| AppError::new(ErrorCode::ProviderUnavailable, format!("下载图片失败: {}", e));
+        // An expired URL answers with an HTML error page that must not pass as the image
+        let response = reqwest::get(image_url)
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(download_error)?;
+        let bytes = response.bytes().await.map_err(download_error)?;
+        Ok(bytes.to_vec())
+    } else if Path::new(image_url).is_file() {
+        Ok(tokio::fs::read(image_url).await?)
+    } else {
+        Err(AppError::invalid_input("不支持的图片格式"))
+    }
+}
+
+/// File extension matching the encoded image, `png` when the format is not recognised.
+pub fn image_extension(bytes: &[u8]) -> &'static str {
+    match image::guess_format(bytes) {
+        Ok(image::ImageFormat::Jpeg) => "jpg",
+        Ok(image::ImageFormat::WebP) => "webp",
+        Ok(image::ImageFormat::Gif) => "gif",
+        _ => "png",
+    }
+}
+
+/// Managed folder every generated image is downloaded to.
+pub fn outputs_dir() -> PathBuf {
+    image_generator::get_app_data_dir().join("outputs")
+}
+
+/// Checks a path that arrived over the HTTP API, which any web page can reach: it must be
+/// absolute and inside the app data directory (outputs, projects, batches, overlays). The
+/// file itself may not exist yet, so export targets pass too.
+pub fn data_dir_path(path: &str, field: &str) -> AppResult<PathBuf> {
+    let outside = || AppError::invalid_input(format!("{} 必须位于应用数据目录内: {}", field, path));
+    let path = PathBuf::from(path);
+    if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+        return Err(outside());
+    }
+    let root = image_generator::get_app_data_dir().canonicalize()?;
+    // Symlinks are resolved through the deepest part of the path that already exists
+    let existing = path.ancestors().find(|p| p.exists()).ok_or_else(outside)?;
+    if existing.canonicalize()?.starts_with(&root) {
+        Ok(path)
+    } else {
+        Err(outside())
+    }
+}
+
+/// Writes `bytes` to `dir` as `<sha256>.<ext>`. Identical images share one file; bytes that
+/// are not a recognised image are refused.
+fn store_output(dir: &Path, bytes: &[u8]) -> AppResult<PathBuf> {
+    if image::guess_format(bytes).is_err() {
+        return Err(AppError::invalid_input("下载的内容不是可识别的图片"));
+    }
+    let hash: String = Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect();
+    let path = dir.join(format!("{}.{}", hash, image_extension(bytes)));
+    if path.exists() {
+        return Ok(path);
+    }
+    fs::create_dir_all(dir)?;
+    // Written under a temporary name so a crash never leaves a truncated file behind the hash
+    let partial = path.with_extension("part");
+    fs::write(&partial, bytes)?;
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// Downloads every image into `outputs_dir()`, one entry per image in order. A failed
+/// download leaves `path` empty instead of failing the whole generation.
+pub async fn archive_images(images: &[String]) -> Vec<ArchivedImage> {
+    let dir = outputs_dir();
+    join_all(images.iter().map(|image| {
+        let dir = dir.clone();
+        async move {
+            let url = (!image.starts_with("data:")).then(|| image.clone());
+            let stored = match fetch_image_bytes(image).await {
+                Ok(bytes) => tokio::task::spawn_blocking(move || store_output(&dir, &bytes))
+                    .await
+                    .map_err(|e| AppError::internal(e.to_string()))
+                    .and_then(|r| r),
+                Err(e) => Err(e),
+            };
+            match stored {
+                Ok(path) => ArchivedImage {
+                    url,
+                    path: Some(path.to_string_lossy().to_string()),
+                },
+                Err(e) => {
+                    eprintln!("Failed to archive generated image: {}", e);
+                    ArchivedImage { url, path: None }
+                }
+            }
+        }
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_archives_by_content_hash() {
+        let dir = std::env::temp_dir().join(format!("huiben_outputs_{}", std::process::id()));
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(2, 2))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let first = store_output(&dir, &png).unwrap();
+        let second = store_output(&dir, &png).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.extension().unwrap(), "png");
+        assert_eq!(first.file_stem().unwrap().len(), 64);
+        assert!(store_output(&dir, b"<html>403 Forbidden</html>").is_err());
+
+        // Archived copies are readable through the same path as URLs
+        let data_uri = format!("data:image/png;base64,{}", STANDARD.encode(&png));
+        assert_eq!(fetch_image_bytes(&data_uri).await.unwrap(), png);
+        assert_eq!(fetch_image_bytes(&first.to_string_lossy()).await.unwrap(), png);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_api_paths_stay_in_data_dir() {
+        let root = image_generator::get_app_data_dir();
+        let export = root.join("projects").join("p1").join("book.pdf");
+        assert_eq!(data_dir_path(&export.to_string_lossy(), "outputPath").unwrap(), export);
+
+        let escape = root.join("..").join("escape.pdf");
+        for path in [escape.to_string_lossy().as_ref(), "/etc/passwd", "outputs/a.png"] {
+            assert!(data_dir_path(path, "outputPath").is_err(), "{}", path);
+        }
+    }
+}
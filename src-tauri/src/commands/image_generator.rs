@@ -2,28 +2,106 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use once_cell::sync::Lazy;
+use tokio::sync::{broadcast, oneshot};
 use aes_gcm::{
     aead::{Aead, KeyInit},
     Aes256Gcm, Nonce,
 };
 use rand::Rng;
 
+use super::generation_queue::{self, JobPriority};
+use super::history::HistoryRecorder;
+use super::image_files::{self, ArchivedImage};
+use super::providers::{
+    FieldError, GenerationRequest, ImageProvider, ProviderError, RetryPolicy, PROVIDER_REGISTRY,
+};
+use crate::error::{AppError, AppResult, ErrorCode};
+
 static API_CONFIG: Mutex<Option<ApiConfig>> = Mutex::new(None);
 static GENERATION_CONFIG: Mutex<Option<GenerationConfig>> = Mutex::new(None);
 static GENERATION_TASKS: Lazy<Mutex<HashMap<String, GenerationTask>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static GENERATION_EVENTS: Lazy<broadcast::Sender<GenerationEvent>> = Lazy::new(|| broadcast::channel(256).0);
+static TASK_COUNTER: AtomicU64 = AtomicU64::new(0);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Tauri event carrying every `GenerationEvent`.
+pub const GENERATION_PROGRESS_EVENT: &str = "generation-progress";
+
+/// Finished tasks stay queryable through `get_generation_progress` for this long.
+const FINISHED_TASK_RETENTION: Duration = Duration::from_secs(30 * 60);
+
+/// Per-provider settings keyed by provider id (see `providers::PROVIDER_REGISTRY`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "ApiConfigFile")]
 pub struct ApiConfig {
-    pub seedream: ModelConfig,
-    pub banana_pro: ModelConfig,
+    pub providers: HashMap<String, ModelConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ApiConfig {
+    pub fn get(&self, provider_id: &str) -> Option<&ModelConfig> {
+        self.providers.get(provider_id)
+    }
+}
+
+/// On-disk shape of `ApiConfig`. Files saved before providers became pluggable
+/// carry fixed `seedream` / `banana_pro` fields, which are folded into the map.
+#[derive(Deserialize)]
+struct ApiConfigFile {
+    #[serde(default)]
+    providers: HashMap<String, ModelConfig>,
+    seedream: Option<ModelConfig>,
+    #[serde(alias = "bananaPro")]
+    banana_pro: Option<ModelConfig>,
+}
+
+impl From<ApiConfigFile> for ApiConfig {
+    fn from(file: ApiConfigFile) -> Self {
+        let mut providers = file.providers;
+        if let Some(c) = file.seedream {
+            providers.entry("seedream".to_string()).or_insert(c);
+        }
+        if let Some(c) = file.banana_pro {
+            providers.entry("banana_pro".to_string()).or_insert(c);
+        }
+        ApiConfig { providers }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ModelConfig {
+    #[serde(alias = "baseUrl")]
     pub base_url: String,
+    #[serde(alias = "apiKey")]
     pub api_key: String,
+    /// Provider-specific settings, e.g. the model id of an OpenAI-compatible relay.
+    #[serde(default)]
+    pub options: HashMap<String, serde_json::Value>,
+    /// Retries for rate-limited and transient failures of this provider.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Jobs run at once for this provider; `None` uses `ImageProvider::max_concurrency`.
+    #[serde(default, alias = "maxConcurrency")]
+    pub max_concurrency: Option<u32>,
+}
+
+impl ModelConfig {
+    pub fn option_str(&self, key: &str) -> Option<&str> {
+        self.options
+            .get(key)
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+    }
+
+    pub fn option_bool(&self, key: &str) -> Option<bool> {
+        self.options.get(key).and_then(|v| v.as_bool())
+    }
+
+    pub fn option_u64(&self, key: &str) -> Option<u64> {
+        self.options.get(key).and_then(|v| v.as_u64())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +118,37 @@ pub struct ImageGenerationParams {
     pub response_format: Option<String>,
     pub watermark: Option<bool>,
     pub images: Option<Vec<String>>,
+    #[serde(alias = "negativePrompt")]
+    pub negative_prompt: Option<String>,
+    pub seed: Option<i64>,
+    /// `width:height`; filled in by validation for providers that only accept fixed ratios.
+    #[serde(alias = "aspectRatio")]
+    pub aspect_ratio: Option<String>,
+    #[serde(default)]
+    pub priority: JobPriority,
+}
+
+impl ImageGenerationParams {
+    /// Copy for writing to disk. Inline images (data URLs or bare base64, often megabytes
+    /// each) are passed through `replace`, which drops them by returning `None`; paths and
+    /// URLs are kept.
+    pub fn for_storage(&self, replace: impl Fn(&str) -> Option<String>) -> Self {
+        let mut stored = self.clone();
+        stored.images = self.images.as_ref().map(|images| {
+            images
+                .iter()
+                .filter_map(|image| {
+                    let inline = image.starts_with("data:") || image.len() > 4096;
+                    if inline {
+                        replace(image)
+                    } else {
+                        Some(image.clone())
+                    }
+                })
+                .collect()
+        });
+        stored
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,10 +164,46 @@ pub struct CharacterBindingInfo {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageGenerationResult {
     pub success: bool,
+    /// As returned by the provider; URLs may expire, see `outputs`.
     pub images: Vec<String>,
+    /// Downloaded copies of `images`, in the same order.
+    #[serde(default)]
+    pub outputs: Vec<ArchivedImage>,
     pub error: Option<String>,
     #[serde(alias = "taskId", alias = "task_id")]
     pub task_id: String,
+    /// Per-field problems found before the provider was called.
+    #[serde(default, alias = "fieldErrors")]
+    pub field_errors: Vec<FieldError>,
+    /// `WxH` actually requested from the provider after normalization and ratio snapping.
+    #[serde(default, alias = "effectiveSize")]
+    pub effective_size: Option<String>,
+    #[serde(default, alias = "aspectRatio")]
+    pub aspect_ratio: Option<String>,
+    /// Seed sent to the provider, generated when the request had none. `None` when the
+    /// provider does not support seeds.
+    #[serde(default)]
+    pub seed: Option<i64>,
+    /// Stable code of the failure, so clients can tell a bad key from a moderation block.
+    #[serde(default, alias = "errorCode")]
+    pub error_code: Option<ErrorCode>,
+}
+
+impl ImageGenerationResult {
+    /// Where to read each image from: the archived copy when there is one, else what the
+    /// provider returned.
+    pub fn image_sources(&self) -> Vec<&str> {
+        self.images
+            .iter()
+            .enumerate()
+            .map(|(n, image)| {
+                self.outputs
+                    .get(n)
+                    .and_then(|o| o.path.as_deref())
+                    .unwrap_or(image)
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +213,59 @@ pub struct GenerationProgress {
     pub status: String,
     pub progress: u32,
     pub message: Option<String>,
+    /// 1-based place among jobs waiting for the same provider; `None` once started.
+    #[serde(alias = "queuePosition")]
+    pub queue_position: Option<u32>,
+    /// Present once the task has finished.
+    pub result: Option<ImageGenerationResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationStage {
+    Queued,
+    UploadingRefs,
+    CallingModel,
+    Downloading,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl GenerationStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GenerationStage::Queued => "queued",
+            GenerationStage::UploadingRefs => "uploading_refs",
+            GenerationStage::CallingModel => "calling_model",
+            GenerationStage::Downloading => "downloading",
+            GenerationStage::Done => "done",
+            GenerationStage::Failed => "failed",
+            GenerationStage::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Payload of the `generation-progress` event. `result` is set on `done` / `failed` / `cancelled`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationEvent {
+    #[serde(alias = "taskId")]
+    pub task_id: String,
+    pub stage: GenerationStage,
+    pub progress: u32,
+    pub message: Option<String>,
+    /// Set on `queued` events while the task waits for a free provider slot.
+    #[serde(default, alias = "queuePosition")]
+    pub queue_position: Option<u32>,
+    pub result: Option<ImageGenerationResult>,
+}
+
+/// Returned by `generate_image` as soon as the task is registered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationStarted {
+    #[serde(alias = "taskId")]
+    pub task_id: String,
+    pub stage: GenerationStage,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,17 +283,62 @@ pub struct GenerationConfig {
     pub watermark: Option<bool>,
 }
 
+impl GenerationConfig {
+    /// Params for generating `prompt` with these settings, as the frontend would send them.
+    pub fn to_params(
+        &self,
+        prompt: String,
+        character_bindings: Vec<CharacterBindingInfo>,
+        priority: JobPriority,
+    ) -> ImageGenerationParams {
+        ImageGenerationParams {
+            model: self.model.clone(),
+            prompt,
+            character_bindings,
+            width: self.width,
+            height: self.height,
+            count: self.count,
+            quality: self.quality.clone(),
+            size: self.size.clone(),
+            sequential_image_generation: self.sequential_image_generation.clone(),
+            response_format: self.response_format.clone(),
+            watermark: self.watermark,
+            images: None,
+            negative_prompt: None,
+            seed: None,
+            aspect_ratio: None,
+            priority,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GenerationTask {
     pub status: String,
     pub progress: u32,
     pub message: Option<String>,
+    pub result: Option<ImageGenerationResult>,
+    pub finished_at: Option<Instant>,
+    /// Aborts the spawned generation future; set once the task is running.
+    pub abort: Option<tokio::task::AbortHandle>,
+    pub queue_position: Option<u32>,
 }
 
-fn get_app_data_dir() -> PathBuf {
-    let app_data = dirs::data_local_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("xuanchen-huiben");
+/// Environment variable that moves the data directory, e.g. for a portable install.
+pub const DATA_DIR_ENV: &str = "HUIBEN_DATA_DIR";
+
+/// Root of history, gallery, outputs, projects and config. Unit tests get a per-process temp
+/// directory unless `DATA_DIR_ENV` is set, so `cargo test` never touches the real library.
+pub(super) fn get_app_data_dir() -> PathBuf {
+    let app_data = match std::env::var_os(DATA_DIR_ENV) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(test) => {
+            std::env::temp_dir().join(format!("xuanchen-huiben-test-{}", std::process::id()))
+        }
+        None => dirs::data_local_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("xuanchen-huiben"),
+    };
     fs::create_dir_all(&app_data).ok();
     app_data
 }
@@ -112,11 +355,11 @@ fn get_key_path() -> PathBuf {
     get_app_data_dir().join("key.bin")
 }
 
-fn get_or_create_key() -> Result<[u8; 32], String> {
+fn get_or_create_key() -> AppResult<[u8; 32]> {
     let key_path = get_key_path();
     
     if key_path.exists() {
-        let key_data = fs::read(&key_path).map_err(|e| e.to_string())?;
+        let key_data = fs::read(&key_path)?;
         if key_data.len() == 32 {
             let mut key = [0u8; 32];
             key.copy_from_slice(&key_data);
@@ -127,19 +370,19 @@ fn get_or_create_key() -> Result<[u8; 32], String> {
     let mut key = [0u8; 32];
     rand::thread_rng().fill(&mut key);
     
-    fs::write(&key_path, &key).map_err(|e| e.to_string())?;
+    fs::write(&key_path, &key)?;
     
     Ok(key)
 }
 
-fn encrypt_data(data: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, String> {
-    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+fn encrypt_data(data: &[u8], key: &[u8; 32]) -> AppResult<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(crypto_error)?;
     
     let mut nonce_bytes = [0u8; 12];
     rand::thread_rng().fill(&mut nonce_bytes);
     let nonce = Nonce::from_slice(&nonce_bytes);
     
-    let ciphertext = cipher.encrypt(nonce, data).map_err(|e| e.to_string())?;
+    let ciphertext = cipher.encrypt(nonce, data).map_err(crypto_error)?;
     
     let mut result = Vec::new();
     result.extend_from_slice(&nonce_bytes);
@@ -148,108 +391,128 @@ fn encrypt_data(data: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, String> {
     Ok(result)
 }
 
-fn decrypt_data(encrypted: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, String> {
+fn decrypt_data(encrypted: &[u8], key: &[u8; 32]) -> AppResult<Vec<u8>> {
     if encrypted.len() < 12 {
-        return Err("加密数据格式错误".to_string());
+        return Err(AppError::new(ErrorCode::Crypto, "加密数据格式错误"));
     }
     
-    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(crypto_error)?;
     
     let nonce = Nonce::from_slice(&encrypted[..12]);
     let ciphertext = &encrypted[12..];
     
-    let plaintext = cipher.decrypt(nonce, ciphertext).map_err(|e| e.to_string())?;
+    let plaintext = cipher.decrypt(nonce, ciphertext).map_err(crypto_error)?;
     
     Ok(plaintext)
 }
 
+fn crypto_error(e: impl std::fmt::Display) -> AppError {
+    AppError::new(ErrorCode::Crypto, format!("配置解密失败: {}", e))
+}
+
 #[tauri::command]
-pub fn save_api_config(config: ApiConfig) -> Result<bool, String> {
+pub fn save_api_config(config: ApiConfig) -> AppResult<bool> {
     let config_path = get_config_path();
-    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(&config)?;
     
     let key = get_or_create_key()?;
     let encrypted = encrypt_data(json.as_bytes(), &key)?;
     
-    fs::write(&config_path, encrypted).map_err(|e| e.to_string())?;
+    fs::write(&config_path, encrypted)?;
     
-    let mut api_config = API_CONFIG.lock().map_err(|e| e.to_string())?;
+    let mut api_config = API_CONFIG.lock()?;
     *api_config = Some(config);
     
     Ok(true)
 }
 
 #[tauri::command]
-pub fn load_api_config() -> Result<ApiConfig, String> {
+pub fn load_api_config() -> AppResult<ApiConfig> {
     let config_path = get_config_path();
     
     if config_path.exists() {
-        let encrypted = fs::read(&config_path).map_err(|e| e.to_string())?;
+        let encrypted = fs::read(&config_path)?;
         
         let key = get_or_create_key()?;
         let decrypted = decrypt_data(&encrypted, &key)?;
-        let json = String::from_utf8(decrypted).map_err(|e| e.to_string())?;
+        let json = String::from_utf8(decrypted).map_err(crypto_error)?;
         
-        let config: ApiConfig = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+        let config: ApiConfig = serde_json::from_str(&json)?;
         
-        let mut api_config = API_CONFIG.lock().map_err(|e| e.to_string())?;
+        let mut api_config = API_CONFIG.lock()?;
         *api_config = Some(config.clone());
         
         Ok(config)
     } else {
-        Err("API配置不存在".to_string())
+        Err(AppError::new(ErrorCode::ConfigMissing, "API配置不存在"))
     }
 }
 
 #[tauri::command]
 pub fn get_default_api_config() -> ApiConfig {
     ApiConfig {
-        seedream: ModelConfig {
-            base_url: "https://eggfans.com".to_string(),
-            api_key: "".to_string(),
-        },
-        banana_pro: ModelConfig {
-            base_url: "https://api.zhongzhuan.chat".to_string(),
-            api_key: "".to_string(),
-        },
+        providers: PROVIDER_REGISTRY
+            .iter()
+            .map(|p| (p.id().to_string(), p.default_config()))
+            .collect(),
     }
 }
 
 #[tauri::command]
-pub fn save_generation_config(config: GenerationConfig) -> Result<bool, String> {
+pub fn save_generation_config(mut config: GenerationConfig) -> AppResult<bool> {
+    let provider = find_provider(&config.model)?;
+    let field_errors = provider.capabilities().validate_output(
+        config.size.as_deref(),
+        &mut config.width,
+        &mut config.height,
+        &mut config.count,
+    );
+    if !field_errors.is_empty() {
+        return Err(AppError::validation(field_errors));
+    }
+    
     let config_path = get_generation_config_path();
-    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
-    fs::write(&config_path, json).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(&config)?;
+    fs::write(&config_path, json)?;
     
-    let mut gen_config = GENERATION_CONFIG.lock().map_err(|e| e.to_string())?;
+    let mut gen_config = GENERATION_CONFIG.lock()?;
     *gen_config = Some(config);
     
     Ok(true)
 }
 
 #[tauri::command]
-pub fn load_generation_config() -> Result<GenerationConfig, String> {
+pub fn load_generation_config() -> AppResult<GenerationConfig> {
     let config_path = get_generation_config_path();
     
     if config_path.exists() {
-        let json = fs::read_to_string(&config_path).map_err(|e| e.to_string())?;
-        let config: GenerationConfig = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+        let json = fs::read_to_string(&config_path)?;
+        let config: GenerationConfig = serde_json::from_str(&json)?;
         
-        let mut gen_config = GENERATION_CONFIG.lock().map_err(|e| e.to_string())?;
+        let mut gen_config = GENERATION_CONFIG.lock()?;
         *gen_config = Some(config.clone());
         
         Ok(config)
     } else {
-        Err("生成参数配置不存在".to_string())
+        Err(AppError::new(ErrorCode::ConfigMissing, "生成参数配置不存在"))
     }
 }
 
+/// The saved generation defaults, or the built-in ones when nothing was saved.
+pub(super) fn current_generation_config() -> GenerationConfig {
+    GENERATION_CONFIG
+        .lock()
+        .ok()
+        .and_then(|c| c.clone())
+        .unwrap_or_else(get_default_generation_config)
+}
+
 #[tauri::command]
 pub fn get_default_generation_config() -> GenerationConfig {
     GenerationConfig {
         model: "seedream".to_string(),
-        width: 1,
-        height: 1,
+        width: 1024,
+        height: 1024,
         count: 1,
         quality: "standard".to_string(),
         size: Some("1024x1024".to_string()),
@@ -262,61 +525,265 @@ pub fn get_default_generation_config() -> GenerationConfig {
 #[tauri::command]
 pub async fn generate_image(
     params: ImageGenerationParams,
-) -> Result<ImageGenerationResult, String> {
-    let task_id = format!("task_{}", chrono::Utc::now().timestamp_millis());
-    
+) -> AppResult<GenerationStarted> {
+    let task_id = create_generation_task()?;
+    // Nobody waits on the receiver here; the result arrives through events
+    generation_queue::enqueue(&task_id, params)?;
+    
+    Ok(GenerationStarted {
+        task_id,
+        stage: GenerationStage::Queued,
+    })
+}
+
+/// Runs a generation to completion on the caller's task, for callers that want the
+/// final result directly (HTTP API, batch jobs). Progress events are still published.
+pub async fn generate_image_and_wait(
+    params: ImageGenerationParams,
+) -> AppResult<ImageGenerationResult> {
+    let task_id = create_generation_task()?;
+    let done = generation_queue::enqueue(&task_id, params)?;
+    // The sender is dropped without a value only when the task is cancelled or aborted
+    Ok(done.await.unwrap_or_else(|_| cancelled_result(&task_id)))
+}
+
+/// Aborts a queued or running task. The in-flight provider request is dropped, the task is
+/// marked `cancelled` and whatever it produced so far is discarded.
+#[tauri::command]
+pub fn cancel_generation(task_id: String) -> AppResult<bool> {
+    let result = cancelled_result(&task_id);
     {
-        let mut tasks = GENERATION_TASKS.lock().map_err(|e| e.to_string())?;
-        tasks.insert(task_id.clone(), GenerationTask {
-            status: "pending".to_string(),
-            progress: 0,
-            message: Some("正在初始化...".to_string()),
-        });
+        let mut tasks = GENERATION_TASKS.lock()?;
+        let task = tasks
+            .get_mut(&task_id)
+            .ok_or_else(|| AppError::not_found("任务不存在"))?;
+        if task.finished_at.is_some() {
+            return Err(AppError::new(ErrorCode::Conflict, "任务已结束"));
+        }
+        if let Some(abort) = task.abort.take() {
+            abort.abort();
+        }
+        task.status = GenerationStage::Cancelled.as_str().to_string();
+        task.progress = 0;
+        task.message = result.error.clone();
+        task.result = Some(result.clone());
+        task.finished_at = Some(Instant::now());
+        task.queue_position = None;
     }
     
-    update_task_progress(&task_id, "processing", 10, "正在准备请求...");
+    generation_queue::remove(&task_id);
     
-    let api_config = {
-        let config = API_CONFIG.lock().map_err(|e| e.to_string())?;
-        config.clone()
+    publish_event(GenerationEvent {
+        task_id,
+        stage: GenerationStage::Cancelled,
+        progress: 0,
+        message: result.error.clone(),
+        queue_position: None,
+        result: Some(result),
+    });
+    
+    Ok(true)
+}
+
+/// Frees the job's queue slot when the spawned generation ends, including when it is aborted.
+struct QueueSlot(String);
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        generation_queue::remove(&self.0);
+    }
+}
+
+/// Spawns `run_generation` for a job the queue just admitted, and keeps its abort handle on
+/// the task for `cancel_generation`.
+pub(super) fn start_queued_task(
+    task_id: String,
+    params: ImageGenerationParams,
+    done: Option<oneshot::Sender<ImageGenerationResult>>,
+) {
+    let slot = QueueSlot(task_id.clone());
+    // Holding the lock across the spawn means a cancel cannot slip in before the handle is stored
+    let Ok(mut tasks) = GENERATION_TASKS.lock() else {
+        return;
+    };
+    let Some(task) = tasks.get_mut(&task_id).filter(|t| t.finished_at.is_none()) else {
+        // Cancelled between admission and start
+        drop(tasks);
+        drop(slot);
+        return;
     };
     
-    let config = match api_config {
-        Some(c) => c,
-        None => {
-            let config_path = get_config_path();
-            eprintln!("Config path: {:?}", config_path);
-            if config_path.exists() {
-                eprintln!("Config file exists, attempting to decrypt...");
-                let encrypted = fs::read(&config_path).map_err(|e| format!("读取配置文件失败: {}", e))?;
-                let key = get_or_create_key().map_err(|e| format!("获取密钥失败: {}", e))?;
-                let decrypted = decrypt_data(&encrypted, &key).map_err(|e| format!("解密失败: {}", e))?;
-                let json = String::from_utf8(decrypted).map_err(|e| format!("UTF8转换失败: {}", e))?;
-                eprintln!("Config JSON: {}", json);
-                let loaded: ApiConfig = serde_json::from_str(&json).map_err(|e| format!("JSON解析失败: {}", e))?;
-                let mut api_config = API_CONFIG.lock().map_err(|e| e.to_string())?;
-                *api_config = Some(loaded.clone());
-                loaded
-            } else {
-                return Err("请先配置API".to_string());
-            }
+    task.queue_position = None;
+    let spawned_id = task_id.clone();
+    let handle = tokio::spawn(async move {
+        let _slot = slot;
+        let result = run_generation(&spawned_id, params).await;
+        if let Some(done) = done {
+            let _ = done.send(result);
         }
+    });
+    task.abort = Some(handle.abort_handle());
+}
+
+/// Records and publishes where a waiting task stands in its provider's queue.
+pub(super) fn report_queue_position(task_id: &str, position: u32) {
+    let message = if position <= 1 {
+        "排队中，即将开始...".to_string()
+    } else {
+        format!("排队中，前面还有 {} 个任务", position - 1)
     };
     
-    update_task_progress(&task_id, "processing", 30, "正在调用AI模型...");
+    if let Ok(mut tasks) = GENERATION_TASKS.lock() {
+        match tasks.get_mut(task_id) {
+            Some(task) if task.finished_at.is_none() => {
+                task.queue_position = Some(position);
+                task.message = Some(message.clone());
+            }
+            _ => return,
+        }
+    }
+    
+    publish_event(GenerationEvent {
+        task_id: task_id.to_string(),
+        stage: GenerationStage::Queued,
+        progress: 0,
+        message: Some(message),
+        queue_position: Some(position),
+        result: None,
+    });
+}
+
+/// Concurrency cap of every registered provider: the saved `max_concurrency`, else the
+/// provider's default. Reads the config once, so callers can look caps up under a lock.
+pub(super) fn provider_concurrency_limits() -> HashMap<String, usize> {
+    let config = current_api_config().ok().flatten();
+    PROVIDER_REGISTRY
+        .iter()
+        .map(|p| {
+            let configured = config
+                .as_ref()
+                .and_then(|c| c.get(p.id()))
+                .and_then(|m| m.max_concurrency);
+            (p.id().to_string(), configured.unwrap_or(p.max_concurrency()).max(1) as usize)
+        })
+        .collect()
+}
+
+/// A seed in the range every seed-aware backend accepts.
+pub fn random_seed() -> i64 {
+    rand::thread_rng().gen_range(0..i64::from(u32::MAX))
+}
+
+pub(super) fn cancelled_result(task_id: &str) -> ImageGenerationResult {
+    ImageGenerationResult {
+        success: false,
+        images: vec![],
+        outputs: vec![],
+        error: Some("任务已取消".to_string()),
+        task_id: task_id.to_string(),
+        field_errors: vec![],
+        effective_size: None,
+        aspect_ratio: None,
+        seed: None,
+        error_code: Some(ErrorCode::Cancelled),
+    }
+}
+
+fn create_generation_task() -> AppResult<String> {
+    let task_id = format!(
+        "task_{}_{}",
+        chrono::Utc::now().timestamp_millis(),
+        TASK_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    register_task(&task_id)?;
+    Ok(task_id)
+}
+
+/// Adds a `queued` entry to `GENERATION_TASKS` and announces it.
+pub(super) fn register_task(task_id: &str) -> AppResult<()> {
+    let task_id = task_id.to_string();
+    {
+        let mut tasks = GENERATION_TASKS.lock()?;
+        tasks.retain(|_, t| {
+            t.finished_at
+                .is_none_or(|at| at.elapsed() < FINISHED_TASK_RETENTION)
+        });
+        tasks.insert(task_id.clone(), GenerationTask {
+            status: GenerationStage::Queued.as_str().to_string(),
+            progress: 0,
+            message: Some("正在初始化...".to_string()),
+            result: None,
+            finished_at: None,
+            abort: None,
+            queue_position: None,
+        });
+    }
+    
+    publish_event(GenerationEvent {
+        task_id,
+        stage: GenerationStage::Queued,
+        progress: 0,
+        message: Some("正在初始化...".to_string()),
+        queue_position: None,
+        result: None,
+    });
     
-    let model_config = match params.model.as_str() {
-        "seedream" => config.seedream,
-        "banana_pro" => config.banana_pro,
-        _ => return Err("不支持的模型".to_string()),
+    Ok(())
+}
+
+/// Executes a registered task and records its final result, also in the generation history.
+/// Never fails: every error ends up in the returned result and in the `failed` event.
+async fn run_generation(task_id: &str, params: ImageGenerationParams) -> ImageGenerationResult {
+    let history = HistoryRecorder::start(task_id, &params);
+    let result = match execute_generation(task_id, params).await {
+        Ok(result) => result,
+        Err(e) => ImageGenerationResult {
+            success: false,
+            images: vec![],
+            outputs: vec![],
+            error: Some(e.message),
+            task_id: task_id.to_string(),
+            field_errors: e.field_errors,
+            effective_size: None,
+            aspect_ratio: None,
+            seed: None,
+            error_code: Some(e.code),
+        },
     };
+    finish_task(task_id, &result);
+    history.finish(&result);
+    result
+}
+
+async fn execute_generation(
+    task_id: &str,
+    mut params: ImageGenerationParams,
+) -> AppResult<ImageGenerationResult> {
+    let task_id = task_id.to_string();
+    
+    let provider = find_provider(&params.model)?;
+    
+    let capabilities = provider.capabilities();
+    let field_errors = capabilities.validate(&mut params);
+    if !field_errors.is_empty() {
+        return Err(AppError::validation(field_errors));
+    }
+    // Pick the seed here so it is recorded and can be reused; a provider without seed
+    // support would ignore it, so none is reported then
+    params.seed = capabilities
+        .supports_seed
+        .then(|| params.seed.unwrap_or_else(random_seed));
+    
+    let model_config = resolve_model_config(provider.as_ref())?;
     
     eprintln!("Using model: {}, base_url: {}", params.model, model_config.base_url);
     
-    if model_config.api_key.is_empty() {
-        return Err("请先配置API Key".to_string());
+    if provider.requires_api_key() && model_config.api_key.is_empty() {
+        return Err(AppError::new(ErrorCode::ApiKeyMissing, "请先配置API Key"));
     }
     
+    update_task_progress(&task_id, GenerationStage::UploadingRefs, 10, "正在处理参考图...");
+    
     // Convert local reference paths to base64 so the API can actually see them
     let mut api_images = params.images.clone().unwrap_or_default();
     use base64::{Engine as _, engine::general_purpose::STANDARD};
@@ -361,305 +828,226 @@ pub async fn generate_image(
     // Only pass Some if we actually loaded reference images
     let final_images = if api_images.is_empty() { None } else { Some(api_images) };
     
-    let prompt = build_prompt_with_bindings(&params);
+    let effective_size = Some(format!("{}x{}", params.width, params.height));
+    let aspect_ratio = params.aspect_ratio.clone();
+    let seed = params.seed;
     
-    let result = match params.model.as_str() {
-        "seedream" => call_seedream_api(&model_config, &prompt, params.size, params.sequential_image_generation, params.response_format, params.watermark, final_images).await,
-        "banana_pro" => call_banana_pro_api(&model_config, &prompt, params.width, params.height, params.count, final_images).await,
-        _ => Err("不支持的模型".to_string()),
+    let request = GenerationRequest {
+        prompt: build_prompt_with_bindings(&params),
+        params,
+        images: final_images,
     };
     
-    match result {
-        Ok(images) => {
-            update_task_progress(&task_id, "completed", 100, "生成完成");
-            
-            {
-                let mut tasks = GENERATION_TASKS.lock().map_err(|e| e.to_string())?;
-                tasks.remove(&task_id);
-            }
-            
-            Ok(ImageGenerationResult {
-                success: true,
-                images,
-                task_id,
-                error: None,
-            })
-        }
+    update_task_progress(&task_id, GenerationStage::CallingModel, 30, "正在调用AI模型...");
+    
+    let images = match generate_with_retry(&task_id, provider.as_ref(), &model_config, &request).await {
+        Ok(images) => images,
         Err(e) => {
-            update_task_progress(&task_id, "failed", 0, &e);
-            
-            {
-                let mut tasks = GENERATION_TASKS.lock().map_err(|e| e.to_string())?;
-                tasks.remove(&task_id);
-            }
-            
-            Ok(ImageGenerationResult {
+            return Ok(ImageGenerationResult {
                 success: false,
                 images: vec![],
+                outputs: vec![],
                 task_id,
-                error: Some(e),
+                error: Some(e.to_string()),
+                error_code: Some(e.kind().into()),
+                field_errors: vec![],
+                effective_size,
+                aspect_ratio,
+                seed,
             })
         }
-    }
+    };
+    
+    update_task_progress(&task_id, GenerationStage::Downloading, 90, "正在获取结果...");
+    let outputs = image_files::archive_images(&images).await;
+    
+    Ok(ImageGenerationResult {
+        success: true,
+        images,
+        outputs,
+        task_id,
+        error: None,
+        field_errors: vec![],
+        effective_size,
+        aspect_ratio,
+        seed,
+        error_code: None,
+    })
 }
 
-fn build_prompt_with_bindings(params: &ImageGenerationParams) -> String {
-    let mut final_prompt = params.prompt.clone();
-    
-    for binding in &params.character_bindings {
-        if let Some(ref_path) = &binding.reference_image_path {
-            if !ref_path.is_empty() {
-                final_prompt = format!(
-                    "{} [{}: {}]",
-                    final_prompt,
-                    binding.character_name,
-                    ref_path
-                );
-            }
-        }
+/// Calls the provider, retrying rate limits and transient failures under `config.retry`.
+async fn generate_with_retry(
+    task_id: &str,
+    provider: &dyn ImageProvider,
+    config: &ModelConfig,
+    request: &GenerationRequest,
+) -> Result<Vec<String>, ProviderError> {
+    let mut attempt = 1;
+    loop {
+        let error = match provider.generate(config, request).await {
+            Ok(images) => return Ok(images),
+            Err(e) => e,
+        };
+        
+        let Some(delay) = config.retry.delay_after(attempt, &error) else {
+            return Err(error);
+        };
+        eprintln!("{} attempt {} failed, retrying in {:?}: {}", provider.id(), attempt, delay, error);
+        
+        update_task_progress(
+            task_id,
+            GenerationStage::CallingModel,
+            30,
+            &format!(
+                "请求失败，{}秒后重试 ({}/{})...",
+                delay.as_secs_f64().ceil(),
+                attempt,
+                config.retry.max_attempts - 1
+            ),
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
     }
-    
-    final_prompt
 }
 
-async fn call_banana_pro_api(
-    config: &ModelConfig,
-    prompt: &str,
-    width: u32,
-    height: u32,
-    count: u32,
-    images: Option<Vec<String>>,
-) -> Result<Vec<String>, String> {
-    let client = reqwest::Client::new();
-    
-    let aspect_ratio = calculate_aspect_ratio(width, height);
-    
-    let image_size = match width {
-        0..=576 => "256k",
-        577..=1024 => "1K",
-        1025..=2048 => "2K",
-        _ => "4K",
+fn finish_task(task_id: &str, result: &ImageGenerationResult) {
+    let (stage, progress, message) = if result.success {
+        (GenerationStage::Done, 100, "生成完成".to_string())
+    } else {
+        (
+            GenerationStage::Failed,
+            0,
+            result.error.clone().unwrap_or_else(|| "生成失败".to_string()),
+        )
     };
     
-    // Build parts with optional reference images
-    let mut parts: Vec<serde_json::Value> = Vec::new();
-    
-    // Add reference images if any
-    if let Some(ref imgs) = images {
-        for img in imgs {
-            let img_data = if img.starts_with("data:") {
-                img.split(',').nth(1).unwrap_or(img.as_str()).to_string()
-            } else {
-                img.clone()
-            };
-            parts.push(serde_json::json!({
-                "inlineData": {
-                    "mimeType": "image/png",
-                    "data": img_data
-                }
-            }));
-        }
-    }
-    
-    // Add text prompt
-    parts.push(serde_json::json!({ "text": prompt }));
-    
-    let contents = vec![serde_json::json!({
-        "role": "user",
-        "parts": parts
-    })];
-    
-    let request_body = serde_json::json!({
-        "contents": contents,
-        "generationConfig": {
-            "responseModalities": ["TEXT", "IMAGE"],
-            "imageConfig": {
-                "aspectRatio": aspect_ratio,
-                "imageSize": image_size
+    if let Ok(mut tasks) = GENERATION_TASKS.lock() {
+        if let Some(task) = tasks.get_mut(task_id) {
+            if task.finished_at.is_some() {
+                // Cancelled while the last step was running; the cancel already reported it
+                return;
             }
+            task.status = stage.as_str().to_string();
+            task.progress = progress;
+            task.message = Some(message.clone());
+            task.result = Some(result.clone());
+            task.finished_at = Some(Instant::now());
+            task.abort = None;
         }
-    });
-
-    eprintln!("Banana Pro API request body: {:?}", request_body);
-    
-    let url = format!(
-        "{}/v1beta/models/gemini-3.1-flash-image-preview:generateContent?key={}",
-        config.base_url, config.api_key
-    );
-    eprintln!("Banana Pro API URL: {}", url);
-    
-    let response = client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| format!("请求失败: {}", e))?;
-    
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response.text().await.unwrap_or_default();
-        eprintln!("Banana Pro API error response: {}", text);
-        return Err(format!("API错误 {}: {}", status, text));
-    }
-    
-    let data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-    
-    eprintln!("Banana Pro API response: {:?}", data);
-    
-    // Check for error in response
-    if let Some(error) = data.get("error") {
-        return Err(format!("API返回错误: {:?}", error));
-    }
-    
-    let images: Vec<String> = data["candidates"]
-        .as_array()
-        .and_then(|arr| {
-            Some(arr.iter()
-                .filter_map(|candidate| {
-                    candidate["content"]["parts"]
-                        .as_array()
-                        .and_then(|parts| {
-                            parts.iter().find_map(|part| {
-                                part["inlineData"]["data"].as_str().map(|s| {
-                                    let mime = part["inlineData"]["mimeType"].as_str().unwrap_or("image/png");
-                                    format!("data:{};base64,{}", mime, s)
-                                })
-                            })
-                        })
-                })
-                .collect::<Vec<_>>())
-        })
-        .unwrap_or_default();
-    
-    if images.is_empty() {
-        return Err("未生成图片".to_string());
     }
     
-    Ok(images)
-}
-
-fn calculate_aspect_ratio(width: u32, height: u32) -> String {
-    let gcd = gcd_u32(width, height);
-    let w = width / gcd;
-    let h = height / gcd;
-    format!("{}:{}", w, h)
+    publish_event(GenerationEvent {
+        task_id: task_id.to_string(),
+        stage,
+        progress,
+        message: Some(message),
+        queue_position: None,
+        result: Some(result.clone()),
+    });
 }
 
-fn gcd_u32(a: u32, b: u32) -> u32 {
-    if b == 0 {
-        a
-    } else {
-        gcd_u32(b, a % b)
-    }
+fn find_provider(model: &str) -> AppResult<std::sync::Arc<dyn ImageProvider>> {
+    PROVIDER_REGISTRY.get(model).ok_or_else(|| {
+        AppError::new(ErrorCode::UnsupportedModel, format!("不支持的模型: {}", model))
+    })
 }
 
-async fn call_seedream_api(
-    config: &ModelConfig,
-    prompt: &str,
-    size: Option<String>,
-    sequential_image_generation: Option<String>,
-    response_format: Option<String>,
-    watermark: Option<bool>,
-    images: Option<Vec<String>>,
-) -> Result<Vec<String>, String> {
-    let client = reqwest::Client::new();
-    
-    let mut request_body = serde_json::json!({
-        "model": "doubao-seedream-4-0-250828",
-        "prompt": prompt,
-    });
-    
-    if let Some(s) = size {
-        request_body["size"] = serde_json::json!(s);
-    } else {
-        request_body["size"] = serde_json::json!("2K");
-    }
-    
-    if let Some(s) = sequential_image_generation {
-        request_body["sequential_image_generation"] = serde_json::json!(s);
-        if s == "auto" {
-            request_body["sequential_image_generation_options"] = serde_json::json!({
-                "max_images": 3
-            });
-        }
-    } else {
-        request_body["sequential_image_generation"] = serde_json::json!("auto");
-        request_body["sequential_image_generation_options"] = serde_json::json!({
-            "max_images": 3
-        });
-    }
+/// Returns the API config held in memory, falling back to the encrypted file on disk.
+pub(crate) fn current_api_config() -> AppResult<Option<ApiConfig>> {
+    let api_config = {
+        let config = API_CONFIG.lock()?;
+        config.clone()
+    };
     
-    if let Some(rf) = response_format {
-        request_body["response_format"] = serde_json::json!(rf);
+    if api_config.is_some() {
+        return Ok(api_config);
     }
     
-    if let Some(w) = watermark {
-        request_body["watermark"] = serde_json::json!(w);
-    } else {
-        request_body["watermark"] = serde_json::json!(false);
+    let config_path = get_config_path();
+    eprintln!("Config path: {:?}", config_path);
+    if !config_path.exists() {
+        return Ok(None);
     }
     
-    if let Some(imgs) = images {
-        if !imgs.is_empty() {
-            // Seedream API requires proper base64 data URIs
-            let formatted_images: Vec<String> = imgs.iter().map(|b64| {
-                if b64.starts_with("data:") {
-                    b64.clone()
-                } else {
-                    format!("data:image/png;base64,{}", b64)
-                }
-            }).collect();
-            request_body["image"] = serde_json::json!(formatted_images);
+    eprintln!("Config file exists, attempting to decrypt...");
+    let encrypted = fs::read(&config_path)?;
+    let key = get_or_create_key()?;
+    let decrypted = decrypt_data(&encrypted, &key)?;
+    let json = String::from_utf8(decrypted).map_err(crypto_error)?;
+    let loaded: ApiConfig = serde_json::from_str(&json)?;
+    let mut api_config = API_CONFIG.lock()?;
+    *api_config = Some(loaded.clone());
+    Ok(Some(loaded))
+}
+
+/// Picks the saved settings for `provider`, or its defaults when none were saved.
+fn resolve_model_config(provider: &dyn ImageProvider) -> AppResult<ModelConfig> {
+    match current_api_config()? {
+        Some(config) => Ok(config
+            .get(provider.id())
+            .cloned()
+            .unwrap_or_else(|| provider.default_config())),
+        None if provider.requires_api_key() => {
+            Err(AppError::new(ErrorCode::ConfigMissing, "请先配置API"))
         }
+        None => Ok(provider.default_config()),
     }
+}
+
+fn build_prompt_with_bindings(params: &ImageGenerationParams) -> String {
+    let mut final_prompt = params.prompt.clone();
     
-    eprintln!("Seedream API request: {:?}", request_body);
-    
-    let response = client
-        .post(&format!("{}/v1/images/generations", config.base_url))
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| format!("请求失败: {}", e))?;
-    
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response.text().await.unwrap_or_default();
-        return Err(format!("API错误 {}: {}", status, text));
+    for binding in &params.character_bindings {
+        if let Some(ref_path) = &binding.reference_image_path {
+            if !ref_path.is_empty() {
+                final_prompt = format!(
+                    "{} [{}: {}]",
+                    final_prompt,
+                    binding.character_name,
+                    ref_path
+                );
+            }
+        }
     }
     
-    let data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-    
-    eprintln!("Seedream API response: {:?}", data);
-    
-    let images: Vec<String> = data["data"]
-        .as_array()
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|item| item["url"].as_str().map(|s| s.to_string()))
-                .collect()
-        })
-        .unwrap_or_default();
-    
-    Ok(images)
+    final_prompt
 }
 
-fn update_task_progress(task_id: &str, status: &str, progress: u32, message: &str) {
+fn update_task_progress(task_id: &str, stage: GenerationStage, progress: u32, message: &str) {
     if let Ok(mut tasks) = GENERATION_TASKS.lock() {
         if let Some(task) = tasks.get_mut(task_id) {
-            task.status = status.to_string();
+            if task.finished_at.is_some() {
+                return;
+            }
+            task.status = stage.as_str().to_string();
             task.progress = progress;
             task.message = Some(message.to_string());
         }
     }
+    
+    publish_event(GenerationEvent {
+        task_id: task_id.to_string(),
+        stage,
+        progress,
+        message: Some(message.to_string()),
+        queue_position: None,
+        result: None,
+    });
+}
+
+fn publish_event(event: GenerationEvent) {
+    // No subscribers is fine: nobody is listening yet
+    let _ = GENERATION_EVENTS.send(event);
+}
+
+/// Subscribes to progress events of every generation task.
+pub fn subscribe_generation_events() -> broadcast::Receiver<GenerationEvent> {
+    GENERATION_EVENTS.subscribe()
 }
 
 #[tauri::command]
-pub fn get_generation_progress(task_id: String) -> Result<GenerationProgress, String> {
-    let tasks = GENERATION_TASKS.lock().map_err(|e| e.to_string())?;
+pub fn get_generation_progress(task_id: String) -> AppResult<GenerationProgress> {
+    let tasks = GENERATION_TASKS.lock()?;
     
     if let Some(task) = tasks.get(&task_id) {
         Ok(GenerationProgress {
@@ -667,85 +1055,36 @@ pub fn get_generation_progress(task_id: String) -> Result<GenerationProgress, St
             status: task.status.clone(),
             progress: task.progress,
             message: task.message.clone(),
+            queue_position: task.queue_position,
+            result: task.result.clone(),
         })
     } else {
-        Err("任务不存在".to_string())
+        Err(AppError::not_found("任务不存在"))
     }
 }
 
 #[tauri::command]
-pub async fn test_api_connection(model: String, base_url: Option<String>, api_key: Option<String>) -> Result<bool, String> {
-    let provided_config = base_url.is_some() && api_key.is_some();
-    
-    let (model_config, _) = if provided_config {
-        (
-            ModelConfig {
-                base_url: base_url.unwrap(),
-                api_key: api_key.unwrap(),
-            },
-            true
-        )
-    } else {
-        let api_config = {
-            let config = API_CONFIG.lock().map_err(|e| e.to_string())?;
-            config.clone()
-        };
-        
-        let config = match api_config {
-            Some(c) => c,
-            None => {
-                let config_path = get_config_path();
-                if config_path.exists() {
-                    let encrypted = fs::read(&config_path).map_err(|e| e.to_string())?;
-                    let key = get_or_create_key()?;
-                    let decrypted = decrypt_data(&encrypted, &key)?;
-                    let json = String::from_utf8(decrypted).map_err(|e| e.to_string())?;
-                    let loaded: ApiConfig = serde_json::from_str(&json).map_err(|e| e.to_string())?;
-                    let mut api_config = API_CONFIG.lock().map_err(|e| e.to_string())?;
-                    *api_config = Some(loaded.clone());
-                    loaded
-                } else {
-                    return Err("请先配置API".to_string());
-                }
-            }
-        };
-        
-        let model_config = match model.as_str() {
-            "seedream" => config.seedream,
-            "banana_pro" => config.banana_pro,
-            _ => return Err("不支持的模型".to_string()),
-        };
-        
-        if model_config.api_key.is_empty() {
-            return Err("API Key未配置".to_string());
+pub async fn test_api_connection(model: String, base_url: Option<String>, api_key: Option<String>) -> AppResult<bool> {
+    let provider = find_provider(&model)?;
+    
+    let model_config = match (base_url, api_key) {
+        (Some(base_url), Some(api_key)) => {
+            // Keep the saved provider options; only the endpoint and key are being tried out.
+            let saved = resolve_model_config(provider.as_ref())
+                .unwrap_or_else(|_| provider.default_config());
+            ModelConfig { base_url, api_key, ..saved }
         }
-        
-        (model_config, false)
+        _ => resolve_model_config(provider.as_ref())?,
     };
     
-    if model_config.api_key.is_empty() {
-        return Err("API Key未配置".to_string());
+    if provider.requires_api_key() && model_config.api_key.is_empty() {
+        return Err(AppError::new(ErrorCode::ApiKeyMissing, "API Key未配置"));
     }
     
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .build()
-        .map_err(|e| e.to_string())?;
-    
-    let test_url = format!("{}/v1/models", model_config.base_url);
-    
-    let response = client
-        .get(&test_url)
-        .header("Authorization", format!("Bearer {}", model_config.api_key))
-        .send()
+    provider
+        .test_connection(&model_config)
         .await
-        .map_err(|e| format!("连接失败: {}", e))?;
-    
-    if response.status().is_success() || response.status().as_u16() == 401 {
-        Ok(true)
-    } else {
-        Err(format!("API返回错误: {}", response.status()))
-    }
+        .map_err(|e| AppError::new(ErrorCode::ProviderFailed, e))
 }
 
 pub fn load_config_from_file() {
@@ -775,3 +1114,246 @@ pub fn load_config_from_file() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::providers::{ProviderCapabilities, ProviderErrorKind};
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    fn mock_params(prompt: &str) -> ImageGenerationParams {
+        ImageGenerationParams {
+            model: "mock".to_string(),
+            prompt: prompt.to_string(),
+            character_bindings: vec![],
+            width: 1024,
+            height: 1024,
+            count: 2,
+            quality: "standard".to_string(),
+            size: Some("640x480".to_string()),
+            sequential_image_generation: None,
+            response_format: None,
+            watermark: None,
+            images: None,
+            negative_prompt: None,
+            seed: None,
+            aspect_ratio: None,
+            priority: Default::default(),
+        }
+    }
+
+    fn decode_data_uri(uri: &str) -> Vec<u8> {
+        let b64 = uri.split(',').nth(1).expect("data URI");
+        STANDARD.decode(b64).expect("base64")
+    }
+
+    #[test]
+    fn test_tests_use_a_temp_data_dir() {
+        if std::env::var_os(DATA_DIR_ENV).is_none() {
+            assert!(get_app_data_dir().starts_with(std::env::temp_dir()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_generation_end_to_end() {
+        let result = generate_image_and_wait(mock_params("在森林里@小明 正在跑步")).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.images.len(), 2);
+        assert_ne!(result.images[0], result.images[1]);
+        // Inline results are archived too, without an original URL
+        assert_eq!(result.outputs.len(), 2);
+        assert!(result.outputs.iter().all(|o| o.url.is_none()));
+        assert!(PathBuf::from(result.outputs[0].path.as_ref().unwrap()).exists());
+
+        let out_dir = std::env::temp_dir().join(format!("huiben_mock_{}", result.task_id));
+        fs::create_dir_all(&out_dir).unwrap();
+        for (i, uri) in result.images.iter().enumerate() {
+            let path = out_dir.join(format!("{}.png", i));
+            fs::write(&path, decode_data_uri(uri)).unwrap();
+
+            let saved = image::open(&path).unwrap();
+            assert_eq!((saved.width(), saved.height()), (640, 480));
+        }
+        fs::remove_dir_all(&out_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_generate_image_returns_task_and_emits_events() {
+        let mut events = subscribe_generation_events();
+        let started = generate_image(mock_params("清晨的湖边")).await.unwrap();
+        assert_eq!(started.stage, GenerationStage::Queued);
+
+        let mut stages = Vec::new();
+        let result = loop {
+            let event = events.recv().await.unwrap();
+            if event.task_id != started.task_id {
+                continue;
+            }
+            stages.push(event.stage);
+            if let Some(result) = event.result {
+                break result;
+            }
+        };
+
+        assert!(result.success);
+        assert_eq!(
+            stages,
+            vec![
+                GenerationStage::Queued,
+                GenerationStage::UploadingRefs,
+                GenerationStage::CallingModel,
+                GenerationStage::Downloading,
+                GenerationStage::Done,
+            ]
+        );
+
+        // The finished task is still queryable
+        let progress = get_generation_progress(started.task_id).unwrap();
+        assert_eq!(progress.status, "done");
+        assert!(progress.result.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_task_and_discards_late_result() {
+        let task_id = create_generation_task().unwrap();
+        let in_flight = tokio::spawn(std::future::pending::<()>());
+        GENERATION_TASKS.lock().unwrap().get_mut(&task_id).unwrap().abort =
+            Some(in_flight.abort_handle());
+
+        let mut events = subscribe_generation_events();
+        assert!(cancel_generation(task_id.clone()).unwrap());
+        assert!(in_flight.await.unwrap_err().is_cancelled());
+
+        let event = loop {
+            let event = events.recv().await.unwrap();
+            if event.task_id == task_id {
+                break event;
+            }
+        };
+        assert_eq!(event.stage, GenerationStage::Cancelled);
+        assert!(!event.result.unwrap().success);
+
+        // A provider that returns right after the cancel must not revive the task
+        let mut late = cancelled_result(&task_id);
+        late.success = true;
+        late.images = vec!["data:image/png;base64,AAAA".to_string()];
+        finish_task(&task_id, &late);
+
+        let progress = get_generation_progress(task_id.clone()).unwrap();
+        assert_eq!(progress.status, "cancelled");
+        assert!(progress.result.unwrap().images.is_empty());
+        assert!(cancel_generation(task_id).is_err());
+    }
+
+    /// Fails with each queued error in turn, then succeeds.
+    struct FlakyProvider {
+        failures: Mutex<Vec<ProviderError>>,
+        calls: AtomicU64,
+    }
+
+    #[async_trait::async_trait]
+    impl ImageProvider for FlakyProvider {
+        fn id(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn display_name(&self) -> &'static str {
+            "Flaky"
+        }
+
+        fn default_config(&self) -> ModelConfig {
+            ModelConfig::default()
+        }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            Default::default()
+        }
+
+        async fn generate(
+            &self,
+            _config: &ModelConfig,
+            _request: &GenerationRequest,
+        ) -> Result<Vec<String>, ProviderError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            match self.failures.lock().unwrap().pop() {
+                Some(e) => Err(e),
+                None => Ok(vec!["data:image/png;base64,AAAA".to_string()]),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures_only() {
+        let config = ModelConfig {
+            retry: RetryPolicy {
+                max_attempts: 3,
+                initial_delay_ms: 1,
+                max_delay_ms: 5,
+                multiplier: 2.0,
+            },
+            ..Default::default()
+        };
+        let request = GenerationRequest {
+            prompt: "test".to_string(),
+            params: mock_params("test"),
+            images: None,
+        };
+
+        let flaky = FlakyProvider {
+            failures: Mutex::new(vec![
+                ProviderError::Transient("502".to_string()),
+                ProviderError::RateLimited { retry_after: None, message: "429".to_string() },
+            ]),
+            calls: AtomicU64::new(0),
+        };
+        assert!(generate_with_retry("retry_test", &flaky, &config, &request).await.is_ok());
+        assert_eq!(flaky.calls.load(Ordering::Relaxed), 3);
+
+        let blocked = FlakyProvider {
+            failures: Mutex::new(vec![ProviderError::ContentPolicy("blocked".to_string())]),
+            calls: AtomicU64::new(0),
+        };
+        let err = generate_with_retry("retry_test", &blocked, &config, &request).await.unwrap_err();
+        assert_eq!(err.kind(), ProviderErrorKind::ContentPolicy);
+        assert_eq!(blocked.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failures_carry_error_codes() {
+        let result = generate_image_and_wait(mock_params("  ")).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.error_code, Some(ErrorCode::ValidationFailed));
+        assert_eq!(result.field_errors[0].field, "prompt");
+
+        let mut params = mock_params("小狐狸");
+        params.model = "no_such_model".to_string();
+        let result = generate_image_and_wait(params).await.unwrap();
+        assert_eq!(result.error_code, Some(ErrorCode::UnsupportedModel));
+
+        let err = get_generation_progress("task_missing".to_string()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_mock_generation_is_deterministic_per_seed() {
+        let seeded = |prompt: &str, seed: Option<i64>| {
+            let mut params = mock_params(prompt);
+            params.seed = seed;
+            generate_image_and_wait(params)
+        };
+        let first = seeded("A beautiful sunset", Some(7)).await.unwrap();
+        let second = seeded("A beautiful sunset", Some(7)).await.unwrap();
+        let other = seeded("A rainy street", Some(7)).await.unwrap();
+
+        assert_eq!(first.seed, Some(7));
+        assert_eq!(first.images, second.images);
+        assert_ne!(first.images, other.images);
+
+        // Without a seed one is generated, returned, and reproduces the images
+        let random = seeded("A beautiful sunset", None).await.unwrap();
+        let seed = random.seed.expect("generated seed");
+        let again = seeded("A beautiful sunset", Some(seed)).await.unwrap();
+        assert_eq!(random.images, again.images);
+    }
+}
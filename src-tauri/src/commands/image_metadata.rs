@@ -0,0 +1,835 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+use super::character_binding::get_bindings_for_prompt;
+use super::generation_queue::JobPriority;
+use super::image_files::fetch_image_bytes;
+use super::image_generator::{current_generation_config, CharacterBindingInfo, ImageGenerationParams};
+use super::prompt_parser::{parse_prompt_internal, ParsedPrompt, PromptSegment};
+use super::providers::PROVIDER_REGISTRY;
+use crate::error::{AppError, AppResult};
+
+/// PNG text keyword / XMP property holding `ImageMetadata` as JSON.
+pub const METADATA_KEYWORD: &str = "huiben";
+/// PNG text keyword Stable Diffusion WebUI (A1111) reads and writes.
+pub const A1111_KEYWORD: &str = "parameters";
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+/// Largest payload of a JPEG marker segment (the length field counts itself).
+const MAX_JPEG_SEGMENT: usize = 65533;
+
+/// How an image was made, embedded into saved files.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageMetadata {
+    pub prompt: String,
+    #[serde(alias = "negativePrompt")]
+    pub negative_prompt: Option<String>,
+    /// Filled from `prompt` when empty.
+    pub segments: Vec<PromptSegment>,
+    pub model: String,
+    pub width: u32,
+    pub height: u32,
+    pub seed: Option<i64>,
+    #[serde(alias = "characterBindings")]
+    pub character_bindings: Vec<CharacterBindingInfo>,
+    /// RFC 3339; the save time when empty.
+    #[serde(alias = "createdAt")]
+    pub created_at: String,
+    #[serde(alias = "taskId")]
+    pub task_id: Option<String>,
+}
+
+impl ImageMetadata {
+    pub fn from_params(params: &ImageGenerationParams) -> Self {
+        ImageMetadata {
+            prompt: params.prompt.clone(),
+            negative_prompt: params.negative_prompt.clone(),
+            segments: Vec::new(),
+            model: params.model.clone(),
+            width: params.width,
+            height: params.height,
+            seed: params.seed,
+            character_bindings: params.character_bindings.clone(),
+            created_at: String::new(),
+            task_id: None,
+        }
+        .completed()
+    }
+
+    /// Fills in what the caller may leave out: parsed segments and the timestamp.
+    pub fn completed(mut self) -> Self {
+        if self.segments.is_empty() {
+            if let Ok(parsed) = parse_prompt_internal(&self.prompt) {
+                self.segments = parsed.segments;
+            }
+        }
+        if self.created_at.is_empty() {
+            self.created_at = chrono::Local::now().to_rfc3339();
+        }
+        self
+    }
+
+    /// The A1111 "parameters" text: prompt, optional `Negative prompt:` line, then one line of
+    /// `Key: value` pairs. A1111 only treats the last line as settings when it has at least
+    /// three pairs, hence the `Generator` entry.
+    pub fn a1111_parameters(&self) -> String {
+        let mut text = self.prompt.trim().to_string();
+        if let Some(negative) = self.negative_prompt.as_deref().filter(|n| !n.trim().is_empty()) {
+            text.push_str(&format!("\nNegative prompt: {}", negative.trim()));
+        }
+
+        let mut settings = Vec::new();
+        if let Some(seed) = self.seed {
+            settings.push(format!("Seed: {}", seed));
+        }
+        if self.width > 0 && self.height > 0 {
+            settings.push(format!("Size: {}x{}", self.width, self.height));
+        }
+        if !self.model.is_empty() {
+            settings.push(format!("Model: {}", a1111_value(&self.model)));
+        }
+        let characters: Vec<&str> = self.character_bindings.iter().map(|b| b.character_name.as_str()).collect();
+        if !characters.is_empty() {
+            settings.push(format!("Characters: {}", a1111_value(&characters.join(", "))));
+        }
+        settings.push("Generator: xuanchen-huiben".to_string());
+        text.push('\n');
+        text.push_str(&settings.join(", "));
+        text
+    }
+}
+
+/// Quotes values containing the pair separators, the way A1111 does.
+fn a1111_value(value: &str) -> String {
+    if value.contains([',', ':', '\n']) {
+        serde_json::to_string(value).unwrap_or_default()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Embeds `metadata` into PNG or JPEG bytes. Other formats are returned unchanged.
+pub fn embed_metadata(bytes: Vec<u8>, metadata: &ImageMetadata) -> AppResult<Vec<u8>> {
+    match image::guess_format(&bytes) {
+        Ok(image::ImageFormat::Png) => embed_png(&bytes, metadata),
+        Ok(image::ImageFormat::Jpeg) => embed_jpeg(&bytes, metadata),
+        _ => Ok(bytes),
+    }
+}
+
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    chunk.extend_from_slice(&crc.finalize().to_be_bytes());
+    chunk
+}
+
+/// `tEXt` when the text is Latin-1, else uncompressed UTF-8 `iTXt` (what A1111 does for
+/// non-English prompts).
+fn png_text_chunk(keyword: &str, text: &str) -> Vec<u8> {
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    if text.chars().all(|c| (c as u32) < 256) {
+        data.extend(text.chars().map(|c| c as u8));
+        png_chunk(b"tEXt", &data)
+    } else {
+        // Compression flag and method, then empty language tag and translated keyword
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(text.as_bytes());
+        png_chunk(b"iTXt", &data)
+    }
+}
+
+/// Splits a PNG into `(type, data)` chunks.
+fn png_chunks(bytes: &[u8]) -> AppResult<Vec<([u8; 4], &[u8])>> {
+    let invalid = || AppError::invalid_input("PNG 文件已损坏");
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err(invalid());
+    }
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 12 <= bytes.len() {
+        let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = bytes[pos + 4..pos + 8].try_into().unwrap();
+        let end = pos + 8 + len;
+        if end + 4 > bytes.len() {
+            return Err(invalid());
+        }
+        chunks.push((kind, &bytes[pos + 8..end]));
+        pos = end + 4;
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+/// Uncompressed `tEXt`/`iTXt` entries of a PNG as `(keyword, text)`.
+pub fn png_text_entries(bytes: &[u8]) -> AppResult<Vec<(String, String)>> {
+    let mut entries = Vec::new();
+    for (kind, data) in png_chunks(bytes)? {
+        let Some(nul) = data.iter().position(|b| *b == 0) else {
+            continue;
+        };
+        let keyword = String::from_utf8_lossy(&data[..nul]).to_string();
+        let rest = &data[nul + 1..];
+        match &kind {
+            b"tEXt" => entries.push((keyword, rest.iter().map(|b| *b as char).collect())),
+            b"iTXt" if rest.len() >= 2 && rest[0] == 0 => {
+                // Skip the language tag and translated keyword
+                let mut fields = rest[2..].splitn(3, |b| *b == 0);
+                if let (Some(_), Some(_), Some(text)) = (fields.next(), fields.next(), fields.next()) {
+                    entries.push((keyword, String::from_utf8_lossy(text).to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+fn is_our_text_chunk(kind: &[u8; 4], data: &[u8]) -> bool {
+    if !matches!(kind, b"tEXt" | b"iTXt" | b"zTXt") {
+        return false;
+    }
+    let keyword = data.split(|b| *b == 0).next().unwrap_or_default();
+    keyword == METADATA_KEYWORD.as_bytes() || keyword == A1111_KEYWORD.as_bytes()
+}
+
+/// Rewrites the PNG with our text chunks right after `IHDR`, replacing earlier ones.
+fn embed_png(bytes: &[u8], metadata: &ImageMetadata) -> AppResult<Vec<u8>> {
+    let json = serde_json::to_string(metadata)?;
+    let mut out = Vec::with_capacity(bytes.len() + json.len() * 2 + 256);
+    out.extend_from_slice(PNG_SIGNATURE);
+    for (kind, data) in png_chunks(bytes)? {
+        if is_our_text_chunk(&kind, data) {
+            continue;
+        }
+        out.extend_from_slice(&png_chunk(&kind, data));
+        if &kind == b"IHDR" {
+            out.extend_from_slice(&png_text_chunk(A1111_KEYWORD, &metadata.a1111_parameters()));
+            out.extend_from_slice(&png_text_chunk(METADATA_KEYWORD, &json));
+        }
+    }
+    Ok(out)
+}
+
+/// `(marker, payload)` header segments and the untouched rest (scan data and trailer).
+type JpegSegments<'a> = (Vec<(u8, &'a [u8])>, &'a [u8]);
+
+/// Splits a JPEG at start-of-scan into its header segments and the rest.
+fn jpeg_segments(bytes: &[u8]) -> AppResult<JpegSegments<'_>> {
+    let invalid = || AppError::invalid_input("JPEG 文件已损坏");
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return Err(invalid());
+    }
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        if pos + 4 > bytes.len() || bytes[pos] != 0xFF {
+            return Err(invalid());
+        }
+        let marker = bytes[pos + 1];
+        if marker == 0xDA {
+            return Ok((segments, &bytes[pos..]));
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        if len < 2 || pos + 2 + len > bytes.len() {
+            return Err(invalid());
+        }
+        segments.push((marker, &bytes[pos + 4..pos + 2 + len]));
+        pos += 2 + len;
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn xmp_packet(metadata: &ImageMetadata, json: &str) -> String {
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:huiben=\"urn:huiben:xmp:1.0#\">\n\
+         <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n\
+         <huiben:{}>{}</huiben:{}>\n\
+         </rdf:Description>\n\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        xml_escape(&metadata.a1111_parameters()),
+        METADATA_KEYWORD,
+        xml_escape(json),
+        METADATA_KEYWORD,
+    )
+}
+
+/// Minimal big-endian EXIF block holding only `UserComment`, which is where A1111 keeps the
+/// parameters of JPEG images.
+fn exif_user_comment(text: &str) -> Vec<u8> {
+    let mut comment = b"UNICODE\0".to_vec();
+    comment.extend(text.encode_utf16().flat_map(|u| u.to_be_bytes()));
+
+    let mut tiff = b"MM\0\x2a".to_vec();
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    // IFD0: one entry pointing at the Exif IFD at offset 26
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&0x8769u16.to_be_bytes());
+    tiff.extend_from_slice(&4u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&26u32.to_be_bytes());
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    // Exif IFD: UserComment (UNDEFINED) with its data at offset 44
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&0x9286u16.to_be_bytes());
+    tiff.extend_from_slice(&7u16.to_be_bytes());
+    tiff.extend_from_slice(&(comment.len() as u32).to_be_bytes());
+    tiff.extend_from_slice(&44u32.to_be_bytes());
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff.extend_from_slice(&comment);
+
+    let mut exif = EXIF_HEADER.to_vec();
+    exif.extend_from_slice(&tiff);
+    exif
+}
+
+fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(payload);
+    segment
+}
+
+/// Rewrites the JPEG with EXIF `UserComment` and XMP segments after the JFIF header,
+/// replacing existing EXIF/XMP. A block too large for one segment is left out.
+fn embed_jpeg(bytes: &[u8], metadata: &ImageMetadata) -> AppResult<Vec<u8>> {
+    let json = serde_json::to_string(metadata)?;
+    let exif = exif_user_comment(&metadata.a1111_parameters());
+    let mut xmp = XMP_NAMESPACE.to_vec();
+    xmp.extend_from_slice(xmp_packet(metadata, &json).as_bytes());
+
+    let (segments, scan) = jpeg_segments(bytes)?;
+    let mut out = Vec::with_capacity(bytes.len() + exif.len() + xmp.len() + 8);
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    let mut inserted = false;
+    for (marker, payload) in segments {
+        let is_ours = marker == 0xE1 && (payload.starts_with(EXIF_HEADER) || payload.starts_with(XMP_NAMESPACE));
+        if is_ours {
+            continue;
+        }
+        // JFIF/JFXX (APP0) must stay first
+        if !inserted && marker != 0xE0 {
+            for block in [&exif, &xmp] {
+                if block.len() <= MAX_JPEG_SEGMENT {
+                    out.extend_from_slice(&jpeg_segment(0xE1, block));
+                } else {
+                    eprintln!("Image metadata too large for a JPEG segment, skipped");
+                }
+            }
+            inserted = true;
+        }
+        out.extend_from_slice(&jpeg_segment(marker, payload));
+    }
+    out.extend_from_slice(scan);
+    Ok(out)
+}
+
+/// Where imported generation info came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataSource {
+    /// Written by this app (`huiben` chunk / XMP property).
+    Huiben,
+    /// Stable Diffusion WebUI `parameters`.
+    A1111,
+    /// ComfyUI `prompt` graph or `workflow`.
+    #[serde(rename = "comfyui")]
+    ComfyUi,
+}
+
+/// Generation info read back from an image, ready to regenerate or tweak.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedImage {
+    pub source: MetadataSource,
+    pub metadata: ImageMetadata,
+    /// The current generation defaults with the imported prompt, size, seed and model
+    /// (when it is one of ours) applied.
+    pub params: ImageGenerationParams,
+    pub parsed: ParsedPrompt,
+}
+
+/// A1111 settings line: `Key: value` pairs, values optionally JSON-quoted.
+static A1111_PAIR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\s*(\w[\w \-/]*):\s*("(?:\\.|[^\\"])+"|[^,]*)(?:,|$)"#).unwrap());
+
+/// Reads our own metadata, or A1111/ComfyUI parameters, from PNG or JPEG bytes.
+pub fn read_metadata(bytes: &[u8]) -> AppResult<(MetadataSource, ImageMetadata)> {
+    let found = match image::guess_format(bytes) {
+        Ok(image::ImageFormat::Png) => read_png_metadata(bytes)?,
+        Ok(image::ImageFormat::Jpeg) => read_jpeg_metadata(bytes)?,
+        _ => return Err(AppError::invalid_input("仅支持读取 PNG 或 JPEG 图片的生成信息")),
+    };
+    found
+        .map(|(source, metadata)| (source, metadata.completed()))
+        .ok_or_else(|| AppError::not_found("图片中没有可识别的生成信息"))
+}
+
+fn read_png_metadata(bytes: &[u8]) -> AppResult<Option<(MetadataSource, ImageMetadata)>> {
+    let entries = png_text_entries(bytes)?;
+    let text = |keyword: &str| entries.iter().find(|(k, _)| k == keyword).map(|(_, t)| t.as_str());
+
+    if let Some(json) = text(METADATA_KEYWORD) {
+        if let Ok(metadata) = serde_json::from_str(json) {
+            return Ok(Some((MetadataSource::Huiben, metadata)));
+        }
+    }
+    if let Some(parameters) = text(A1111_KEYWORD) {
+        return Ok(Some((MetadataSource::A1111, parse_a1111_parameters(parameters))));
+    }
+    let comfy = text("prompt")
+        .and_then(|json| serde_json::from_str(json).ok())
+        .and_then(|graph| comfy_prompt_metadata(&graph))
+        .or_else(|| {
+            text("workflow")
+                .and_then(|json| serde_json::from_str(json).ok())
+                .and_then(|workflow| comfy_workflow_metadata(&workflow))
+        });
+    Ok(comfy.map(|metadata| (MetadataSource::ComfyUi, metadata)))
+}
+
+fn read_jpeg_metadata(bytes: &[u8]) -> AppResult<Option<(MetadataSource, ImageMetadata)>> {
+    let (segments, _) = jpeg_segments(bytes)?;
+    let app1 = |prefix: &[u8]| {
+        segments
+            .iter()
+            .find(|(marker, payload)| *marker == 0xE1 && payload.starts_with(prefix))
+            .map(|(_, payload)| *payload)
+    };
+
+    let xmp = app1(XMP_NAMESPACE).map(|p| String::from_utf8_lossy(&p[XMP_NAMESPACE.len()..]).to_string());
+    if let Some(xmp) = &xmp {
+        let property = format!("huiben:{}", METADATA_KEYWORD);
+        if let Some(metadata) = xml_element_text(xmp, &property).and_then(|json| serde_json::from_str(&json).ok()) {
+            return Ok(Some((MetadataSource::Huiben, metadata)));
+        }
+    }
+    let parameters = app1(EXIF_HEADER)
+        .and_then(exif_user_comment_text)
+        .or_else(|| xmp.as_deref().and_then(|x| xml_element_text(x, "rdf:li")));
+    Ok(parameters.map(|p| (MetadataSource::A1111, parse_a1111_parameters(&p))))
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Unescaped text of the first `<name ...>text</name>` element.
+fn xml_element_text(xml: &str, name: &str) -> Option<String> {
+    let open = xml.find(&format!("<{}", name))?;
+    let start = open + xml[open..].find('>')? + 1;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(xml_unescape(&xml[start..end]))
+}
+
+/// `UserComment` of an EXIF block in either byte order.
+fn exif_user_comment_text(exif: &[u8]) -> Option<String> {
+    let tiff = &exif[EXIF_HEADER.len()..];
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| {
+        let b: [u8; 2] = tiff.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    };
+    let u32_at = |pos: usize| {
+        let b: [u8; 4] = tiff.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    };
+    // Position of the 12-byte entry for `tag` in the IFD at `ifd`
+    let entry = |ifd: usize, tag: u16| {
+        let count = u16_at(ifd)? as usize;
+        (0..count).map(|i| ifd + 2 + i * 12).find(|pos| u16_at(*pos) == Some(tag))
+    };
+
+    let exif_ifd = u32_at(entry(u32_at(4)? as usize, 0x8769)? + 8)? as usize;
+    let comment = entry(exif_ifd, 0x9286)?;
+    let len = u32_at(comment + 4)? as usize;
+    let data = if len <= 4 {
+        tiff.get(comment + 8..comment + 8 + len)?
+    } else {
+        let offset = u32_at(comment + 8)? as usize;
+        tiff.get(offset..offset.checked_add(len)?)?
+    };
+
+    let (prefix, text) = data.split_at(8.min(data.len()));
+    let text = match prefix {
+        b"UNICODE\0" => {
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|b| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).to_string(),
+    };
+    let text = text.trim_end_matches('\0').trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Parses A1111 "parameters" text the way the WebUI's "PNG Info" tab does: the last line
+/// holds the settings when it has at least three `Key: value` pairs.
+pub fn parse_a1111_parameters(text: &str) -> ImageMetadata {
+    let mut lines: Vec<&str> = text.trim().lines().collect();
+    let mut settings = Vec::new();
+    if let Some(last) = lines.last() {
+        let pairs: Vec<(String, String)> = A1111_PAIR
+            .captures_iter(last)
+            .map(|c| {
+                let value = c[2].trim();
+                let value = serde_json::from_str::<String>(value).unwrap_or_else(|_| value.to_string());
+                (c[1].trim().to_string(), value)
+            })
+            .collect();
+        if pairs.len() >= 3 {
+            settings = pairs;
+            lines.pop();
+        }
+    }
+
+    let mut metadata = ImageMetadata::default();
+    let mut prompt = Vec::new();
+    let mut negative: Option<Vec<&str>> = None;
+    for line in lines {
+        match (&mut negative, line.strip_prefix("Negative prompt:")) {
+            (None, Some(rest)) => negative = Some(vec![rest.trim_start()]),
+            (Some(negative), _) => negative.push(line),
+            (None, None) => prompt.push(line),
+        }
+    }
+    metadata.prompt = prompt.join("\n").trim().to_string();
+    metadata.negative_prompt = negative.map(|n| n.join("\n").trim().to_string()).filter(|n| !n.is_empty());
+
+    for (key, value) in settings {
+        match key.as_str() {
+            "Seed" => metadata.seed = value.parse().ok(),
+            "Size" => {
+                if let Some((w, h)) = value.split_once('x') {
+                    metadata.width = w.trim().parse().unwrap_or_default();
+                    metadata.height = h.trim().parse().unwrap_or_default();
+                }
+            }
+            "Model" => metadata.model = value,
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// Follows a ComfyUI API-graph link `[node_id, output]` to the node's inputs.
+fn comfy_node<'a>(graph: &'a Value, link: &Value) -> Option<&'a Value> {
+    let id = match link.get(0)? {
+        Value::String(id) => id.clone(),
+        other => other.to_string(),
+    };
+    graph.get(id)?.get("inputs")
+}
+
+/// Prompt text behind a conditioning link; reroutes and text nodes feeding `text` are followed.
+fn comfy_text(graph: &Value, link: &Value, depth: u32) -> Option<String> {
+    if depth > 8 {
+        return None;
+    }
+    let inputs = comfy_node(graph, link)?;
+    for key in ["text", "text_g", "prompt", "conditioning", "value"] {
+        match inputs.get(key) {
+            Some(Value::String(text)) => return Some(text.clone()),
+            Some(next @ Value::Array(_)) => {
+                if let Some(text) = comfy_text(graph, next, depth + 1) {
+                    return Some(text);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Reads the first sampler of a ComfyUI API-format graph (the PNG `prompt` chunk).
+fn comfy_prompt_metadata(graph: &Value) -> Option<ImageMetadata> {
+    let sampler = graph
+        .as_object()?
+        .values()
+        .filter_map(|node| node.get("inputs"))
+        .find(|inputs| inputs.get("positive").is_some())?;
+
+    let mut metadata = ImageMetadata {
+        prompt: comfy_text(graph, sampler.get("positive")?, 0)?,
+        negative_prompt: sampler
+            .get("negative")
+            .and_then(|link| comfy_text(graph, link, 0))
+            .filter(|n| !n.trim().is_empty()),
+        seed: sampler.get("seed").or_else(|| sampler.get("noise_seed")).and_then(Value::as_i64),
+        ..Default::default()
+    };
+    if let Some(latent) = sampler.get("latent_image").and_then(|link| comfy_node(graph, link)) {
+        metadata.width = latent.get("width").and_then(Value::as_u64).unwrap_or_default() as u32;
+        metadata.height = latent.get("height").and_then(Value::as_u64).unwrap_or_default() as u32;
+    }
+    // LoRA loaders sit between the sampler and the checkpoint
+    let mut model = sampler.get("model");
+    for _ in 0..8 {
+        let Some(inputs) = model.and_then(|link| comfy_node(graph, link)) else {
+            break;
+        };
+        if let Some(name) = ["ckpt_name", "unet_name"].iter().find_map(|k| inputs.get(*k)?.as_str()) {
+            metadata.model = name.to_string();
+            break;
+        }
+        model = inputs.get("model");
+    }
+    Some(metadata)
+}
+
+/// Fallback for images carrying only the editor `workflow`: nodes with positional
+/// `widgets_values`, connected through the `links` table.
+fn comfy_workflow_metadata(workflow: &Value) -> Option<ImageMetadata> {
+    let nodes = workflow.get("nodes")?.as_array()?;
+    let links = workflow.get("links").and_then(Value::as_array);
+    let widget = |node: &Value, index: usize| node.get("widgets_values")?.get(index).cloned();
+    // Node feeding the input called `name` of `node`; links are `[id, from, slot, to, slot, type]`
+    let source = |node: &Value, name: &str| {
+        let link_id = node
+            .get("inputs")?
+            .as_array()?
+            .iter()
+            .find(|input| input.get("name").and_then(Value::as_str) == Some(name))?
+            .get("link")?
+            .as_i64()?;
+        let from = links?.iter().find(|link| link.get(0).and_then(Value::as_i64) == Some(link_id))?.get(1)?.as_i64()?;
+        nodes.iter().find(|n| n.get("id").and_then(Value::as_i64) == Some(from))
+    };
+    let text = |node: &Value| widget(node, 0).and_then(|v| v.as_str().map(str::to_string));
+
+    let sampler = nodes
+        .iter()
+        .find(|n| n.get("type").and_then(Value::as_str).is_some_and(|t| t.contains("KSampler")))?;
+    let mut metadata = ImageMetadata {
+        prompt: source(sampler, "positive").and_then(text)?,
+        negative_prompt: source(sampler, "negative").and_then(text).filter(|n| !n.trim().is_empty()),
+        seed: widget(sampler, 0).and_then(|v| v.as_i64()),
+        ..Default::default()
+    };
+    if let Some(latent) = source(sampler, "latent_image") {
+        metadata.width = widget(latent, 0).and_then(|v| v.as_u64()).unwrap_or_default() as u32;
+        metadata.height = widget(latent, 1).and_then(|v| v.as_u64()).unwrap_or_default() as u32;
+    }
+    if let Some(checkpoint) = source(sampler, "model") {
+        metadata.model = text(checkpoint).unwrap_or_default();
+    }
+    Some(metadata)
+}
+
+/// Generation params for `metadata` on top of the current defaults. Embedded bindings are kept
+/// while their reference image still exists; other `@name` characters use saved bindings.
+fn imported_params(metadata: &ImageMetadata, parsed: &ParsedPrompt) -> ImageGenerationParams {
+    let mut bindings: Vec<CharacterBindingInfo> = metadata
+        .character_bindings
+        .iter()
+        .filter(|b| b.reference_image_path.as_deref().is_some_and(|p| Path::new(p).exists()))
+        .cloned()
+        .collect();
+    let missing: Vec<String> = parsed
+        .characters
+        .iter()
+        .filter(|c| !bindings.iter().any(|b| b.character_name == c.name))
+        .map(|c| c.name.clone())
+        .collect();
+    for saved in get_bindings_for_prompt(missing) {
+        if saved.reference_image_path.is_some() {
+            bindings.push(CharacterBindingInfo {
+                character_name: saved.character_name,
+                reference_image_path: saved.reference_image_path,
+                image_type: saved.image_type,
+            });
+        }
+    }
+
+    let mut params =
+        current_generation_config().to_params(metadata.prompt.clone(), bindings, JobPriority::Interactive);
+    if PROVIDER_REGISTRY.get(&metadata.model).is_some() && metadata.model != params.model {
+        // The default size belongs to the default model and may not exist on this one
+        params.model = metadata.model.clone();
+        params.size = None;
+    }
+    if metadata.width > 0 && metadata.height > 0 {
+        params.width = metadata.width;
+        params.height = metadata.height;
+        // Sizes the provider does not list are left to width/height and aspect snapping
+        let size = format!("{}x{}", metadata.width, metadata.height);
+        let listed = PROVIDER_REGISTRY.get(&params.model).is_some_and(|p| {
+            let sizes = p.capabilities().sizes;
+            sizes.is_empty() || sizes.iter().any(|s| s.eq_ignore_ascii_case(&size))
+        });
+        params.size = listed.then_some(size);
+    }
+    params.negative_prompt = metadata.negative_prompt.clone();
+    params.seed = metadata.seed;
+    params
+}
+
+/// Reads the generation info embedded in an image (path, URL or data URL).
+#[tauri::command]
+pub async fn import_image_metadata(image: String) -> AppResult<ImportedImage> {
+    let bytes = fetch_image_bytes(&image).await?;
+    let (source, metadata) = read_metadata(&bytes)?;
+    let parsed = parse_prompt_internal(&metadata.prompt)?;
+    let params = imported_params(&metadata, &parsed);
+    Ok(ImportedImage {
+        source,
+        metadata,
+        params,
+        parsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn metadata() -> ImageMetadata {
+        ImageMetadata {
+            prompt: "@小狐狸 在森林里, watercolor".to_string(),
+            negative_prompt: Some("blurry".to_string()),
+            model: "seedream".to_string(),
+            width: 64,
+            height: 48,
+            seed: Some(42),
+            ..Default::default()
+        }
+        .completed()
+    }
+
+    fn encoded(format: image::ImageOutputFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(64, 48))
+            .write_to(&mut Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_embeds_png_text_chunks() {
+        let meta = metadata();
+        assert_eq!(
+            meta.a1111_parameters(),
+            "@小狐狸 在森林里, watercolor\nNegative prompt: blurry\nSeed: 42, Size: 64x48, Model: seedream, Generator: xuanchen-huiben"
+        );
+
+        let png = embed_metadata(encoded(image::ImageOutputFormat::Png), &meta).unwrap();
+        // Embedding twice replaces instead of duplicating
+        let png = embed_metadata(png, &meta).unwrap();
+        let entries = png_text_entries(&png).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], (A1111_KEYWORD.to_string(), meta.a1111_parameters()));
+        let stored: ImageMetadata = serde_json::from_str(&entries[1].1).unwrap();
+        assert_eq!(stored.seed, Some(42));
+        assert!(!stored.segments.is_empty());
+        assert_eq!(image::load_from_memory(&png).unwrap().width(), 64);
+        assert_eq!(read_metadata(&png).unwrap().0, MetadataSource::Huiben);
+    }
+
+    #[test]
+    fn test_embeds_jpeg_exif_and_xmp() {
+        let jpeg = embed_metadata(encoded(image::ImageOutputFormat::Jpeg(80)), &metadata()).unwrap();
+        let (segments, _) = jpeg_segments(&jpeg).unwrap();
+        assert_eq!(segments[0].0, 0xE0);
+        assert!(segments[1].1.starts_with(EXIF_HEADER));
+        let xmp = String::from_utf8_lossy(&segments[2].1[XMP_NAMESPACE.len()..]).to_string();
+        assert!(xmp.contains("<huiben:huiben>{\"prompt\""));
+        assert!(xmp.contains("Negative prompt: blurry"));
+        assert_eq!(image::load_from_memory(&jpeg).unwrap().height(), 48);
+
+        let (source, read) = read_metadata(&jpeg).unwrap();
+        assert_eq!(source, MetadataSource::Huiben);
+        assert_eq!(read.prompt, metadata().prompt);
+        // Without our XMP, the EXIF UserComment is read the way A1111 writes it
+        let (segments, scan) = jpeg_segments(&jpeg).unwrap();
+        let mut exif_only = vec![0xFF, 0xD8];
+        for (marker, payload) in segments.into_iter().filter(|(_, p)| !p.starts_with(XMP_NAMESPACE)) {
+            exif_only.extend_from_slice(&jpeg_segment(marker, payload));
+        }
+        exif_only.extend_from_slice(scan);
+        let (source, read) = read_metadata(&exif_only).unwrap();
+        assert_eq!(source, MetadataSource::A1111);
+        assert_eq!((read.seed, read.width, read.model.as_str()), (Some(42), 64, "seedream"));
+    }
+
+    #[test]
+    fn test_reads_a1111_and_comfyui() {
+        let a1111 = parse_a1111_parameters(
+            "masterpiece, a fox\nin the forest\nNegative prompt: lowres, blurry\n\
+             Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 1234, Size: 512x768, Model: \"v1-5, pruned\"",
+        );
+        assert_eq!(a1111.prompt, "masterpiece, a fox\nin the forest");
+        assert_eq!(a1111.negative_prompt.as_deref(), Some("lowres, blurry"));
+        assert_eq!((a1111.seed, a1111.width, a1111.height), (Some(1234), 512, 768));
+        assert_eq!(a1111.model, "v1-5, pruned");
+        // Too few pairs: the last line is still prompt
+        assert_eq!(parse_a1111_parameters("a cat\nSeed: 1").prompt, "a cat\nSeed: 1");
+
+        let graph = serde_json::json!({
+            "3": {"class_type": "KSampler", "inputs": {
+                "seed": 99, "model": ["10", 0], "positive": ["6", 0], "negative": ["7", 0], "latent_image": ["5", 0]
+            }},
+            "4": {"class_type": "CheckpointLoaderSimple", "inputs": {"ckpt_name": "sdxl.safetensors"}},
+            "10": {"class_type": "LoraLoader", "inputs": {"model": ["4", 0]}},
+            "5": {"class_type": "EmptyLatentImage", "inputs": {"width": 832, "height": 1216}},
+            "6": {"class_type": "CLIPTextEncode", "inputs": {"text": "@小狐狸 reading"}},
+            "7": {"class_type": "CLIPTextEncode", "inputs": {"text": "ugly"}}
+        });
+        let mut png = Vec::new();
+        for (kind, data) in png_chunks(&encoded(image::ImageOutputFormat::Png)).unwrap() {
+            png.extend_from_slice(&png_chunk(&kind, data));
+            if &kind == b"IHDR" {
+                png.extend_from_slice(&png_text_chunk("prompt", &graph.to_string()));
+            }
+        }
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&png);
+        let (source, comfy) = read_metadata(&bytes).unwrap();
+        assert_eq!(source, MetadataSource::ComfyUi);
+        assert_eq!(comfy.prompt, "@小狐狸 reading");
+        assert_eq!(comfy.negative_prompt.as_deref(), Some("ugly"));
+        assert_eq!((comfy.seed, comfy.width, comfy.height), (Some(99), 832, 1216));
+        assert_eq!(comfy.model, "sdxl.safetensors");
+        assert!(!comfy.segments.is_empty());
+
+        // Imported params are ready to generate with the default model
+        let mut params = imported_params(&comfy, &parse_prompt_internal(&comfy.prompt).unwrap());
+        assert_eq!((params.model.as_str(), params.size.as_deref()), ("seedream", None));
+        let capabilities = PROVIDER_REGISTRY.get(&params.model).unwrap().capabilities();
+        assert!(capabilities.validate(&mut params).is_empty());
+
+        let plain = encoded(image::ImageOutputFormat::Png);
+        assert_eq!(read_metadata(&plain).unwrap_err().code, crate::error::ErrorCode::NotFound);
+    }
+}
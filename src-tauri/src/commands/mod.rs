@@ -1,3 +1,16 @@
 pub mod character_binding;
 pub mod prompt_parser;
 pub mod image_generator;
+pub mod generation_queue;
+pub mod batch;
+pub mod history;
+pub mod gallery;
+pub mod image_files;
+pub mod thumbnails;
+pub mod image_metadata;
+pub mod project;
+pub mod typesetting;
+pub mod pdf_export;
+pub mod epub_export;
+pub mod text_overlay;
+pub mod providers;
@@ -0,0 +1,439 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, DynamicImage, RgbImage};
+use printpdf::lopdf::{Dictionary, Object};
+use printpdf::{
+    ColorBits, ColorSpace, Image, ImageFilter, ImageTransform, ImageXObject, IndirectFontRef, Mm,
+    PdfDocument, PdfDocumentReference, Px,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufWriter, Cursor};
+use std::path::PathBuf;
+
+use super::project::{self, Project, ProjectPage};
+use super::providers::capabilities::parse_size;
+use super::providers::FieldError;
+use super::typesetting::TextFont;
+use crate::error::{AppError, AppResult};
+
+const MM_PER_INCH: f32 = 25.4;
+const PT_PER_INCH: f32 = 72.0;
+const JPEG_QUALITY: u8 = 92;
+
+fn pt_to_mm(pt: f32) -> f32 {
+    pt * MM_PER_INCH / PT_PER_INCH
+}
+
+fn mm_to_pt(mm: f32) -> f32 {
+    mm * PT_PER_INCH / MM_PER_INCH
+}
+
+/// Layout of a printed book. Sizes are in millimetres of the trimmed page; the bleed is added
+/// on every side and the image runs into it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdfExportOptions {
+    /// `A4`, `A5`, `B5`, `letter`, `square` (210x210) or a custom `WxH` in mm.
+    #[serde(alias = "pageSize")]
+    pub page_size: String,
+    #[serde(alias = "bleedMm")]
+    pub bleed_mm: f32,
+    /// Keeps text away from the trim edge.
+    #[serde(alias = "marginMm")]
+    pub margin_mm: f32,
+    /// Images printed below this resolution are reported in `warnings`.
+    #[serde(alias = "minDpi")]
+    pub min_dpi: f32,
+    /// Fail the export instead of warning when an image is below `min_dpi`.
+    #[serde(alias = "strictDpi")]
+    pub strict_dpi: bool,
+    /// A `.ttf`/`.otf` font with Chinese glyphs; found automatically when empty.
+    #[serde(alias = "fontPath")]
+    pub font_path: Option<String>,
+    /// Story text size in pt.
+    #[serde(alias = "fontSize")]
+    pub font_size: f32,
+    #[serde(alias = "lineSpacing")]
+    pub line_spacing: f32,
+}
+
+impl Default for PdfExportOptions {
+    fn default() -> Self {
+        PdfExportOptions {
+            page_size: "A4".to_string(),
+            bleed_mm: 3.0,
+            margin_mm: 12.0,
+            min_dpi: 300.0,
+            strict_dpi: false,
+            font_path: None,
+            font_size: 16.0,
+            line_spacing: 1.5,
+        }
+    }
+}
+
+impl PdfExportOptions {
+    /// Trimmed page width and height in mm.
+    fn trim_size(&self) -> AppResult<(f32, f32)> {
+        let size = match self.page_size.to_ascii_lowercase().as_str() {
+            "a4" => (210.0, 297.0),
+            "a5" => (148.0, 210.0),
+            "b5" => (176.0, 250.0),
+            "letter" => (215.9, 279.4),
+            "square" => (210.0, 210.0),
+            other => parse_size(other)
+                .map(|(w, h)| (w as f32, h as f32))
+                .ok_or_else(|| AppError::invalid_input(format!("无效的页面尺寸: {}", self.page_size)))?,
+        };
+        Ok(size)
+    }
+
+    fn validate(&self, trim_width: f32) -> AppResult<()> {
+        if self.bleed_mm < 0.0 || self.margin_mm < 0.0 {
+            return Err(AppError::invalid_input("出血和边距不能为负数"));
+        }
+        if self.margin_mm * 2.0 >= trim_width {
+            return Err(AppError::invalid_input("边距过大，没有留给文字的空间"));
+        }
+        if self.font_size <= 0.0 || self.line_spacing <= 0.0 {
+            return Err(AppError::invalid_input("字号和行距必须大于0"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportWarning {
+    /// 1-based page number in the book.
+    pub page: usize,
+    #[serde(alias = "pageId")]
+    pub page_id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PdfExportReport {
+    pub path: String,
+    pub pages: usize,
+    pub warnings: Vec<ExportWarning>,
+}
+
+/// Pixel rect `(x, y, width, height)` of the largest centred region of an image with the
+/// aspect ratio of the target area, so the image covers the area without distortion.
+pub(super) fn cover_crop(image_width: u32, image_height: u32, area_width: f32, area_height: f32) -> (u32, u32, u32, u32) {
+    let target = area_width / area_height;
+    let source = image_width as f32 / image_height as f32;
+    if source > target {
+        let width = ((image_height as f32 * target).round() as u32).clamp(1, image_width);
+        ((image_width - width) / 2, 0, width, image_height)
+    } else {
+        let height = ((image_width as f32 / target).round() as u32).clamp(1, image_height);
+        (0, (image_height - height) / 2, image_width, height)
+    }
+}
+
+/// Resolution an image of `pixels` gets when printed `mm` wide.
+pub(super) fn effective_dpi(pixels: u32, mm: f32) -> f32 {
+    pixels as f32 / (mm / MM_PER_INCH)
+}
+
+/// Loads the page's chosen image. `Ok(None)` when the page has none yet.
+pub(super) fn load_page_image(page: &ProjectPage) -> AppResult<Option<DynamicImage>> {
+    let Some(path) = &page.image else {
+        return Ok(None);
+    };
+    image::open(path)
+        .map(Some)
+        .map_err(|e| AppError::invalid_input(format!("无法读取页面图片 {}: {}", path, e)))
+}
+
+/// Embeds pixels as JPEG; raw RGB would make a 20-page book several hundred MB.
+fn jpeg_xobject(pixels: &RgbImage) -> AppResult<ImageXObject> {
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+        .encode(pixels.as_raw(), pixels.width(), pixels.height(), ColorType::Rgb8)
+        .map_err(pdf_error)?;
+    Ok(ImageXObject {
+        width: Px(pixels.width() as usize),
+        height: Px(pixels.height() as usize),
+        color_space: ColorSpace::Rgb,
+        bits_per_component: ColorBits::Bit8,
+        interpolate: true,
+        image_data: jpeg,
+        image_filter: Some(ImageFilter::DCT),
+        smask: None,
+        clipping_bbox: None,
+    })
+}
+
+struct PageGeometry {
+    media_width: f32,
+    media_height: f32,
+    bleed: f32,
+    trim_width: f32,
+    trim_height: f32,
+}
+
+fn render_page(
+    doc: &PdfDocumentReference,
+    geometry: &PageGeometry,
+    options: &PdfExportOptions,
+    page: &ProjectPage,
+    font: Option<(&TextFont, &IndirectFontRef)>,
+    warnings: &mut Vec<(String, Option<f32>)>,
+) -> AppResult<()> {
+    let (page_index, layer_index) = doc.add_page(
+        Mm(geometry.media_width),
+        Mm(geometry.media_height),
+        "page",
+    );
+    let pdf_page = doc.get_page(page_index);
+    let layer = pdf_page.get_layer(layer_index);
+
+    let bleed_pt = mm_to_pt(geometry.bleed);
+    let trim_box = vec![
+        Object::from(bleed_pt),
+        Object::from(bleed_pt),
+        Object::from(bleed_pt + mm_to_pt(geometry.trim_width)),
+        Object::from(bleed_pt + mm_to_pt(geometry.trim_height)),
+    ];
+    let bleed_box = vec![
+        Object::from(0.0f32),
+        Object::from(0.0f32),
+        Object::from(mm_to_pt(geometry.media_width)),
+        Object::from(mm_to_pt(geometry.media_height)),
+    ];
+    pdf_page.extend_with(Dictionary::from_iter(vec![
+        ("TrimBox", Object::Array(trim_box)),
+        ("BleedBox", Object::Array(bleed_box)),
+    ]));
+
+    // Text sits in a band at the bottom of the page; the image fills everything above it
+    let text_width = geometry.trim_width - 2.0 * options.margin_mm;
+    let line_height = pt_to_mm(options.font_size * options.line_spacing);
+    let lines = match font {
+        Some((text_font, _)) if !page.text.trim().is_empty() => {
+            text_font.wrap(page.text.trim(), options.font_size, mm_to_pt(text_width))
+        }
+        _ => Vec::new(),
+    };
+    let band_height = if lines.is_empty() {
+        0.0
+    } else {
+        geometry.bleed + 2.0 * options.margin_mm + lines.len() as f32 * line_height
+    };
+    if band_height > geometry.media_height * 0.6 {
+        warnings.push(("文字过多，图片只剩下不到四成的版面".to_string(), None));
+    }
+
+    match load_page_image(page)? {
+        Some(img) => {
+            let area_width = geometry.media_width;
+            let area_height = (geometry.media_height - band_height).max(1.0);
+            let (x, y, w, h) = cover_crop(img.width(), img.height(), area_width, area_height);
+            let dpi = effective_dpi(w, area_width);
+            if dpi < options.min_dpi {
+                warnings.push((
+                    format!("图片分辨率约为{:.0} DPI，低于{:.0} DPI，印刷可能模糊", dpi, options.min_dpi),
+                    Some(dpi),
+                ));
+            }
+            let cropped = img.crop_imm(x, y, w, h).to_rgb8();
+            Image::from(jpeg_xobject(&cropped)?).add_to_layer(
+                layer.clone(),
+                ImageTransform {
+                    translate_x: Some(Mm(0.0)),
+                    translate_y: Some(Mm(band_height)),
+                    dpi: Some(dpi),
+                    ..Default::default()
+                },
+            );
+        }
+        None => warnings.push(("该页面还没有选择图片".to_string(), None)),
+    }
+
+    if let Some((text_font, font_ref)) = font {
+        let missing = text_font.missing_glyphs(&page.text);
+        if !missing.is_empty() {
+            let missing: String = missing.into_iter().collect();
+            warnings.push((format!("字体缺少以下字符，将无法显示: {}", missing), None));
+        }
+
+        let left = geometry.bleed + options.margin_mm;
+        let mut baseline =
+            band_height - options.margin_mm - pt_to_mm(text_font.ascent(options.font_size));
+        for line in &lines {
+            let width = pt_to_mm(text_font.text_width(line, options.font_size));
+            let x = left + (text_width - width).max(0.0) / 2.0;
+            layer.use_text(line.as_str(), options.font_size, Mm(x), Mm(baseline), font_ref);
+            baseline -= line_height;
+        }
+    }
+
+    Ok(())
+}
+
+fn pdf_error(e: impl std::fmt::Display) -> AppError {
+    AppError::internal(format!("PDF生成失败: {}", e))
+}
+
+/// Builds the PDF for `project` and writes it to `output`.
+pub fn export_pdf(
+    project: &Project,
+    output: PathBuf,
+    options: &PdfExportOptions,
+) -> AppResult<PdfExportReport> {
+    if project.pages.is_empty() {
+        return Err(AppError::invalid_input("项目还没有页面"));
+    }
+    let (trim_width, trim_height) = options.trim_size()?;
+    options.validate(trim_width)?;
+    let geometry = PageGeometry {
+        media_width: trim_width + 2.0 * options.bleed_mm,
+        media_height: trim_height + 2.0 * options.bleed_mm,
+        bleed: options.bleed_mm,
+        trim_width,
+        trim_height,
+    };
+
+    let doc = PdfDocument::empty(project.name.clone()).with_author(project.settings.author.clone());
+
+    // Image-only books do not need a font at all
+    let text_font = if project.pages.iter().any(|p| !p.text.trim().is_empty()) {
+        let text_font = TextFont::load(options.font_path.as_deref(), true)?;
+        let font_ref = doc
+            .add_external_font(Cursor::new(text_font.bytes().to_vec()))
+            .map_err(pdf_error)?;
+        Some((text_font, font_ref))
+    } else {
+        None
+    };
+
+    let mut warnings = Vec::new();
+    for (index, page) in project.pages.iter().enumerate() {
+        let mut page_warnings = Vec::new();
+        let font = text_font.as_ref().map(|(f, r)| (f, r));
+        render_page(&doc, &geometry, options, page, font, &mut page_warnings)?;
+        warnings.extend(page_warnings.into_iter().map(|(message, dpi)| {
+            (
+                ExportWarning {
+                    page: index + 1,
+                    page_id: page.id.clone(),
+                    message,
+                },
+                dpi,
+            )
+        }));
+    }
+
+    if options.strict_dpi {
+        let low_res: Vec<FieldError> = warnings
+            .iter()
+            .filter(|(_, dpi)| dpi.is_some())
+            .map(|(w, _)| FieldError {
+                field: format!("pages[{}].image", w.page - 1),
+                message: w.message.clone(),
+            })
+            .collect();
+        if !low_res.is_empty() {
+            return Err(AppError::validation(low_res));
+        }
+    }
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::File::create(&output)?;
+    doc.save(&mut BufWriter::new(file)).map_err(pdf_error)?;
+
+    Ok(PdfExportReport {
+        path: output.to_string_lossy().to_string(),
+        pages: project.pages.len(),
+        warnings: warnings.into_iter().map(|(w, _)| w).collect(),
+    })
+}
+
+/// Exports a project as a print-ready PDF. Without `output_path` the file goes to the
+/// project's `exports` folder.
+#[tauri::command]
+pub async fn export_project_pdf(
+    project_id: String,
+    output_path: Option<String>,
+    options: Option<PdfExportOptions>,
+) -> AppResult<PdfExportReport> {
+    tokio::task::spawn_blocking(move || {
+        let project = project::load_project(&project_id)?;
+        let output = match output_path {
+            Some(path) => PathBuf::from(path),
+            None => project::default_export_path(&project, "pdf")?,
+        };
+        export_pdf(&project, output, &options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("导出任务异常: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cover_crop_and_dpi() {
+        // A square image on a portrait area loses its sides
+        assert_eq!(cover_crop(1000, 1000, 100.0, 200.0), (250, 0, 500, 1000));
+        assert_eq!(cover_crop(1000, 500, 100.0, 100.0), (250, 0, 500, 500));
+        assert!((effective_dpi(2480, 210.0) - 300.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_exports_image_pages_with_dpi_warnings() {
+        let dir = std::env::temp_dir().join(format!("huiben_pdf_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image_path = dir.join("page.png");
+        image::RgbImage::from_pixel(400, 400, image::Rgb([200, 120, 40]))
+            .save(&image_path)
+            .unwrap();
+
+        let page = |id: &str, image: Option<&PathBuf>| ProjectPage {
+            id: id.to_string(),
+            text: String::new(),
+            prompt: String::new(),
+            params: None,
+            negative_prompt: None,
+            seed: None,
+            last_seed: None,
+            image: image.map(|p| p.to_string_lossy().to_string()),
+            images: Vec::new(),
+        };
+        let project = Project {
+            id: "proj_test".to_string(),
+            name: "测试绘本".to_string(),
+            created_at: String::new(),
+            updated_at: String::new(),
+            pages: vec![page("p1", Some(&image_path)), page("p2", None)],
+            bindings: Default::default(),
+            settings: Default::default(),
+        };
+
+        let options = PdfExportOptions {
+            page_size: "square".to_string(),
+            ..Default::default()
+        };
+        let report = export_pdf(&project, dir.join("book.pdf"), &options).unwrap();
+        assert_eq!(report.pages, 2);
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.warnings[0].message.contains("DPI"));
+        assert_eq!(report.warnings[1].page, 2);
+
+        let pdf = fs::read(dir.join("book.pdf")).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+
+        let strict = PdfExportOptions {
+            strict_dpi: true,
+            ..options
+        };
+        let err = export_pdf(&project, dir.join("strict.pdf"), &strict).unwrap_err();
+        assert_eq!(err.field_errors[0].field, "pages[0].image");
+        fs::remove_dir_all(&dir).ok();
+    }
+}
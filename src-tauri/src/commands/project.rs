@@ -0,0 +1,573 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::character_binding::{get_bindings_for_prompt, CharacterBinding};
+use super::generation_queue::JobPriority;
+use super::image_files::{fetch_image_bytes, image_extension};
+use super::image_generator::{
+    self, generate_image_and_wait, CharacterBindingInfo, GenerationConfig, ImageGenerationResult,
+};
+use super::prompt_parser::parse_prompt_internal;
+use crate::error::{AppError, AppResult, ErrorCode};
+
+/// Serializes read-modify-write of project files; `generate_page` saves after a long await.
+static PROJECT_LOCK: Mutex<()> = Mutex::new(());
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+const PROJECT_FILE: &str = "project.json";
+
+/// A picture book: ordered pages plus character bindings that apply only inside this book.
+///
+/// Stored as `projects/<id>/project.json`, with generated images in `projects/<id>/images/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    #[serde(alias = "createdAt")]
+    pub created_at: String,
+    #[serde(alias = "updatedAt")]
+    pub updated_at: String,
+    #[serde(default)]
+    pub pages: Vec<ProjectPage>,
+    /// Take precedence over the global `CHARACTER_BINDINGS` for this project's pages.
+    #[serde(default)]
+    pub bindings: HashMap<String, CharacterBinding>,
+    #[serde(default)]
+    pub settings: ProjectSettings,
+}
+
+/// Book metadata written into exports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectSettings {
+    pub author: String,
+    /// BCP 47 tag, e.g. `zh-CN`.
+    pub language: String,
+    pub description: String,
+}
+
+impl Default for ProjectSettings {
+    fn default() -> Self {
+        ProjectSettings {
+            author: String::new(),
+            language: "zh-CN".to_string(),
+            description: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectPage {
+    pub id: String,
+    /// Story text printed on the page.
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub prompt: String,
+    /// Per-page settings; `None` uses the saved generation config.
+    #[serde(default)]
+    pub params: Option<GenerationConfig>,
+    #[serde(default, alias = "negativePrompt")]
+    pub negative_prompt: Option<String>,
+    /// Pinned seed so the page reproduces; `None` draws a new one each time.
+    #[serde(default)]
+    pub seed: Option<i64>,
+    /// Seed of the latest generation, to pin when it came out well.
+    #[serde(default, alias = "lastSeed")]
+    pub last_seed: Option<i64>,
+    /// The image chosen for the page, one of `images`.
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Every image generated for this page, oldest first.
+    #[serde(default)]
+    pub images: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectSummary {
+    pub id: String,
+    pub name: String,
+    #[serde(alias = "updatedAt")]
+    pub updated_at: String,
+    #[serde(alias = "pageCount")]
+    pub page_count: usize,
+    /// Chosen image of the first page that has one.
+    pub cover: Option<String>,
+}
+
+/// Fields to change on a page; `None` leaves the field as it is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PageUpdate {
+    pub text: Option<String>,
+    pub prompt: Option<String>,
+    pub params: Option<GenerationConfig>,
+    /// Empty clears it.
+    #[serde(alias = "negativePrompt")]
+    pub negative_prompt: Option<String>,
+    /// `null` unpins the seed.
+    #[serde(default, deserialize_with = "present")]
+    pub seed: Option<Option<i64>>,
+    pub image: Option<String>,
+}
+
+/// Tells an explicit `null` (`Some(None)`) apart from a missing field (`None`).
+fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageGenerationResult {
+    pub page: ProjectPage,
+    pub result: ImageGenerationResult,
+}
+
+fn get_projects_dir() -> PathBuf {
+    let dir = image_generator::get_app_data_dir().join("projects");
+    fs::create_dir_all(&dir).ok();
+    dir
+}
+
+pub(super) fn get_project_dir(project_id: &str) -> AppResult<PathBuf> {
+    // Ids end up in paths; refuse anything that could escape the projects folder
+    if project_id.is_empty() || !project_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(AppError::invalid_input("无效的项目ID"));
+    }
+    Ok(get_projects_dir().join(project_id))
+}
+
+/// `projects/<id>/exports/<project name>.<extension>`, for exports without an explicit path.
+pub(super) fn default_export_path(project: &Project, extension: &str) -> AppResult<PathBuf> {
+    let dir = get_project_dir(&project.id)?.join("exports");
+    fs::create_dir_all(&dir)?;
+    let name: String = project
+        .name
+        .chars()
+        .map(|c| if "\\/:*?\"<>|".contains(c) || c.is_control() { '_' } else { c })
+        .collect();
+    Ok(dir.join(format!("{}.{}", name, extension)))
+}
+
+fn new_id(prefix: &str) -> String {
+    format!(
+        "{}_{}_{}",
+        prefix,
+        chrono::Utc::now().timestamp_millis(),
+        ID_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+pub(super) fn load_project(project_id: &str) -> AppResult<Project> {
+    let path = get_project_dir(project_id)?.join(PROJECT_FILE);
+    if !path.exists() {
+        return Err(AppError::not_found("项目不存在"));
+    }
+    let json = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+fn save_project(project: &Project) -> AppResult<()> {
+    let dir = get_project_dir(&project.id)?;
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(PROJECT_FILE), serde_json::to_string_pretty(project)?)?;
+    Ok(())
+}
+
+/// Loads a project, applies `change` and saves it with a fresh `updated_at`.
+fn update_project(
+    project_id: &str,
+    change: impl FnOnce(&mut Project) -> AppResult<()>,
+) -> AppResult<Project> {
+    let _guard = PROJECT_LOCK.lock()?;
+    let mut project = load_project(project_id)?;
+    change(&mut project)?;
+    project.updated_at = chrono::Local::now().to_rfc3339();
+    save_project(&project)?;
+    Ok(project)
+}
+
+fn find_page<'a>(project: &'a mut Project, page_id: &str) -> AppResult<&'a mut ProjectPage> {
+    project
+        .pages
+        .iter_mut()
+        .find(|p| p.id == page_id)
+        .ok_or_else(|| AppError::not_found("页面不存在"))
+}
+
+/// Reference images for the `@name` characters of a page prompt: the project's own
+/// bindings first, then the global ones. Characters without an image are left out.
+fn page_bindings(project: &Project, prompt: &str) -> AppResult<Vec<CharacterBindingInfo>> {
+    let parsed = parse_prompt_internal(prompt)?;
+    let names: Vec<String> = parsed.characters.into_iter().map(|c| c.name).collect();
+
+    let (scoped, global): (Vec<String>, Vec<String>) = names
+        .into_iter()
+        .partition(|name| project.bindings.contains_key(name));
+    let bindings = scoped
+        .iter()
+        .filter_map(|name| project.bindings.get(name).cloned())
+        .chain(get_bindings_for_prompt(global));
+
+    Ok(bindings
+        .filter(|b| b.bound)
+        .filter_map(|b| {
+            Some(CharacterBindingInfo {
+                reference_image_path: Some(b.reference_image_path?),
+                character_name: b.character_name,
+                image_type: b.image_type,
+            })
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn create_project(name: String) -> AppResult<Project> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::invalid_input("项目名称不能为空"));
+    }
+    let now = chrono::Local::now().to_rfc3339();
+    let project = Project {
+        id: new_id("proj"),
+        name,
+        created_at: now.clone(),
+        updated_at: now,
+        pages: Vec::new(),
+        bindings: HashMap::new(),
+        settings: ProjectSettings::default(),
+    };
+    let _guard = PROJECT_LOCK.lock()?;
+    save_project(&project)?;
+    Ok(project)
+}
+
+#[tauri::command]
+pub fn list_projects() -> AppResult<Vec<ProjectSummary>> {
+    let mut summaries = Vec::new();
+    for entry in fs::read_dir(get_projects_dir())? {
+        let path = entry?.path().join(PROJECT_FILE);
+        let Ok(json) = fs::read_to_string(&path) else {
+            continue;
+        };
+        match serde_json::from_str::<Project>(&json) {
+            Ok(project) => summaries.push(ProjectSummary {
+                cover: project.pages.iter().find_map(|p| p.image.clone()),
+                page_count: project.pages.len(),
+                id: project.id,
+                name: project.name,
+                updated_at: project.updated_at,
+            }),
+            Err(e) => eprintln!("Skipping unreadable project {:?}: {}", path, e),
+        }
+    }
+    summaries.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    Ok(summaries)
+}
+
+#[tauri::command]
+pub fn get_project(project_id: String) -> AppResult<Project> {
+    load_project(&project_id)
+}
+
+#[tauri::command]
+pub fn rename_project(project_id: String, name: String) -> AppResult<Project> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::invalid_input("项目名称不能为空"));
+    }
+    update_project(&project_id, |project| {
+        project.name = name;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn update_project_settings(project_id: String, settings: ProjectSettings) -> AppResult<Project> {
+    if settings.language.trim().is_empty() {
+        return Err(AppError::invalid_input("语言不能为空"));
+    }
+    update_project(&project_id, |project| {
+        project.settings = settings;
+        Ok(())
+    })
+}
+
+/// Deletes the project folder, including its generated images.
+#[tauri::command]
+pub fn delete_project(project_id: String) -> AppResult<bool> {
+    let dir = get_project_dir(&project_id)?;
+    let _guard = PROJECT_LOCK.lock()?;
+    if !dir.join(PROJECT_FILE).exists() {
+        return Ok(false);
+    }
+    fs::remove_dir_all(dir)?;
+    Ok(true)
+}
+
+/// Inserts a page at `index`, or at the end when `index` is `None` or past the end.
+#[tauri::command]
+pub fn add_page(
+    project_id: String,
+    text: String,
+    prompt: String,
+    index: Option<usize>,
+) -> AppResult<Project> {
+    update_project(&project_id, |project| {
+        let page = ProjectPage {
+            id: new_id("page"),
+            text,
+            prompt,
+            params: None,
+            negative_prompt: None,
+            seed: None,
+            last_seed: None,
+            image: None,
+            images: Vec::new(),
+        };
+        let index = index.unwrap_or(project.pages.len()).min(project.pages.len());
+        project.pages.insert(index, page);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn update_page(project_id: String, page_id: String, update: PageUpdate) -> AppResult<Project> {
+    update_project(&project_id, |project| {
+        let page = find_page(project, &page_id)?;
+        if let Some(image) = update.image {
+            if !page.images.contains(&image) {
+                return Err(AppError::invalid_input("只能选择该页面已生成的图片"));
+            }
+            page.image = Some(image);
+        }
+        if let Some(text) = update.text {
+            page.text = text;
+        }
+        if let Some(prompt) = update.prompt {
+            page.prompt = prompt;
+        }
+        if update.params.is_some() {
+            page.params = update.params;
+        }
+        if let Some(negative_prompt) = update.negative_prompt {
+            page.negative_prompt = Some(negative_prompt).filter(|n| !n.trim().is_empty());
+        }
+        if let Some(seed) = update.seed {
+            page.seed = seed;
+        }
+        Ok(())
+    })
+}
+
+/// Removes a page. Its generated images stay on disk until the project is deleted.
+#[tauri::command]
+pub fn delete_page(project_id: String, page_id: String) -> AppResult<Project> {
+    update_project(&project_id, |project| {
+        let before = project.pages.len();
+        project.pages.retain(|p| p.id != page_id);
+        if project.pages.len() == before {
+            return Err(AppError::not_found("页面不存在"));
+        }
+        Ok(())
+    })
+}
+
+/// Puts the pages in the order of `page_ids`, which must list every page exactly once.
+#[tauri::command]
+pub fn reorder_pages(project_id: String, page_ids: Vec<String>) -> AppResult<Project> {
+    update_project(&project_id, |project| {
+        let mut pages: HashMap<String, ProjectPage> =
+            project.pages.drain(..).map(|p| (p.id.clone(), p)).collect();
+        let count = pages.len();
+
+        let mut ordered = Vec::with_capacity(count);
+        for id in &page_ids {
+            match pages.remove(id) {
+                Some(page) => ordered.push(page),
+                None => return Err(AppError::invalid_input(format!("页面不存在或重复: {}", id))),
+            }
+        }
+        if ordered.len() != count {
+            return Err(AppError::invalid_input("页面顺序必须包含所有页面"));
+        }
+        project.pages = ordered;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn bind_project_character(
+    project_id: String,
+    character_name: String,
+    reference_image_path: String,
+    image_type: Option<String>,
+) -> AppResult<Project> {
+    if character_name.is_empty() {
+        return Err(AppError::invalid_input("角色名称不能为空"));
+    }
+    if !PathBuf::from(&reference_image_path).exists() {
+        return Err(AppError::not_found("参考图文件不存在"));
+    }
+    update_project(&project_id, |project| {
+        let binding = CharacterBinding {
+            character_name: character_name.clone(),
+            reference_image_path: Some(reference_image_path),
+            image_type: image_type.unwrap_or_else(|| "人物".to_string()),
+            created_at: chrono::Local::now().to_rfc3339(),
+            bound: true,
+            tags: Vec::new(),
+        };
+        project.bindings.insert(character_name, binding);
+        Ok(())
+    })
+}
+
+/// Drops the project's binding; the character falls back to its global binding, if any.
+#[tauri::command]
+pub fn unbind_project_character(project_id: String, character_name: String) -> AppResult<Project> {
+    update_project(&project_id, |project| {
+        project.bindings.remove(&character_name);
+        Ok(())
+    })
+}
+
+/// Generates images for a page, saves them into the project and makes the first one the
+/// page image if none was chosen yet.
+#[tauri::command]
+pub async fn generate_page(project_id: String, page_id: String) -> AppResult<PageGenerationResult> {
+    let params = {
+        let _guard = PROJECT_LOCK.lock()?;
+        let project = load_project(&project_id)?;
+        let page = project
+            .pages
+            .iter()
+            .find(|p| p.id == page_id)
+            .ok_or_else(|| AppError::not_found("页面不存在"))?;
+        if page.prompt.trim().is_empty() {
+            return Err(AppError::invalid_input("该页面还没有提示词"));
+        }
+        let config = page
+            .params
+            .clone()
+            .unwrap_or_else(image_generator::current_generation_config);
+        let bindings = page_bindings(&project, &page.prompt)?;
+        let mut params = config.to_params(page.prompt.clone(), bindings, JobPriority::Interactive);
+        params.negative_prompt = page.negative_prompt.clone();
+        params.seed = page.seed;
+        params
+    };
+
+    let result = generate_image_and_wait(params).await?;
+
+    let mut files = Vec::new();
+    if result.success {
+        let images_dir = get_project_dir(&project_id)?.join("images");
+        fs::create_dir_all(&images_dir)?;
+        for (n, url) in result.image_sources().into_iter().enumerate() {
+            let bytes = fetch_image_bytes(url).await?;
+            let path = images_dir.join(format!(
+                "{}_{}_{}.{}",
+                page_id,
+                result.task_id,
+                n + 1,
+                image_extension(&bytes)
+            ));
+            fs::write(&path, bytes)?;
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+
+    let project = update_project(&project_id, |project| {
+        let page = find_page(project, &page_id)
+            .map_err(|_| AppError::new(ErrorCode::Conflict, "生成期间页面已被删除"))?;
+        if page.image.is_none() {
+            page.image = files.first().cloned();
+        }
+        page.images.extend(files);
+        if result.success {
+            page.last_seed = result.seed;
+        }
+        Ok(())
+    })?;
+
+    let page = project
+        .pages
+        .into_iter()
+        .find(|p| p.id == page_id)
+        .ok_or_else(|| AppError::not_found("页面不存在"))?;
+    Ok(PageGenerationResult { page, result })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pages_reorder_and_generate() {
+        let project = create_project("小狐狸的冒险".to_string()).unwrap();
+        let id = project.id.clone();
+
+        add_page(id.clone(), "第一页".to_string(), "森林里的小狐狸".to_string(), None).unwrap();
+        let project = add_page(id.clone(), "封面".to_string(), String::new(), Some(0)).unwrap();
+        let ids: Vec<String> = project.pages.iter().map(|p| p.id.clone()).collect();
+        assert_eq!(project.pages[0].text, "封面");
+
+        assert!(reorder_pages(id.clone(), vec![ids[0].clone()]).is_err());
+        let project = reorder_pages(id.clone(), vec![ids[1].clone(), ids[0].clone()]).unwrap();
+        assert_eq!(project.pages[0].text, "第一页");
+
+        let mut params = image_generator::get_default_generation_config();
+        params.model = "mock".to_string();
+        params.size = Some("64x64".to_string());
+        params.width = 64;
+        params.height = 64;
+        let update = PageUpdate {
+            params: Some(params),
+            ..Default::default()
+        };
+        update_page(id.clone(), ids[1].clone(), update).unwrap();
+
+        let generated = generate_page(id.clone(), ids[1].clone()).await.unwrap();
+        assert!(generated.result.success, "{:?}", generated.result.error);
+        assert_eq!(generated.page.images.len(), 1);
+        assert_eq!(generated.page.image, generated.page.images.first().cloned());
+        assert!(PathBuf::from(&generated.page.images[0]).exists());
+
+        // Pinning the last seed reproduces the page
+        let seed = generated.page.last_seed.expect("recorded seed");
+        let update: PageUpdate = serde_json::from_value(serde_json::json!({ "seed": seed })).unwrap();
+        update_page(id.clone(), ids[1].clone(), update).unwrap();
+        let again = generate_page(id.clone(), ids[1].clone()).await.unwrap();
+        assert_eq!(again.page.last_seed, Some(seed));
+        assert_eq!(fs::read(&again.page.images[1]).unwrap(), fs::read(&again.page.images[0]).unwrap());
+        let update: PageUpdate = serde_json::from_value(serde_json::json!({ "seed": null })).unwrap();
+        assert_eq!(update_page(id.clone(), ids[1].clone(), update).unwrap().pages[1].seed, None);
+
+        let err = generate_page(id.clone(), ids[0].clone()).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidInput);
+
+        assert!(delete_project(id.clone()).unwrap());
+        assert_eq!(get_project(id).unwrap_err().code, ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn test_project_bindings_shadow_global_ones() {
+        let ref_path = std::env::temp_dir().join(format!("huiben_ref_{}.png", std::process::id()));
+        fs::write(&ref_path, b"png").unwrap();
+        let ref_path = ref_path.to_string_lossy().to_string();
+
+        let project = create_project("绑定".to_string()).unwrap();
+        let project = bind_project_character(project.id, "阿狸".to_string(), ref_path.clone(), None).unwrap();
+
+        let bindings = page_bindings(&project, "@阿狸 和 @无名 在河边").unwrap();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].reference_image_path.as_deref(), Some(ref_path.as_str()));
+
+        assert!(get_project_dir("../etc").is_err());
+        delete_project(project.id).unwrap();
+        fs::remove_file(ref_path).ok();
+    }
+}
@@ -2,6 +2,8 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::error::{AppError, AppResult};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PromptSegment {
     #[serde(rename = "type")]
@@ -544,9 +546,9 @@ fn extract_character_references(prompt: &str) -> Vec<CharacterRef> {
     characters
 }
 
-pub fn parse_prompt_internal(prompt: &str) -> Result<ParsedPrompt, String> {
+pub fn parse_prompt_internal(prompt: &str) -> AppResult<ParsedPrompt> {
     if prompt.is_empty() {
-        return Err("Prompt cannot be empty".to_string());
+        return Err(AppError::invalid_input("提示词不能为空"));
     }
 
     let characters = extract_character_references(prompt);
@@ -567,7 +569,7 @@ pub fn parse_prompt_internal(prompt: &str) -> Result<ParsedPrompt, String> {
 }
 
 #[tauri::command]
-pub fn parse_prompt(prompt: &str) -> Result<ParsedPrompt, String> {
+pub fn parse_prompt(prompt: &str) -> AppResult<ParsedPrompt> {
     parse_prompt_internal(prompt)
 }
 
@@ -580,7 +582,7 @@ pub fn extract_character_names(prompt: &str) -> Vec<String> {
 }
 
 #[tauri::command]
-pub fn test_parse(prompt: &str) -> Result<ParsedPrompt, String> {
+pub fn test_parse(prompt: &str) -> AppResult<ParsedPrompt> {
     parse_prompt_internal(prompt)
 }
 
@@ -0,0 +1,221 @@
+use async_trait::async_trait;
+
+use super::{error, GenerationRequest, ImageProvider, ProviderCapabilities, ProviderError};
+use crate::commands::image_generator::{ImageGenerationParams, ModelConfig};
+
+pub struct BananaProProvider;
+
+#[async_trait]
+impl ImageProvider for BananaProProvider {
+    fn id(&self) -> &'static str {
+        "banana_pro"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Banana Pro"
+    }
+
+    fn default_config(&self) -> ModelConfig {
+        ModelConfig {
+            base_url: "https://api.zhongzhuan.chat".to_string(),
+            api_key: "".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            sizes: ["1K", "2K", "4K"].iter().map(|s| s.to_string()).collect(),
+            aspect_ratios: [
+                "1:1", "2:3", "3:2", "3:4", "4:3", "4:5", "5:4", "9:16", "16:9", "21:9",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            max_reference_images: 14,
+            max_count: 4,
+            ..Default::default()
+        }
+    }
+
+    async fn generate(
+        &self,
+        config: &ModelConfig,
+        request: &GenerationRequest,
+    ) -> Result<Vec<String>, ProviderError> {
+        let params = &request.params;
+        // Validation has already snapped the size onto a supported ratio
+        let aspect_ratio = params.aspect_ratio.clone().unwrap_or_else(|| "1:1".to_string());
+        let image_size = image_size(params);
+        // One image per call, so `count` means that many calls
+        let mut images = Vec::new();
+        for _ in 0..params.count.max(1) {
+            images.extend(
+                call_banana_pro_api(
+                    config,
+                    &request.prompt,
+                    &aspect_ratio,
+                    image_size,
+                    request.images.clone(),
+                )
+                .await?,
+            );
+        }
+        Ok(images)
+    }
+}
+
+/// `imageSize` for the request: the requested `size` when it is one Gemini accepts,
+/// else the bucket matching `width`.
+fn image_size(params: &ImageGenerationParams) -> &str {
+    match params.size.as_deref() {
+        Some(size @ ("1K" | "2K" | "4K")) => size,
+        _ => match params.width {
+            0..=1024 => "1K",
+            1025..=2048 => "2K",
+            _ => "4K",
+        },
+    }
+}
+
+async fn call_banana_pro_api(
+    config: &ModelConfig,
+    prompt: &str,
+    aspect_ratio: &str,
+    image_size: &str,
+    images: Option<Vec<String>>,
+) -> Result<Vec<String>, ProviderError> {
+    let client = reqwest::Client::new();
+
+    // Build parts with optional reference images
+    let mut parts: Vec<serde_json::Value> = Vec::new();
+
+    // Add reference images if any
+    if let Some(ref imgs) = images {
+        for img in imgs {
+            let img_data = if img.starts_with("data:") {
+                img.split(',').nth(1).unwrap_or(img.as_str()).to_string()
+            } else {
+                img.clone()
+            };
+            parts.push(serde_json::json!({
+                "inlineData": {
+                    "mimeType": "image/png",
+                    "data": img_data
+                }
+            }));
+        }
+    }
+
+    // Add text prompt
+    parts.push(serde_json::json!({ "text": prompt }));
+
+    let contents = vec![serde_json::json!({
+        "role": "user",
+        "parts": parts
+    })];
+
+    let request_body = serde_json::json!({
+        "contents": contents,
+        "generationConfig": {
+            "responseModalities": ["TEXT", "IMAGE"],
+            "imageConfig": {
+                "aspectRatio": aspect_ratio,
+                "imageSize": image_size
+            }
+        }
+    });
+
+    eprintln!(
+        "Banana Pro API request: {} {}, prompt {:?}",
+        aspect_ratio, image_size, prompt
+    );
+
+    let url = format!(
+        "{}/v1beta/models/gemini-3.1-flash-image-preview:generateContent?key={}",
+        config.base_url, config.api_key
+    );
+
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .json(&request_body)
+        .send()
+        .await
+        .map_err(ProviderError::from_reqwest)?;
+
+    if !response.status().is_success() {
+        let error = ProviderError::from_http_response(response).await;
+        eprintln!("Banana Pro API error response: {}", error);
+        return Err(error);
+    }
+
+    let data: serde_json::Value = response.json().await.map_err(ProviderError::from_reqwest)?;
+
+    // Check for error in response
+    if let Some(error) = data.get("error") {
+        let message = format!("API返回错误: {:?}", error);
+        return Err(if error::is_content_policy_text(&message) {
+            ProviderError::ContentPolicy(message)
+        } else {
+            ProviderError::InvalidRequest(message)
+        });
+    }
+
+    // Gemini answers 200 with no image when moderation blocks the prompt or the output
+    if let Some(reason) = data["promptFeedback"]["blockReason"].as_str() {
+        return Err(ProviderError::ContentPolicy(format!("提示词被拦截: {}", reason)));
+    }
+
+    let images: Vec<String> = data["candidates"]
+        .as_array()
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|candidate| {
+                    candidate["content"]["parts"]
+                        .as_array()
+                        .and_then(|parts| {
+                            parts.iter().find_map(|part| {
+                                part["inlineData"]["data"].as_str().map(|s| {
+                                    let mime = part["inlineData"]["mimeType"].as_str().unwrap_or("image/png");
+                                    format!("data:{};base64,{}", mime, s)
+                                })
+                            })
+                        })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if images.is_empty() {
+        let blocked = data["candidates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|c| c["finishReason"].as_str())
+            .find(|r| matches!(*r, "SAFETY" | "IMAGE_SAFETY" | "PROHIBITED_CONTENT" | "BLOCKLIST"));
+        if let Some(reason) = blocked {
+            return Err(ProviderError::ContentPolicy(format!("生成结果被拦截: {}", reason)));
+        }
+        return Err("未生成图片".to_string().into());
+    }
+
+    Ok(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_size_prefers_requested_size() {
+        let mut params: ImageGenerationParams = serde_json::from_value(serde_json::json!({
+            "model": "banana_pro", "prompt": "p", "character_bindings": [], "width": 1536,
+            "height": 1536, "count": 2, "quality": "standard", "size": "4K"
+        }))
+        .unwrap();
+        assert_eq!(image_size(&params), "4K");
+        params.size = None;
+        assert_eq!(image_size(&params), "2K");
+    }
+}
@@ -0,0 +1,329 @@
+use serde::{Deserialize, Serialize};
+
+use crate::commands::image_generator::ImageGenerationParams;
+
+/// What a provider accepts. `generate_image` checks requests against this before any HTTP call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderCapabilities {
+    /// Accepted `size` values; empty means the provider does not use `size`.
+    pub sizes: Vec<String>,
+    /// Accepted `width:height` ratios; empty means any ratio.
+    #[serde(alias = "aspectRatios")]
+    pub aspect_ratios: Vec<String>,
+    #[serde(alias = "minDimension")]
+    pub min_dimension: u32,
+    #[serde(alias = "maxDimension")]
+    pub max_dimension: u32,
+    /// Width and height are rounded down to a multiple of this (e.g. 8 for latent diffusion).
+    #[serde(alias = "dimensionStep")]
+    pub dimension_step: u32,
+    #[serde(alias = "maxReferenceImages")]
+    pub max_reference_images: u32,
+    #[serde(alias = "maxCount")]
+    pub max_count: u32,
+    /// Whether `seed` reaches the backend and reproduces an image.
+    #[serde(default, alias = "supportsSeed")]
+    pub supports_seed: bool,
+}
+
+impl Default for ProviderCapabilities {
+    fn default() -> Self {
+        ProviderCapabilities {
+            sizes: Vec::new(),
+            aspect_ratios: Vec::new(),
+            min_dimension: 64,
+            max_dimension: 4096,
+            dimension_step: 1,
+            max_reference_images: 16,
+            max_count: 8,
+            supports_seed: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: String) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+impl ProviderCapabilities {
+    /// Normalizes `params` in place (count, `WxH` sizes, dimension step, aspect ratio
+    /// snapping) and returns every field that the provider cannot accept.
+    pub fn validate(&self, params: &mut ImageGenerationParams) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if params.prompt.trim().is_empty() {
+            errors.push(FieldError::new("prompt", "提示词不能为空".to_string()));
+        }
+
+        errors.extend(self.validate_output(
+            params.size.as_deref(),
+            &mut params.width,
+            &mut params.height,
+            &mut params.count,
+        ));
+
+        if !self.aspect_ratios.is_empty() && params.width > 0 && params.height > 0 {
+            match params.aspect_ratio.as_deref() {
+                Some(ratio) if !self.aspect_ratios.iter().any(|r| r == ratio) => {
+                    errors.push(FieldError::new(
+                        "aspect_ratio",
+                        format!(
+                            "不支持的宽高比 {}，可选: {}",
+                            ratio,
+                            self.aspect_ratios.join(", ")
+                        ),
+                    ));
+                }
+                requested => {
+                    let ratios: Vec<String> = match requested {
+                        Some(r) => vec![r.to_string()],
+                        None => self.aspect_ratios.clone(),
+                    };
+                    if let Some(snap) = snap_to_aspect_ratio(params.width, params.height, &ratios) {
+                        params.width = snap.width;
+                        params.height = snap.height;
+                        params.aspect_ratio = Some(snap.ratio);
+                    }
+                }
+            }
+        }
+
+        let reference_count = params
+            .character_bindings
+            .iter()
+            .filter(|b| b.reference_image_path.as_deref().is_some_and(|p| !p.is_empty()))
+            .count()
+            + params.images.as_ref().map_or(0, |imgs| imgs.len());
+        if reference_count > self.max_reference_images as usize {
+            errors.push(FieldError::new(
+                "character_bindings",
+                format!(
+                    "参考图数量 {} 超过上限 {}",
+                    reference_count, self.max_reference_images
+                ),
+            ));
+        }
+
+        errors
+    }
+
+    /// Checks and normalizes the output shape: count, `size` and width/height.
+    pub fn validate_output(
+        &self,
+        size: Option<&str>,
+        width: &mut u32,
+        height: &mut u32,
+        count: &mut u32,
+    ) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if *count == 0 {
+            *count = 1;
+        }
+        if *count > self.max_count {
+            errors.push(FieldError::new(
+                "count",
+                format!("生成数量不能超过 {}", self.max_count),
+            ));
+        }
+
+        if let Some(size) = size {
+            if !self.sizes.is_empty() && !self.sizes.iter().any(|s| s.eq_ignore_ascii_case(size)) {
+                errors.push(FieldError::new(
+                    "size",
+                    format!("不支持的尺寸 {}，可选: {}", size, self.sizes.join(", ")),
+                ));
+            }
+            if let Some((w, h)) = parse_size(size) {
+                *width = w;
+                *height = h;
+            }
+        }
+
+        if self.dimension_step > 1 {
+            *width -= *width % self.dimension_step;
+            *height -= *height % self.dimension_step;
+        }
+
+        for (field, value) in [("width", *width), ("height", *height)] {
+            if value < self.min_dimension || value > self.max_dimension {
+                errors.push(FieldError::new(
+                    field,
+                    format!(
+                        "取值 {} 超出范围 {}-{}",
+                        value, self.min_dimension, self.max_dimension
+                    ),
+                ));
+            }
+        }
+
+        errors
+    }
+}
+
+/// Parses `"1024x768"`-style sizes; named sizes such as `"2K"` return `None`.
+pub fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (w, h) = size.split_once(['x', 'X', '*'])?;
+    Some((w.trim().parse().ok()?, h.trim().parse().ok()?))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AspectSnap {
+    pub ratio: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Picks the supported `w:h` ratio closest to `width`x`height` and returns the
+/// effective size with the requested long edge kept.
+///
+/// Closeness is measured on the log of the ratio so that 2:1 and 1:2 are equally far from 1:1.
+pub fn snap_to_aspect_ratio(width: u32, height: u32, ratios: &[String]) -> Option<AspectSnap> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let requested = (width as f64 / height as f64).ln();
+
+    let (ratio, rw, rh) = ratios
+        .iter()
+        .filter_map(|r| {
+            let (w, h) = r.split_once(':')?;
+            let (w, h): (f64, f64) = (w.trim().parse().ok()?, h.trim().parse().ok()?);
+            (w > 0.0 && h > 0.0).then_some((r, w, h))
+        })
+        .min_by(|a, b| {
+            let da = ((a.1 / a.2).ln() - requested).abs();
+            let db = ((b.1 / b.2).ln() - requested).abs();
+            da.total_cmp(&db)
+        })?;
+
+    let long_edge = width.max(height) as f64;
+    let (w, h) = if rw >= rh {
+        (long_edge, long_edge * rh / rw)
+    } else {
+        (long_edge * rw / rh, long_edge)
+    };
+
+    Some(AspectSnap {
+        ratio: ratio.clone(),
+        width: (w.round() as u32).max(1),
+        height: (h.round() as u32).max(1),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::image_generator::CharacterBindingInfo;
+
+    fn params(width: u32, height: u32, size: Option<&str>) -> ImageGenerationParams {
+        ImageGenerationParams {
+            model: "test".to_string(),
+            prompt: "森林里的小狐狸".to_string(),
+            character_bindings: vec![],
+            width,
+            height,
+            count: 1,
+            quality: "standard".to_string(),
+            size: size.map(|s| s.to_string()),
+            sequential_image_generation: None,
+            response_format: None,
+            watermark: None,
+            images: None,
+            negative_prompt: None,
+            seed: None,
+            aspect_ratio: None,
+            priority: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_size_must_be_listed() {
+        let caps = ProviderCapabilities {
+            sizes: vec!["1K".to_string(), "2048x2048".to_string()],
+            ..Default::default()
+        };
+
+        let mut ok = params(1, 1, Some("2048x2048"));
+        assert!(caps.validate(&mut ok).is_empty());
+        assert_eq!((ok.width, ok.height), (2048, 2048));
+
+        let mut bad = params(1024, 1024, Some("999x999"));
+        let errors = caps.validate(&mut bad);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "size");
+    }
+
+    #[test]
+    fn test_field_errors_are_collected() {
+        let caps = ProviderCapabilities {
+            aspect_ratios: vec!["1:1".to_string()],
+            max_count: 2,
+            max_reference_images: 0,
+            ..Default::default()
+        };
+
+        let mut p = params(1024, 768, None);
+        p.prompt = "  ".to_string();
+        p.aspect_ratio = Some("4:3".to_string());
+        p.count = 4;
+        p.character_bindings.push(CharacterBindingInfo {
+            character_name: "小明".to_string(),
+            reference_image_path: Some("ref.png".to_string()),
+            image_type: "人物".to_string(),
+        });
+
+        let fields: Vec<String> = caps.validate(&mut p).into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, vec!["prompt", "count", "aspect_ratio", "character_bindings"]);
+    }
+
+    #[test]
+    fn test_snaps_to_nearest_ratio() {
+        let ratios: Vec<String> = ["1:1", "3:4", "4:3", "9:16", "16:9", "2:3", "3:2", "21:9"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let snap = snap_to_aspect_ratio(1000, 1001, &ratios).unwrap();
+        assert_eq!(snap, AspectSnap { ratio: "1:1".to_string(), width: 1001, height: 1001 });
+
+        let snap = snap_to_aspect_ratio(1920, 1000, &ratios).unwrap();
+        assert_eq!((snap.ratio.as_str(), snap.width, snap.height), ("16:9", 1920, 1080));
+
+        let snap = snap_to_aspect_ratio(700, 1000, &ratios).unwrap();
+        assert_eq!((snap.ratio.as_str(), snap.width, snap.height), ("2:3", 667, 1000));
+
+        let caps = ProviderCapabilities {
+            aspect_ratios: ratios,
+            ..Default::default()
+        };
+        let mut p = params(2480, 3508, None); // A4 at 300 DPI
+        assert!(caps.validate(&mut p).is_empty());
+        assert_eq!(p.aspect_ratio.as_deref(), Some("2:3"));
+        assert_eq!((p.width, p.height), (2339, 3508));
+    }
+
+    #[test]
+    fn test_normalizes_count_and_step() {
+        let caps = ProviderCapabilities {
+            dimension_step: 8,
+            ..Default::default()
+        };
+
+        let mut p = params(1001, 766, None);
+        p.count = 0;
+        assert!(caps.validate(&mut p).is_empty());
+        assert_eq!((p.width, p.height, p.count), (1000, 760, 1));
+    }
+}
@@ -0,0 +1,409 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::{GenerationRequest, ImageProvider, ProviderCapabilities, ProviderError};
+use crate::commands::image_files::{fetch_image_bytes, image_extension};
+use crate::commands::image_generator::{random_seed, ModelConfig};
+
+/// Drives a user-built ComfyUI graph saved in API format ("Save (API Format)").
+///
+/// String values in the workflow may contain placeholders, which are filled per call:
+/// - `{{prompt}}`, `{{negative_prompt}}`
+/// - `{{width}}`, `{{height}}`, `{{count}}`, `{{seed}}`
+/// - `{{image:<角色名>}}`: the uploaded reference image bound to that character
+/// - `{{image_0}}`, `{{image_1}}`, ...: uploaded reference images in binding order, followed
+///   by the request's extra `images`
+///
+/// A value that is exactly one numeric placeholder (e.g. `"{{width}}"`) becomes a JSON number.
+///
+/// Options read from `ModelConfig.options`:
+/// - `workflow_path`: the workflow JSON file (required)
+/// - `poll_interval_ms`: `/history` polling interval, defaults to 1000
+/// - `timeout_secs`: how long to wait for the queue, defaults to 600
+pub struct ComfyUiProvider;
+
+#[async_trait]
+impl ImageProvider for ComfyUiProvider {
+    fn id(&self) -> &'static str {
+        "comfyui"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "ComfyUI 工作流"
+    }
+
+    fn default_config(&self) -> ModelConfig {
+        let mut config = ModelConfig {
+            base_url: "http://127.0.0.1:8188".to_string(),
+            ..Default::default()
+        };
+        config
+            .options
+            .insert("workflow_path".to_string(), serde_json::json!(""));
+        config
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    fn max_concurrency(&self) -> u32 {
+        // ComfyUI executes its prompt queue serially; extra requests only wait server-side
+        1
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            max_dimension: 8192,
+            dimension_step: 8,
+            max_count: 16,
+            supports_seed: true,
+            ..Default::default()
+        }
+    }
+
+    async fn generate(
+        &self,
+        config: &ModelConfig,
+        request: &GenerationRequest,
+    ) -> Result<Vec<String>, ProviderError> {
+        let workflow_path = config
+            .option_str("workflow_path")
+            .ok_or_else(|| "请先选择ComfyUI工作流文件".to_string())?;
+        let workflow_json = std::fs::read_to_string(workflow_path)
+            .map_err(|e| format!("读取工作流失败: {}", e))?;
+        let workflow: serde_json::Value =
+            serde_json::from_str(&workflow_json).map_err(|e| format!("工作流JSON解析失败: {}", e))?;
+
+        let base_url = config.base_url.trim_end_matches('/');
+        let client = reqwest::Client::new();
+
+        let mut values = placeholder_values(request);
+        let mut index = 0;
+        for binding in &request.params.character_bindings {
+            let Some(path) = binding.reference_image_path.as_deref().filter(|p| !p.is_empty()) else {
+                continue;
+            };
+            let data = std::fs::read(path).map_err(|e| format!("读取参考图失败: {}", e))?;
+            let file_name = Path::new(path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| format!("reference.{}", image_extension(&data)));
+            let uploaded = upload_image(&client, base_url, data, &file_name).await?;
+            values.insert(format!("image:{}", binding.character_name), uploaded.clone().into());
+            values.insert(format!("image_{}", index), uploaded.into());
+            index += 1;
+        }
+        for image in request.params.images.iter().flatten() {
+            let data = match fetch_image_bytes(image).await {
+                Ok(data) => data,
+                // Plain base64 without a data URL prefix
+                Err(_) => STANDARD.decode(image).map_err(|e| format!("参考图解码失败: {}", e))?,
+            };
+            let file_name = format!("image.{}", image_extension(&data));
+            let uploaded = upload_image(&client, base_url, data, &file_name).await?;
+            values.insert(format!("image_{}", index), uploaded.into());
+            index += 1;
+        }
+
+        let workflow = fill_placeholders(workflow, &values);
+
+        let client_id = format!("xuanchen-huiben-{}", chrono::Utc::now().timestamp_millis());
+        let response = client
+            .post(format!("{}/prompt", base_url))
+            .json(&serde_json::json!({ "prompt": workflow, "client_id": client_id }))
+            .send()
+            .await
+            .map_err(ProviderError::from_reqwest)?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_http_response(response).await);
+        }
+
+        let queued: serde_json::Value = response.json().await.map_err(ProviderError::from_reqwest)?;
+        if let Some(errors) = queued.get("node_errors").and_then(|v| v.as_object()) {
+            if !errors.is_empty() {
+                return Err(ProviderError::InvalidRequest(format!(
+                    "工作流节点错误: {}",
+                    serde_json::Value::Object(errors.clone())
+                )));
+            }
+        }
+        let prompt_id = queued["prompt_id"]
+            .as_str()
+            .ok_or_else(|| "ComfyUI未返回prompt_id".to_string())?
+            .to_string();
+
+        let outputs = wait_for_outputs(&client, base_url, &prompt_id, config).await?;
+
+        let mut images = Vec::new();
+        for file in outputs {
+            let response = client
+                .get(format!("{}/view", base_url))
+                .query(&[
+                    ("filename", file.filename.as_str()),
+                    ("subfolder", file.subfolder.as_str()),
+                    ("type", file.kind.as_str()),
+                ])
+                .send()
+                .await
+                .map_err(ProviderError::from_reqwest)?;
+            if !response.status().is_success() {
+                return Err(ProviderError::from_http_response(response).await);
+            }
+            let bytes = response.bytes().await.map_err(ProviderError::from_reqwest)?;
+            images.push(format!(
+                "data:{};base64,{}",
+                mime_for(&file.filename),
+                STANDARD.encode(&bytes)
+            ));
+        }
+
+        if images.is_empty() {
+            return Err("未生成图片".to_string().into());
+        }
+
+        Ok(images)
+    }
+
+    async fn test_connection(&self, config: &ModelConfig) -> Result<bool, String> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let response = client
+            .get(format!("{}/system_stats", config.base_url.trim_end_matches('/')))
+            .send()
+            .await
+            .map_err(|e| format!("连接失败: {}", e))?;
+
+        if response.status().is_success() {
+            Ok(true)
+        } else {
+            Err(format!("API返回错误: {}", response.status()))
+        }
+    }
+}
+
+struct OutputFile {
+    filename: String,
+    subfolder: String,
+    kind: String,
+}
+
+fn placeholder_values(request: &GenerationRequest) -> HashMap<String, serde_json::Value> {
+    let params = &request.params;
+    let seed = params.seed.unwrap_or_else(random_seed);
+
+    let mut values = HashMap::new();
+    values.insert("prompt".to_string(), serde_json::json!(params.prompt));
+    values.insert(
+        "negative_prompt".to_string(),
+        serde_json::json!(params.negative_prompt.clone().unwrap_or_default()),
+    );
+    values.insert("width".to_string(), serde_json::json!(params.width));
+    values.insert("height".to_string(), serde_json::json!(params.height));
+    values.insert("count".to_string(), serde_json::json!(params.count.max(1)));
+    values.insert("seed".to_string(), serde_json::json!(seed));
+    values
+}
+
+/// Replaces `{{name}}` placeholders in every string of the workflow.
+fn fill_placeholders(
+    value: serde_json::Value,
+    values: &HashMap<String, serde_json::Value>,
+) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => {
+            let trimmed = s.trim();
+            if let Some(name) = trimmed.strip_prefix("{{").and_then(|t| t.strip_suffix("}}")) {
+                if !name.contains("{{") {
+                    if let Some(v) = values.get(name.trim()) {
+                        return v.clone();
+                    }
+                }
+            }
+
+            // One pass over the template, so substituted text (e.g. a prompt that itself
+            // contains `{{seed}}`) is never expanded again
+            let mut filled = String::with_capacity(s.len());
+            let mut rest = s.as_str();
+            while let Some(open) = rest.find("{{") {
+                filled.push_str(&rest[..open]);
+                let after = &rest[open + 2..];
+                let Some(close) = after.find("}}") else {
+                    rest = &rest[open..];
+                    break;
+                };
+                match values.get(after[..close].trim()) {
+                    Some(serde_json::Value::String(text)) => filled.push_str(text),
+                    Some(other) => filled.push_str(&other.to_string()),
+                    None => filled.push_str(&rest[open..open + 2 + close + 2]),
+                }
+                rest = &after[close + 2..];
+            }
+            filled.push_str(rest);
+            serde_json::Value::String(filled)
+        }
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .into_iter()
+                .map(|v| fill_placeholders(v, values))
+                .collect(),
+        ),
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, fill_placeholders(v, values)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Uploads a reference image and returns the name a `LoadImage` node expects. The name is
+/// prefixed with a content hash, so different files sharing a basename don't overwrite
+/// each other on the server.
+async fn upload_image(
+    client: &reqwest::Client,
+    base_url: &str,
+    data: Vec<u8>,
+    file_name: &str,
+) -> Result<String, ProviderError> {
+    let digest = Sha256::digest(&data);
+    let hash: String = digest[..6].iter().map(|b| format!("{:02x}", b)).collect();
+    let part = reqwest::multipart::Part::bytes(data)
+        .file_name(format!("{}_{}", hash, file_name))
+        .mime_str(mime_for(file_name))
+        .map_err(|e| e.to_string())?;
+    let form = reqwest::multipart::Form::new()
+        .part("image", part)
+        .text("overwrite", "true");
+
+    let response = client
+        .post(format!("{}/upload/image", base_url))
+        .multipart(form)
+        .send()
+        .await
+        .map_err(ProviderError::from_reqwest)?;
+
+    if !response.status().is_success() {
+        return Err(ProviderError::from_http_response(response).await);
+    }
+
+    let data: serde_json::Value = response.json().await.map_err(ProviderError::from_reqwest)?;
+    let name = data["name"]
+        .as_str()
+        .ok_or_else(|| "上传参考图失败: 缺少文件名".to_string())?;
+    match data["subfolder"].as_str() {
+        Some(sub) if !sub.is_empty() => Ok(format!("{}/{}", sub, name)),
+        _ => Ok(name.to_string()),
+    }
+}
+
+/// Polls `/history/{prompt_id}` until the prompt finishes and returns its image outputs.
+async fn wait_for_outputs(
+    client: &reqwest::Client,
+    base_url: &str,
+    prompt_id: &str,
+    config: &ModelConfig,
+) -> Result<Vec<OutputFile>, ProviderError> {
+    let interval = Duration::from_millis(config.option_u64("poll_interval_ms").unwrap_or(1000));
+    let timeout = Duration::from_secs(config.option_u64("timeout_secs").unwrap_or(600));
+    let started = Instant::now();
+
+    loop {
+        let response = client
+            .get(format!("{}/history/{}", base_url, prompt_id))
+            .send()
+            .await
+            .map_err(ProviderError::from_reqwest)?;
+        if !response.status().is_success() {
+            return Err(ProviderError::from_http_response(response).await);
+        }
+        let history: serde_json::Value = response.json().await.map_err(ProviderError::from_reqwest)?;
+
+        if let Some(entry) = history.get(prompt_id) {
+            if entry["status"]["status_str"].as_str() == Some("error") {
+                return Err(format!("ComfyUI执行失败: {}", entry["status"]["messages"]).into());
+            }
+
+            if let Some(outputs) = entry["outputs"].as_object() {
+                let mut files: Vec<OutputFile> = outputs
+                    .values()
+                    .filter_map(|node| node["images"].as_array())
+                    .flatten()
+                    .filter_map(|img| {
+                        Some(OutputFile {
+                            filename: img["filename"].as_str()?.to_string(),
+                            subfolder: img["subfolder"].as_str().unwrap_or("").to_string(),
+                            kind: img["type"].as_str().unwrap_or("output").to_string(),
+                        })
+                    })
+                    .collect();
+
+                // Preview nodes write "temp" images; only fall back to them when nothing was saved.
+                if files.iter().any(|f| f.kind == "output") {
+                    files.retain(|f| f.kind == "output");
+                }
+                return Ok(files);
+            }
+        }
+
+        if started.elapsed() > timeout {
+            return Err("ComfyUI任务超时".to_string().into());
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+fn mime_for(file_name: &str) -> &'static str {
+    let lower = file_name.to_lowercase();
+    if lower.ends_with(".jpg") || lower.ends_with(".jpeg") {
+        "image/jpeg"
+    } else if lower.ends_with(".webp") {
+        "image/webp"
+    } else {
+        "image/png"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_placeholders() {
+        let workflow = serde_json::json!({
+            "3": { "inputs": { "seed": "{{seed}}", "steps": 20 } },
+            "5": { "inputs": { "width": "{{width}}", "height": "{{ height }}", "batch_size": "{{count}}" } },
+            "6": { "inputs": { "text": "{{prompt}}, best quality" } },
+            "10": { "inputs": { "image": "{{image:小明}}" } },
+            "11": { "inputs": { "text": "{{unknown}} {{width}}px {{" } },
+        });
+
+        let mut values = HashMap::new();
+        values.insert("seed".to_string(), serde_json::json!(7));
+        values.insert("width".to_string(), serde_json::json!(1024));
+        values.insert("height".to_string(), serde_json::json!(768));
+        values.insert("count".to_string(), serde_json::json!(2));
+        values.insert("prompt".to_string(), serde_json::json!("a red fox {{seed}}"));
+        values.insert("image:小明".to_string(), serde_json::json!("xiaoming.png"));
+
+        let filled = fill_placeholders(workflow, &values);
+
+        assert_eq!(filled["3"]["inputs"]["seed"], 7);
+        assert_eq!(filled["3"]["inputs"]["steps"], 20);
+        assert_eq!(filled["5"]["inputs"]["width"], 1024);
+        assert_eq!(filled["5"]["inputs"]["height"], 768);
+        assert_eq!(filled["5"]["inputs"]["batch_size"], 2);
+        // Placeholders inside substituted text stay as typed
+        assert_eq!(filled["6"]["inputs"]["text"], "a red fox {{seed}}, best quality");
+        assert_eq!(filled["10"]["inputs"]["image"], "xiaoming.png");
+        assert_eq!(filled["11"]["inputs"]["text"], "{{unknown}} 1024px {{");
+    }
+}
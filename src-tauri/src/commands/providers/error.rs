@@ -0,0 +1,214 @@
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// Longest `Retry-After` taken at face value; anything above is clamped. The retry policy
+/// gives up on waits this long anyway.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Body fragments that relays use to report a moderation block on an otherwise ordinary 400.
+const CONTENT_POLICY_MARKERS: &[&str] = &[
+    "content_policy",
+    "moderation",
+    "sensitivecontent",
+    "safety",
+    "prohibited_content",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderErrorKind {
+    RateLimited,
+    Transient,
+    Auth,
+    ContentPolicy,
+    InvalidRequest,
+    Other,
+}
+
+/// A failed provider call, classified so `generate_image` knows whether retrying can help.
+#[derive(Debug, Clone)]
+pub enum ProviderError {
+    /// 429; `retry_after` comes from the `Retry-After` header when the server sent one.
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// 5xx, timeouts and dropped connections.
+    Transient(String),
+    /// 401/403: wrong or expired key.
+    Auth(String),
+    /// The prompt or the output was blocked by the provider's moderation.
+    ContentPolicy(String),
+    /// Any other 4xx: the request itself is wrong and will fail again.
+    InvalidRequest(String),
+    /// Local failures (unreadable workflow, malformed response, ...).
+    Other(String),
+}
+
+impl ProviderError {
+    pub fn kind(&self) -> ProviderErrorKind {
+        match self {
+            ProviderError::RateLimited { .. } => ProviderErrorKind::RateLimited,
+            ProviderError::Transient(_) => ProviderErrorKind::Transient,
+            ProviderError::Auth(_) => ProviderErrorKind::Auth,
+            ProviderError::ContentPolicy(_) => ProviderErrorKind::ContentPolicy,
+            ProviderError::InvalidRequest(_) => ProviderErrorKind::InvalidRequest,
+            ProviderError::Other(_) => ProviderErrorKind::Other,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ProviderError::RateLimited { .. } | ProviderError::Transient(_)
+        )
+    }
+
+    /// Classifies a non-2xx response from its status, headers and body text.
+    pub fn from_response(status: StatusCode, headers: &HeaderMap, body: &str) -> Self {
+        let message = format!("API错误 {}: {}", status, body);
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return ProviderError::RateLimited {
+                retry_after: parse_retry_after(headers),
+                message,
+            };
+        }
+        if status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_EARLY
+        {
+            return ProviderError::Transient(message);
+        }
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return ProviderError::Auth(message);
+        }
+        if is_content_policy_text(body) {
+            return ProviderError::ContentPolicy(message);
+        }
+        if status.is_client_error() {
+            return ProviderError::InvalidRequest(message);
+        }
+        ProviderError::Other(message)
+    }
+
+    /// Reads a non-2xx response to the end and classifies it.
+    pub async fn from_http_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let text = response.text().await.unwrap_or_default();
+        Self::from_response(status, &headers, &text)
+    }
+
+    /// Classifies a send failure: timeouts and connection errors are worth retrying.
+    pub fn from_reqwest(error: reqwest::Error) -> Self {
+        let message = format!("请求失败: {}", error);
+        if error.is_timeout() || error.is_connect() || error.is_request() {
+            ProviderError::Transient(message)
+        } else {
+            ProviderError::Other(message)
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            ProviderError::RateLimited { message, .. }
+            | ProviderError::Transient(message)
+            | ProviderError::Auth(message)
+            | ProviderError::ContentPolicy(message)
+            | ProviderError::InvalidRequest(message)
+            | ProviderError::Other(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self {
+            ProviderError::RateLimited { .. } => "请求过于频繁",
+            ProviderError::Transient(_) => "服务暂时不可用",
+            ProviderError::Auth(_) => "鉴权失败，请检查API Key",
+            ProviderError::ContentPolicy(_) => "内容被安全策略拦截",
+            ProviderError::InvalidRequest(_) => "请求参数错误",
+            ProviderError::Other(_) => return f.write_str(self.message()),
+        };
+        write!(f, "{}: {}", prefix, self.message())
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl From<String> for ProviderError {
+    fn from(message: String) -> Self {
+        ProviderError::Other(message)
+    }
+}
+
+impl From<ProviderError> for String {
+    fn from(error: ProviderError) -> Self {
+        error.to_string()
+    }
+}
+
+/// True when a provider message names a moderation block.
+pub fn is_content_policy_text(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    CONTENT_POLICY_MARKERS.iter().any(|m| lower.contains(m))
+}
+
+/// `Retry-After` in delta-seconds form. HTTP dates are rare on these APIs and fall back to backoff.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| *secs >= 0.0)
+        .map(|secs| Duration::try_from_secs_f64(secs).map_or(MAX_RETRY_AFTER, |d| d.min(MAX_RETRY_AFTER)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_classifies_statuses() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+
+        match ProviderError::from_response(StatusCode::TOO_MANY_REQUESTS, &headers, "slow down") {
+            ProviderError::RateLimited { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(7)))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // An absurd value must not panic the generation task
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("1e30"));
+        assert_eq!(parse_retry_after(&headers), Some(MAX_RETRY_AFTER));
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("NaN"));
+        assert_eq!(parse_retry_after(&headers), None);
+
+        let none = HeaderMap::new();
+        let kind = |status, body| ProviderError::from_response(status, &none, body).kind();
+        assert_eq!(kind(StatusCode::BAD_GATEWAY, ""), ProviderErrorKind::Transient);
+        assert_eq!(kind(StatusCode::UNAUTHORIZED, ""), ProviderErrorKind::Auth);
+        assert_eq!(
+            kind(
+                StatusCode::BAD_REQUEST,
+                r#"{"error":{"code":"InputTextSensitiveContentDetected"}}"#
+            ),
+            ProviderErrorKind::ContentPolicy
+        );
+        assert_eq!(
+            kind(StatusCode::BAD_REQUEST, r#"{"error":"size is invalid"}"#),
+            ProviderErrorKind::InvalidRequest
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::{ImageOutputFormat, Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+
+use super::capabilities::parse_size;
+use super::{GenerationRequest, ImageProvider, ProviderCapabilities, ProviderError};
+use crate::commands::image_generator::ModelConfig;
+
+/// Largest edge the mock will render, so a typo in `size` cannot allocate gigabytes.
+const MAX_EDGE: u32 = 4096;
+
+/// Offline provider that renders deterministic placeholder PNGs.
+///
+/// Each image is a canvas tinted by the prompt hash with an 8x8 block pattern of the
+/// same hash, at the requested size. The same prompt, seed and index always give the
+/// same bytes, so demos and tests can run the whole flow without network or keys.
+pub struct MockProvider;
+
+#[async_trait]
+impl ImageProvider for MockProvider {
+    fn id(&self) -> &'static str {
+        "mock"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "离线演示 (Mock)"
+    }
+
+    fn default_config(&self) -> ModelConfig {
+        ModelConfig::default()
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    fn max_concurrency(&self) -> u32 {
+        16
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            min_dimension: 1,
+            max_dimension: MAX_EDGE,
+            supports_seed: true,
+            ..Default::default()
+        }
+    }
+
+    async fn generate(
+        &self,
+        _config: &ModelConfig,
+        request: &GenerationRequest,
+    ) -> Result<Vec<String>, ProviderError> {
+        let params = &request.params;
+        let (width, height) = params
+            .size
+            .as_deref()
+            .and_then(parse_size)
+            .unwrap_or((params.width, params.height));
+
+        if width == 0 || height == 0 {
+            return Err(ProviderError::InvalidRequest("图片尺寸无效".to_string()));
+        }
+
+        (0..params.count.max(1))
+            .map(|index| {
+                let digest = prompt_digest(&request.prompt, params.seed, index);
+                let png = render_placeholder(&digest, width.min(MAX_EDGE), height.min(MAX_EDGE))?;
+                Ok(format!("data:image/png;base64,{}", STANDARD.encode(png)))
+            })
+            .collect()
+    }
+
+    async fn test_connection(&self, _config: &ModelConfig) -> Result<bool, String> {
+        Ok(true)
+    }
+}
+
+fn prompt_digest(prompt: &str, seed: Option<i64>, index: u32) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(prompt.as_bytes());
+    hasher.update(seed.unwrap_or(0).to_le_bytes());
+    hasher.update(index.to_le_bytes());
+    hasher.finalize().into()
+}
+
+fn render_placeholder(digest: &[u8; 32], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let background = Rgb([digest[0], digest[1], digest[2]]);
+    // Inverted tint keeps the pattern readable on any background
+    let foreground = Rgb([255 - digest[0], 255 - digest[1], 255 - digest[2]]);
+
+    let cell = (width.min(height) / 10).max(1);
+    let origin_x = width.saturating_sub(cell * 8) / 2;
+    let origin_y = height.saturating_sub(cell * 8) / 2;
+
+    let img = RgbImage::from_fn(width, height, |x, y| {
+        if x < origin_x || y < origin_y {
+            return background;
+        }
+        let (col, row) = ((x - origin_x) / cell, (y - origin_y) / cell);
+        if col >= 8 || row >= 8 {
+            return background;
+        }
+        // 64 cells, one bit each, taken from bytes 3..11 of the digest
+        let bit = digest[3 + row as usize] >> col & 1;
+        if bit == 1 {
+            foreground
+        } else {
+            background
+        }
+    });
+
+    let mut buf = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(img)
+        .write_to(&mut buf, ImageOutputFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(buf.into_inner())
+}
@@ -0,0 +1,138 @@
+pub mod banana_pro;
+pub mod capabilities;
+pub mod comfyui;
+pub mod error;
+pub mod mock;
+pub mod openai_compatible;
+pub mod retry;
+pub mod sd_webui;
+pub mod seedream;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::image_generator::{ImageGenerationParams, ModelConfig};
+pub use capabilities::{FieldError, ProviderCapabilities};
+pub use error::{ProviderError, ProviderErrorKind};
+pub use retry::RetryPolicy;
+
+/// Everything a provider needs for one generation call. Reference images have
+/// already been loaded and base64-encoded by `generate_image`.
+#[derive(Debug, Clone)]
+pub struct GenerationRequest {
+    pub prompt: String,
+    pub params: ImageGenerationParams,
+    pub images: Option<Vec<String>>,
+}
+
+#[async_trait]
+pub trait ImageProvider: Send + Sync {
+    /// Stable id used as `ImageGenerationParams.model` and as the key in `ApiConfig.providers`.
+    fn id(&self) -> &'static str;
+
+    fn display_name(&self) -> &'static str;
+
+    fn default_config(&self) -> ModelConfig;
+
+    fn requires_api_key(&self) -> bool {
+        true
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities;
+
+    /// Generations the queue lets run at once; `ModelConfig.max_concurrency` overrides it.
+    fn max_concurrency(&self) -> u32 {
+        2
+    }
+
+    /// One call to the backend. Failures are classified so the caller can retry
+    /// rate limits and transient errors under `ModelConfig.retry`.
+    async fn generate(
+        &self,
+        config: &ModelConfig,
+        request: &GenerationRequest,
+    ) -> Result<Vec<String>, ProviderError>;
+
+    async fn test_connection(&self, config: &ModelConfig) -> Result<bool, String> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let test_url = format!("{}/v1/models", config.base_url);
+
+        let response = client
+            .get(&test_url)
+            .header("Authorization", format!("Bearer {}", config.api_key))
+            .send()
+            .await
+            .map_err(|e| format!("连接失败: {}", e))?;
+
+        if response.status().is_success() || response.status().as_u16() == 401 {
+            Ok(true)
+        } else {
+            Err(format!("API返回错误: {}", response.status()))
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn ImageProvider>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a provider, replacing any earlier one with the same id.
+    pub fn register(&mut self, provider: Arc<dyn ImageProvider>) {
+        self.providers.retain(|p| p.id() != provider.id());
+        self.providers.push(provider);
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<dyn ImageProvider>> {
+        self.providers.iter().find(|p| p.id() == id).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn ImageProvider>> {
+        self.providers.iter()
+    }
+}
+
+pub static PROVIDER_REGISTRY: Lazy<ProviderRegistry> = Lazy::new(|| {
+    let mut registry = ProviderRegistry::new();
+    registry.register(Arc::new(seedream::SeedreamProvider));
+    registry.register(Arc::new(banana_pro::BananaProProvider));
+    registry.register(Arc::new(openai_compatible::OpenAiCompatibleProvider));
+    registry.register(Arc::new(sd_webui::SdWebUiProvider));
+    registry.register(Arc::new(comfyui::ComfyUiProvider));
+    registry.register(Arc::new(mock::MockProvider));
+    registry
+});
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderInfo {
+    pub id: String,
+    #[serde(alias = "displayName")]
+    pub display_name: String,
+    #[serde(alias = "requiresApiKey")]
+    pub requires_api_key: bool,
+    pub capabilities: ProviderCapabilities,
+}
+
+#[tauri::command]
+pub fn list_image_providers() -> Vec<ProviderInfo> {
+    PROVIDER_REGISTRY
+        .iter()
+        .map(|p| ProviderInfo {
+            id: p.id().to_string(),
+            display_name: p.display_name().to_string(),
+            requires_api_key: p.requires_api_key(),
+            capabilities: p.capabilities(),
+        })
+        .collect()
+}
@@ -0,0 +1,157 @@
+use async_trait::async_trait;
+
+use super::{GenerationRequest, ImageProvider, ProviderCapabilities, ProviderError};
+use crate::commands::image_generator::ModelConfig;
+
+/// Any relay that speaks the OpenAI `/v1/images/generations` schema.
+///
+/// Options read from `ModelConfig.options`:
+/// - `model`: model id sent to the relay (required)
+/// - `response_format`: `"url"` or `"b64_json"`, overrides the request's value
+/// - `extra_body`: JSON object merged into the request body for relay-specific fields
+pub struct OpenAiCompatibleProvider;
+
+#[async_trait]
+impl ImageProvider for OpenAiCompatibleProvider {
+    fn id(&self) -> &'static str {
+        "openai_compatible"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "OpenAI 兼容接口"
+    }
+
+    fn default_config(&self) -> ModelConfig {
+        let mut config = ModelConfig {
+            base_url: "https://api.openai.com".to_string(),
+            ..Default::default()
+        };
+        config
+            .options
+            .insert("model".to_string(), serde_json::json!(""));
+        config
+            .options
+            .insert("response_format".to_string(), serde_json::json!("url"));
+        config
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Relays differ too much to list sizes; leave that check to the relay itself.
+        ProviderCapabilities {
+            max_count: 10,
+            ..Default::default()
+        }
+    }
+
+    async fn generate(
+        &self,
+        config: &ModelConfig,
+        request: &GenerationRequest,
+    ) -> Result<Vec<String>, ProviderError> {
+        let model = config
+            .option_str("model")
+            .ok_or_else(|| ProviderError::InvalidRequest("请先配置模型ID".to_string()))?;
+        let params = &request.params;
+
+        let mut request_body = serde_json::json!({
+            "model": model,
+            "prompt": request.prompt,
+            "n": params.count.max(1),
+            "size": params
+                .size
+                .clone()
+                .unwrap_or_else(|| format!("{}x{}", params.width, params.height)),
+        });
+
+        let response_format = config
+            .option_str("response_format")
+            .map(|s| s.to_string())
+            .or_else(|| params.response_format.clone());
+        if let Some(rf) = response_format {
+            request_body["response_format"] = serde_json::json!(rf);
+        }
+
+        if let Some(imgs) = &request.images {
+            if !imgs.is_empty() {
+                request_body["image"] = serde_json::json!(to_data_uris(imgs));
+            }
+        }
+
+        if let Some(extra) = config.options.get("extra_body").and_then(|v| v.as_object()) {
+            for (key, value) in extra {
+                request_body[key] = value.clone();
+            }
+        }
+
+        post_images_generation(config, &request_body).await
+    }
+}
+
+/// Reference images must be sent as full data URIs on this schema.
+pub(super) fn to_data_uris(images: &[String]) -> Vec<String> {
+    images
+        .iter()
+        .map(|b64| {
+            if b64.starts_with("data:") {
+                b64.clone()
+            } else {
+                format!("data:image/png;base64,{}", b64)
+            }
+        })
+        .collect()
+}
+
+/// Sends a prepared body to `{base_url}/v1/images/generations` and collects the results.
+pub(super) async fn post_images_generation(
+    config: &ModelConfig,
+    request_body: &serde_json::Value,
+) -> Result<Vec<String>, ProviderError> {
+    let client = reqwest::Client::new();
+
+    eprintln!(
+        "Images API request: model {:?}, prompt {:?}",
+        request_body["model"], request_body["prompt"]
+    );
+
+    let response = client
+        .post(format!(
+            "{}/v1/images/generations",
+            config.base_url.trim_end_matches('/')
+        ))
+        .header("Authorization", format!("Bearer {}", config.api_key))
+        .header("Content-Type", "application/json")
+        .json(request_body)
+        .send()
+        .await
+        .map_err(ProviderError::from_reqwest)?;
+
+    if !response.status().is_success() {
+        return Err(ProviderError::from_http_response(response).await);
+    }
+
+    let data: serde_json::Value = response.json().await.map_err(ProviderError::from_reqwest)?;
+
+    let images = parse_images_response(&data);
+    eprintln!("Images API response: {} image(s)", images.len());
+    Ok(images)
+}
+
+/// Reads `data[]` entries, accepting either `url` or `b64_json` (returned as a data URI).
+pub(super) fn parse_images_response(data: &serde_json::Value) -> Vec<String> {
+    data["data"]
+        .as_array()
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|item| {
+                    if let Some(url) = item["url"].as_str() {
+                        Some(url.to_string())
+                    } else {
+                        item["b64_json"]
+                            .as_str()
+                            .map(|b64| format!("data:image/png;base64,{}", b64))
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
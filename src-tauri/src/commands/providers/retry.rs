@@ -0,0 +1,96 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::ProviderError;
+
+/// How `generate_image` retries rate-limited and transient provider failures.
+/// Stored per provider in `ModelConfig.retry`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total calls including the first one; 1 disables retrying.
+    #[serde(alias = "maxAttempts")]
+    pub max_attempts: u32,
+    #[serde(alias = "initialDelayMs")]
+    pub initial_delay_ms: u64,
+    #[serde(alias = "maxDelayMs")]
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_delay_ms: 1000,
+            max_delay_ms: 30_000,
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the next call after `attempt` (1-based) failed with `error`,
+    /// or `None` when the error is final or the attempts are used up.
+    ///
+    /// A server-sent `Retry-After` wins over backoff; one longer than `max_delay_ms` gives up
+    /// rather than hammering the server early.
+    pub fn delay_after(&self, attempt: u32, error: &ProviderError) -> Option<Duration> {
+        if !error.is_retryable() || attempt >= self.max_attempts {
+            return None;
+        }
+        let max_delay = Duration::from_millis(self.max_delay_ms);
+
+        if let ProviderError::RateLimited {
+            retry_after: Some(wait),
+            ..
+        } = error
+        {
+            return (*wait <= max_delay).then_some(*wait);
+        }
+
+        let backoff = self.backoff(attempt);
+        // Equal jitter: keep half the backoff, randomize the rest so parallel tasks spread out
+        let half = backoff / 2;
+        let jitter = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+        Some(half + Duration::from_millis(jitter))
+    }
+
+    /// Exponential backoff without jitter, capped at `max_delay_ms`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.multiplier.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        let ms = (self.initial_delay_ms as f64 * exp).min(self.max_delay_ms as f64);
+        Duration::from_millis(ms as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_respects_limits() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            initial_delay_ms: 100,
+            max_delay_ms: 300,
+            multiplier: 2.0,
+        };
+        let transient = ProviderError::Transient("502".to_string());
+
+        for (attempt, full) in [(1, 100), (2, 200), (3, 300)] {
+            let delay = policy.delay_after(attempt, &transient).unwrap();
+            assert!(delay >= Duration::from_millis(full / 2) && delay <= Duration::from_millis(full));
+        }
+        assert_eq!(policy.delay_after(4, &transient), None);
+        assert_eq!(policy.delay_after(1, &ProviderError::Auth("401".to_string())), None);
+
+        let limited = |ms| ProviderError::RateLimited {
+            retry_after: Some(Duration::from_millis(ms)),
+            message: String::new(),
+        };
+        assert_eq!(policy.delay_after(1, &limited(250)), Some(Duration::from_millis(250)));
+        assert_eq!(policy.delay_after(1, &limited(5000)), None);
+    }
+}
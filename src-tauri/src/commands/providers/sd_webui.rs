@@ -0,0 +1,328 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+use super::{GenerationRequest, ImageProvider, ProviderCapabilities, ProviderError};
+use crate::commands::image_generator::ModelConfig;
+
+/// Local Stable Diffusion WebUI (A1111 / Forge) through its `/sdapi/v1` API.
+///
+/// Bound character references become `init_images` and switch the call to img2img.
+/// `api_key`, when set, is sent as HTTP basic auth (`user:password`, as for `--api-auth`).
+///
+/// Options read from `ModelConfig.options`:
+/// - `steps`, `cfg_scale`, `sampler_name`
+/// - `denoising_strength`: img2img strength, defaults to 0.75
+/// - `checkpoint`: overrides `sd_model_checkpoint` for this call only
+pub struct SdWebUiProvider;
+
+#[async_trait]
+impl ImageProvider for SdWebUiProvider {
+    fn id(&self) -> &'static str {
+        "sd_webui"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Stable Diffusion WebUI"
+    }
+
+    fn default_config(&self) -> ModelConfig {
+        let mut config = ModelConfig {
+            base_url: "http://127.0.0.1:7860".to_string(),
+            ..Default::default()
+        };
+        config
+            .options
+            .insert("steps".to_string(), serde_json::json!(20));
+        config
+            .options
+            .insert("cfg_scale".to_string(), serde_json::json!(7.0));
+        config
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    fn max_concurrency(&self) -> u32 {
+        // The local WebUI renders one job at a time; extra requests only wait server-side
+        1
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            dimension_step: 8,
+            max_reference_images: 1,
+            max_count: 16,
+            supports_seed: true,
+            ..Default::default()
+        }
+    }
+
+    async fn generate(
+        &self,
+        config: &ModelConfig,
+        request: &GenerationRequest,
+    ) -> Result<Vec<String>, ProviderError> {
+        let init_images: Vec<String> = request
+            .images
+            .iter()
+            .flatten()
+            .map(|img| strip_data_uri(img).to_string())
+            .collect();
+
+        let body = build_request_body(config, request, &init_images);
+        let endpoint = if init_images.is_empty() {
+            "txt2img"
+        } else {
+            "img2img"
+        };
+
+        eprintln!("SD WebUI {} request: {:?}", endpoint, body["prompt"]);
+
+        let response = with_auth(
+            reqwest::Client::new().post(format!(
+                "{}/sdapi/v1/{}",
+                config.base_url.trim_end_matches('/'),
+                endpoint
+            )),
+            config,
+        )
+        .json(&body)
+        .send()
+        .await
+        .map_err(ProviderError::from_reqwest)?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_http_response(response).await);
+        }
+
+        let data: serde_json::Value = response.json().await.map_err(ProviderError::from_reqwest)?;
+
+        let mut images: Vec<String> = data["images"]
+            .as_array()
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|v| v.as_str())
+                    .map(|b64| format!("data:image/png;base64,{}", b64))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // With "return grid" enabled the WebUI prepends a contact sheet; keep only the real outputs.
+        let wanted = request.params.count.max(1) as usize;
+        if images.len() > wanted {
+            images.drain(..images.len() - wanted);
+        }
+
+        if images.is_empty() {
+            return Err("未生成图片".to_string().into());
+        }
+
+        Ok(images)
+    }
+
+    async fn test_connection(&self, config: &ModelConfig) -> Result<bool, String> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let response = with_auth(
+            client.get(format!(
+                "{}/sdapi/v1/sd-models",
+                config.base_url.trim_end_matches('/')
+            )),
+            config,
+        )
+        .send()
+        .await
+        .map_err(|e| format!("连接失败: {}", e))?;
+
+        if response.status().is_success() {
+            Ok(true)
+        } else {
+            Err(format!("API返回错误: {}", response.status()))
+        }
+    }
+}
+
+fn build_request_body(
+    config: &ModelConfig,
+    request: &GenerationRequest,
+    init_images: &[String],
+) -> serde_json::Value {
+    let params = &request.params;
+
+    let mut body = serde_json::json!({
+        "prompt": params.prompt,
+        "negative_prompt": params.negative_prompt.clone().unwrap_or_default(),
+        "width": params.width,
+        "height": params.height,
+        "n_iter": params.count.max(1),
+        "batch_size": 1,
+        "seed": params.seed.unwrap_or(-1),
+        "steps": config.option_u64("steps").unwrap_or(20),
+        "cfg_scale": config.options.get("cfg_scale").and_then(|v| v.as_f64()).unwrap_or(7.0),
+        "send_images": true,
+        "save_images": false,
+    });
+
+    if let Some(sampler) = config.option_str("sampler_name") {
+        body["sampler_name"] = serde_json::json!(sampler);
+    }
+
+    if let Some(checkpoint) = config.option_str("checkpoint") {
+        body["override_settings"] = serde_json::json!({ "sd_model_checkpoint": checkpoint });
+    }
+
+    if !init_images.is_empty() {
+        body["init_images"] = serde_json::json!(init_images);
+        body["denoising_strength"] = serde_json::json!(config
+            .options
+            .get("denoising_strength")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.75));
+    }
+
+    body
+}
+
+fn strip_data_uri(image: &str) -> &str {
+    if image.starts_with("data:") {
+        image.split(',').nth(1).unwrap_or(image)
+    } else {
+        image
+    }
+}
+
+fn with_auth(builder: reqwest::RequestBuilder, config: &ModelConfig) -> reqwest::RequestBuilder {
+    if config.api_key.is_empty() {
+        return builder;
+    }
+    builder.header(
+        "Authorization",
+        format!("Basic {}", STANDARD.encode(&config.api_key)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::image_generator::ImageGenerationParams;
+    use std::sync::{Arc, Mutex};
+
+    type Captured = Arc<Mutex<Vec<(String, serde_json::Value)>>>;
+
+    /// Starts a stand-in WebUI that records each request and answers with `returned` images.
+    async fn spawn_stand_in(returned: usize) -> (String, Captured) {
+        use axum::{extract::State, routing::post, Json, Router};
+
+        let captured: Captured = Arc::new(Mutex::new(Vec::new()));
+
+        async fn handle(
+            State((captured, endpoint, returned)): State<(Captured, &'static str, usize)>,
+            Json(body): Json<serde_json::Value>,
+        ) -> Json<serde_json::Value> {
+            captured.lock().unwrap().push((endpoint.to_string(), body));
+            let images: Vec<String> = (0..returned).map(|i| format!("img{}", i)).collect();
+            Json(serde_json::json!({ "images": images, "info": "{}" }))
+        }
+
+        let app = Router::new()
+            .route(
+                "/sdapi/v1/txt2img",
+                post(handle).with_state((captured.clone(), "txt2img", returned)),
+            )
+            .route(
+                "/sdapi/v1/img2img",
+                post(handle).with_state((captured.clone(), "img2img", returned)),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        (format!("http://{}", addr), captured)
+    }
+
+    fn request(images: Option<Vec<String>>) -> GenerationRequest {
+        GenerationRequest {
+            prompt: "a fox in the forest".to_string(),
+            params: ImageGenerationParams {
+                model: "sd_webui".to_string(),
+                prompt: "a fox in the forest".to_string(),
+                character_bindings: vec![],
+                width: 768,
+                height: 512,
+                count: 2,
+                quality: "standard".to_string(),
+                size: None,
+                sequential_image_generation: None,
+                response_format: None,
+                watermark: None,
+                images: None,
+                negative_prompt: Some("blurry".to_string()),
+                seed: Some(42),
+                aspect_ratio: None,
+                priority: Default::default(),
+            },
+            images,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_txt2img_maps_params() {
+        let (base_url, captured) = spawn_stand_in(2).await;
+        let config = ModelConfig {
+            base_url,
+            ..SdWebUiProvider.default_config()
+        };
+
+        let images = SdWebUiProvider
+            .generate(&config, &request(None))
+            .await
+            .unwrap();
+
+        assert_eq!(images, vec!["data:image/png;base64,img0", "data:image/png;base64,img1"]);
+
+        let captured = captured.lock().unwrap();
+        let (endpoint, body) = &captured[0];
+        assert_eq!(endpoint, "txt2img");
+        assert_eq!(body["prompt"], "a fox in the forest");
+        assert_eq!(body["negative_prompt"], "blurry");
+        assert_eq!(body["width"], 768);
+        assert_eq!(body["height"], 512);
+        assert_eq!(body["n_iter"], 2);
+        assert_eq!(body["seed"], 42);
+        assert!(body.get("init_images").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_references_switch_to_img2img() {
+        let (base_url, captured) = spawn_stand_in(3).await;
+        let config = ModelConfig {
+            base_url,
+            ..SdWebUiProvider.default_config()
+        };
+
+        let images = SdWebUiProvider
+            .generate(
+                &config,
+                &request(Some(vec!["data:image/png;base64,AAAA".to_string()])),
+            )
+            .await
+            .unwrap();
+
+        // The leading grid image is dropped
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0], "data:image/png;base64,img1");
+
+        let captured = captured.lock().unwrap();
+        let (endpoint, body) = &captured[0];
+        assert_eq!(endpoint, "img2img");
+        assert_eq!(body["init_images"], serde_json::json!(["AAAA"]));
+        assert_eq!(body["denoising_strength"], 0.75);
+    }
+}
@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+
+use super::openai_compatible::{post_images_generation, to_data_uris};
+use super::{GenerationRequest, ImageProvider, ProviderCapabilities, ProviderError};
+use crate::commands::image_generator::ModelConfig;
+
+pub struct SeedreamProvider;
+
+#[async_trait]
+impl ImageProvider for SeedreamProvider {
+    fn id(&self) -> &'static str {
+        "seedream"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Seedream 4.5"
+    }
+
+    fn default_config(&self) -> ModelConfig {
+        ModelConfig {
+            base_url: "https://eggfans.com".to_string(),
+            api_key: "".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            sizes: [
+                "1K", "2K", "4K", "1024x1024", "2048x2048", "4096x4096", "2304x1728",
+                "1728x2304", "2560x1440", "1440x2560", "2496x1664", "1664x2496", "3024x1296",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            min_dimension: 256,
+            max_reference_images: 10,
+            max_count: 15,
+            ..Default::default()
+        }
+    }
+
+    async fn generate(
+        &self,
+        config: &ModelConfig,
+        request: &GenerationRequest,
+    ) -> Result<Vec<String>, ProviderError> {
+        let params = &request.params;
+        call_seedream_api(
+            config,
+            &request.prompt,
+            params.size.clone(),
+            params.sequential_image_generation.clone(),
+            params.response_format.clone(),
+            params.watermark,
+            request.images.clone(),
+        )
+        .await
+    }
+}
+
+async fn call_seedream_api(
+    config: &ModelConfig,
+    prompt: &str,
+    size: Option<String>,
+    sequential_image_generation: Option<String>,
+    response_format: Option<String>,
+    watermark: Option<bool>,
+    images: Option<Vec<String>>,
+) -> Result<Vec<String>, ProviderError> {
+    let mut request_body = serde_json::json!({
+        "model": "doubao-seedream-4-0-250828",
+        "prompt": prompt,
+    });
+
+    if let Some(s) = size {
+        request_body["size"] = serde_json::json!(s);
+    } else {
+        request_body["size"] = serde_json::json!("2K");
+    }
+
+    if let Some(s) = sequential_image_generation {
+        request_body["sequential_image_generation"] = serde_json::json!(s);
+        if s == "auto" {
+            request_body["sequential_image_generation_options"] = serde_json::json!({
+                "max_images": 3
+            });
+        }
+    } else {
+        request_body["sequential_image_generation"] = serde_json::json!("auto");
+        request_body["sequential_image_generation_options"] = serde_json::json!({
+            "max_images": 3
+        });
+    }
+
+    if let Some(rf) = response_format {
+        request_body["response_format"] = serde_json::json!(rf);
+    }
+
+    if let Some(w) = watermark {
+        request_body["watermark"] = serde_json::json!(w);
+    } else {
+        request_body["watermark"] = serde_json::json!(false);
+    }
+
+    if let Some(imgs) = images {
+        if !imgs.is_empty() {
+            request_body["image"] = serde_json::json!(to_data_uris(&imgs));
+        }
+    }
+
+    post_images_generation(config, &request_body).await
+}
@@ -0,0 +1,490 @@
+use ab_glyph::{point, Font, PxScale};
+use image::{imageops, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::image_files;
+use super::image_generator;
+use super::providers::FieldError;
+use super::typesetting::TextFont;
+use crate::error::{AppError, AppResult};
+
+/// Where the text box goes on the image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPreset {
+    /// Full-width band along the top edge.
+    TopBand,
+    /// Full-width band along the bottom edge.
+    #[default]
+    BottomBand,
+    /// Rounded bubble with a tail, placed by `bubble`.
+    SpeechBubble,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+/// Speech bubble placement as fractions (0-1) of the image size. The bubble grows downwards
+/// from `y` to fit the text; the tail points at (`tail_x`, `tail_y`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BubblePlacement {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    #[serde(alias = "tailX")]
+    pub tail_x: f32,
+    #[serde(alias = "tailY")]
+    pub tail_y: f32,
+}
+
+impl Default for BubblePlacement {
+    fn default() -> Self {
+        BubblePlacement {
+            x: 0.5,
+            y: 0.06,
+            width: 0.42,
+            tail_x: 0.55,
+            tail_y: 0.45,
+        }
+    }
+}
+
+/// Colors are `#RRGGBB` or `#RRGGBBAA`; sizes are in pixels of the source image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextOverlayOptions {
+    pub preset: OverlayPreset,
+    /// Any `.ttf`/`.otf`/`.ttc`; a CJK font is found automatically when empty.
+    #[serde(alias = "fontPath")]
+    pub font_path: Option<String>,
+    /// Defaults to 1/22 of the image width.
+    #[serde(alias = "fontSize")]
+    pub font_size: Option<f32>,
+    pub color: String,
+    #[serde(alias = "lineSpacing")]
+    pub line_spacing: f32,
+    pub align: TextAlign,
+    /// Space between the box edge and the text, as a multiple of the font size.
+    pub padding: f32,
+    /// Band or bubble fill; empty for none.
+    #[serde(alias = "boxColor")]
+    pub box_color: String,
+    #[serde(alias = "outlineColor")]
+    pub outline_color: String,
+    /// 0 disables the outline.
+    #[serde(alias = "outlineWidth")]
+    pub outline_width: f32,
+    /// Empty disables the shadow.
+    #[serde(alias = "shadowColor")]
+    pub shadow_color: String,
+    #[serde(alias = "shadowOffsetX")]
+    pub shadow_offset_x: f32,
+    #[serde(alias = "shadowOffsetY")]
+    pub shadow_offset_y: f32,
+    #[serde(alias = "shadowBlur")]
+    pub shadow_blur: f32,
+    pub bubble: BubblePlacement,
+}
+
+impl Default for TextOverlayOptions {
+    fn default() -> Self {
+        TextOverlayOptions {
+            preset: OverlayPreset::BottomBand,
+            font_path: None,
+            font_size: None,
+            color: "#222222".to_string(),
+            line_spacing: 1.4,
+            align: TextAlign::Center,
+            padding: 0.8,
+            box_color: "#FFFFFFD9".to_string(),
+            outline_color: "#FFFFFF".to_string(),
+            outline_width: 0.0,
+            shadow_color: String::new(),
+            shadow_offset_x: 2.0,
+            shadow_offset_y: 2.0,
+            shadow_blur: 2.0,
+            bubble: BubblePlacement::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextOverlayResult {
+    pub path: String,
+    /// The text as it was wrapped onto the image.
+    pub lines: Vec<String>,
+    /// Characters the font could not draw.
+    pub missing_glyphs: Vec<String>,
+}
+
+/// Parses `#RGB`, `#RRGGBB` or `#RRGGBBAA`. `None` for an empty string.
+fn parse_color(value: &str, field: &str) -> AppResult<Option<Rgba<u8>>> {
+    let hex = value.trim().trim_start_matches('#');
+    if hex.is_empty() {
+        return Ok(None);
+    }
+    let invalid = || {
+        AppError::validation(vec![FieldError {
+            field: field.to_string(),
+            message: format!("颜色格式无效: {}，应为 #RRGGBB 或 #RRGGBBAA", value),
+        }])
+    };
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect::<String>(),
+        6 | 8 => hex.to_string(),
+        _ => return Err(invalid()),
+    };
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Ok(Some(Rgba([channel(0)?, channel(2)?, channel(4)?, alpha])))
+}
+
+fn blend(image: &mut RgbaImage, x: i32, y: i32, color: Rgba<u8>, coverage: f32) {
+    if x < 0 || y < 0 || x >= image.width() as i32 || y >= image.height() as i32 {
+        return;
+    }
+    let alpha = coverage.clamp(0.0, 1.0) * color[3] as f32 / 255.0;
+    if alpha <= 0.0 {
+        return;
+    }
+    let dst = image.get_pixel_mut(x as u32, y as u32);
+    let dst_alpha = dst[3] as f32 / 255.0;
+    let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+    for i in 0..3 {
+        let mixed = (color[i] as f32 * alpha + dst[i] as f32 * dst_alpha * (1.0 - alpha)) / out_alpha;
+        dst[i] = mixed.round() as u8;
+    }
+    dst[3] = (out_alpha * 255.0).round() as u8;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+fn fill_rounded_rect(image: &mut RgbaImage, rect: Rect, radius: f32, color: Rgba<u8>) {
+    let radius = radius.min(rect.width / 2.0).min(rect.height / 2.0);
+    let x0 = rect.x.floor().max(0.0) as i32;
+    let y0 = rect.y.floor().max(0.0) as i32;
+    let x1 = (rect.x + rect.width).ceil() as i32;
+    let y1 = (rect.y + rect.height).ceil() as i32;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            // Distance past the rounded corner; the last pixel inside fades out for antialiasing
+            let cx = px.clamp(rect.x + radius, rect.x + rect.width - radius);
+            let cy = py.clamp(rect.y + radius, rect.y + rect.height - radius);
+            let outside = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt() - radius;
+            blend(image, x, y, color, 1.0 - outside);
+        }
+    }
+}
+
+fn fill_triangle(image: &mut RgbaImage, points: [(f32, f32); 3], color: Rgba<u8>) {
+    let min_x = points.iter().map(|p| p.0).fold(f32::MAX, f32::min).floor() as i32;
+    let max_x = points.iter().map(|p| p.0).fold(f32::MIN, f32::max).ceil() as i32;
+    let min_y = points.iter().map(|p| p.1).fold(f32::MAX, f32::min).floor() as i32;
+    let max_y = points.iter().map(|p| p.1).fold(f32::MIN, f32::max).ceil() as i32;
+    let edge = |a: (f32, f32), b: (f32, f32), p: (f32, f32)| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+    let area = edge(points[0], points[1], points[2]);
+    if area.abs() < f32::EPSILON {
+        return;
+    }
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let p = (x as f32 + 0.5, y as f32 + 0.5);
+            let w0 = edge(points[1], points[2], p) / area;
+            let w1 = edge(points[2], points[0], p) / area;
+            let w2 = edge(points[0], points[1], p) / area;
+            if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                blend(image, x, y, color, 1.0);
+            }
+        }
+    }
+}
+
+/// Draws one line with its top-left corner at (`x`, `top`).
+fn draw_line(image: &mut RgbaImage, font: &TextFont, size: f32, x: f32, top: f32, text: &str, color: Rgba<u8>) {
+    let baseline = top + font.ascent(size);
+    let mut caret = x;
+    for c in text.chars() {
+        let glyph = font
+            .font()
+            .glyph_id(c)
+            .with_scale_and_position(PxScale::from(size), point(caret, baseline));
+        caret += font.advance(c, size);
+        if let Some(outlined) = font.font().outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                blend(
+                    image,
+                    bounds.min.x as i32 + gx as i32,
+                    bounds.min.y as i32 + gy as i32,
+                    color,
+                    coverage,
+                );
+            });
+        }
+    }
+}
+
+/// Text already wrapped and measured for its box.
+struct TextBlock {
+    lines: Vec<String>,
+    size: f32,
+    line_height: f32,
+    /// Area the lines are laid into (inside the padding).
+    inner: Rect,
+}
+
+impl TextBlock {
+    fn line_origins<'a>(&'a self, font: &'a TextFont, align: TextAlign) -> impl Iterator<Item = (f32, f32, &'a str)> + 'a {
+        self.lines.iter().enumerate().map(move |(i, line)| {
+            let width = font.text_width(line, self.size);
+            let x = match align {
+                TextAlign::Left => self.inner.x,
+                TextAlign::Center => self.inner.x + (self.inner.width - width) / 2.0,
+                TextAlign::Right => self.inner.x + self.inner.width - width,
+            };
+            (x, self.inner.y + i as f32 * self.line_height, line.as_str())
+        })
+    }
+}
+
+/// Box of a `text_height`-tall paragraph for the preset on a `width`x`height` image.
+fn layout_box(options: &TextOverlayOptions, width: f32, height: f32, padding: f32, text_height: f32) -> Rect {
+    let box_height = text_height + 2.0 * padding;
+    match options.preset {
+        OverlayPreset::TopBand => Rect { x: 0.0, y: 0.0, width, height: box_height },
+        OverlayPreset::BottomBand => Rect {
+            x: 0.0,
+            y: height - box_height,
+            width,
+            height: box_height,
+        },
+        OverlayPreset::SpeechBubble => {
+            let bubble = &options.bubble;
+            let box_width = (bubble.width.clamp(0.05, 1.0) * width).min(width);
+            Rect {
+                x: (bubble.x.clamp(0.0, 1.0) * width).min(width - box_width),
+                y: (bubble.y.clamp(0.0, 1.0) * height).min((height - box_height).max(0.0)),
+                width: box_width,
+                height: box_height,
+            }
+        }
+    }
+}
+
+/// Draws `text` onto `image` according to `options`; returns the wrapped lines.
+pub fn render_text_overlay(
+    image: &mut RgbaImage,
+    text: &str,
+    font: &TextFont,
+    options: &TextOverlayOptions,
+) -> AppResult<Vec<String>> {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let size = options.font_size.unwrap_or(width / 22.0);
+    if size.is_nan() || size <= 0.0 || options.line_spacing <= 0.0 {
+        return Err(AppError::invalid_input("字号和行距必须大于 0"));
+    }
+    let color = parse_color(&options.color, "color")?.unwrap_or(Rgba([0, 0, 0, 255]));
+    let box_color = parse_color(&options.box_color, "box_color")?;
+    let outline_color = parse_color(&options.outline_color, "outline_color")?;
+    let shadow_color = parse_color(&options.shadow_color, "shadow_color")?;
+
+    let padding = size * options.padding.max(0.0);
+    let line_height = size * options.line_spacing;
+    // Wrap against the box width first; the height follows from the line count
+    let probe = layout_box(options, width, height, padding, 0.0);
+    let lines = font.wrap(text.trim(), size, (probe.width - 2.0 * padding).max(size));
+    let text_height = line_height * (lines.len() as f32 - 1.0) + font.line_height(size);
+    let outer = layout_box(options, width, height, padding, text_height);
+    let block = TextBlock {
+        lines,
+        size,
+        line_height,
+        inner: Rect {
+            x: outer.x + padding,
+            y: outer.y + padding,
+            width: outer.width - 2.0 * padding,
+            height: text_height,
+        },
+    };
+
+    if let Some(box_color) = box_color {
+        if options.preset == OverlayPreset::SpeechBubble {
+            let bubble = &options.bubble;
+            let tip = (bubble.tail_x * width, bubble.tail_y * height);
+            let center = (outer.x + outer.width / 2.0, outer.y + outer.height / 2.0);
+            // The tail leaves the bubble from its centre so it always joins the body
+            let base = outer.width.min(outer.height) * 0.18;
+            let (dx, dy) = (tip.0 - center.0, tip.1 - center.1);
+            let length = (dx * dx + dy * dy).sqrt().max(1.0);
+            let (nx, ny) = (-dy / length * base, dx / length * base);
+            fill_triangle(
+                image,
+                [(center.0 + nx, center.1 + ny), (center.0 - nx, center.1 - ny), tip],
+                box_color,
+            );
+            fill_rounded_rect(image, outer, outer.height.min(outer.width) * 0.35, box_color);
+        } else {
+            fill_rounded_rect(image, outer, 0.0, box_color);
+        }
+    }
+
+    if let Some(shadow_color) = shadow_color {
+        let mut layer = RgbaImage::new(image.width(), image.height());
+        for (x, y, line) in block.line_origins(font, options.align) {
+            draw_line(
+                &mut layer,
+                font,
+                size,
+                x + options.shadow_offset_x,
+                y + options.shadow_offset_y,
+                line,
+                shadow_color,
+            );
+        }
+        if options.shadow_blur > 0.0 {
+            layer = imageops::blur(&layer, options.shadow_blur);
+        }
+        imageops::overlay(image, &layer, 0, 0);
+    }
+
+    if let (Some(outline_color), true) = (outline_color, options.outline_width > 0.0) {
+        // Stamping the glyphs around a circle is cheap and looks right at caption sizes
+        let radius = options.outline_width;
+        let steps = ((radius * 8.0).ceil() as usize).clamp(8, 48);
+        for (x, y, line) in block.line_origins(font, options.align) {
+            for step in 0..steps {
+                let angle = step as f32 / steps as f32 * std::f32::consts::TAU;
+                let (ox, oy) = (angle.cos() * radius, angle.sin() * radius);
+                draw_line(image, font, size, x + ox, y + oy, line, outline_color);
+            }
+        }
+    }
+
+    for (x, y, line) in block.line_origins(font, options.align) {
+        draw_line(image, font, size, x, y, line, color);
+    }
+
+    Ok(block.lines)
+}
+
+/// Local images get a sibling `<name>_text.png`; downloaded ones go to the app's `overlays` folder.
+fn default_output_path(image: &str) -> PathBuf {
+    let source = Path::new(image);
+    if !image.starts_with("data:") && !image.starts_with("http") {
+        if let (Some(parent), Some(stem)) = (source.parent(), source.file_stem()) {
+            return parent.join(format!("{}_text.png", stem.to_string_lossy()));
+        }
+    }
+    image_generator::get_app_data_dir()
+        .join("overlays")
+        .join(format!("overlay_{}.png", chrono::Utc::now().timestamp_millis()))
+}
+
+/// Composites `text` onto `image` (a file path, data URI or URL) and saves the result as PNG.
+#[tauri::command]
+pub async fn overlay_text(
+    image: String,
+    text: String,
+    options: Option<TextOverlayOptions>,
+    output_path: Option<String>,
+) -> AppResult<TextOverlayResult> {
+    if text.trim().is_empty() {
+        return Err(AppError::invalid_input("文字内容不能为空"));
+    }
+    let bytes = if image.starts_with("data:") || image.starts_with("http") {
+        image_files::fetch_image_bytes(&image).await?
+    } else {
+        tokio::fs::read(&image).await?
+    };
+    let output = output_path.map(PathBuf::from).unwrap_or_else(|| default_output_path(&image));
+    let options = options.unwrap_or_default();
+
+    tokio::task::spawn_blocking(move || {
+        let mut canvas = image::load_from_memory(&bytes)
+            .map_err(|e| AppError::invalid_input(format!("无法解析图片: {}", e)))?
+            .to_rgba8();
+        // Collections are fine here: ab_glyph rasterises the first face itself
+        let font = TextFont::load(options.font_path.as_deref(), false)?;
+        let lines = render_text_overlay(&mut canvas, &text, &font, &options)?;
+
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        canvas
+            .save_with_format(&output, image::ImageFormat::Png)
+            .map_err(|e| AppError::internal(format!("保存图片失败: {}", e)))?;
+
+        Ok(TextOverlayResult {
+            path: output.to_string_lossy().to_string(),
+            lines,
+            missing_glyphs: font.missing_glyphs(&text).into_iter().map(String::from).collect(),
+        })
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("文字合成任务异常: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_colors_and_places_boxes() {
+        assert_eq!(parse_color("#ff8000", "color").unwrap(), Some(Rgba([255, 128, 0, 255])));
+        assert_eq!(parse_color("#FFFFFF80", "color").unwrap(), Some(Rgba([255, 255, 255, 128])));
+        assert_eq!(parse_color("#0f0", "color").unwrap(), Some(Rgba([0, 255, 0, 255])));
+        assert_eq!(parse_color("", "color").unwrap(), None);
+        assert!(parse_color("red", "color").is_err());
+        assert!(parse_color("#aéééb", "color").is_err());
+
+        let mut options = TextOverlayOptions::default();
+        let bottom = layout_box(&options, 1000.0, 800.0, 10.0, 100.0);
+        assert_eq!(bottom, Rect { x: 0.0, y: 680.0, width: 1000.0, height: 120.0 });
+
+        // A bubble near the right edge is pushed back inside the image
+        options.preset = OverlayPreset::SpeechBubble;
+        options.bubble.x = 0.9;
+        let bubble = layout_box(&options, 1000.0, 800.0, 10.0, 100.0);
+        assert_eq!(bubble.x, 580.0);
+        assert_eq!(bubble.width, 420.0);
+    }
+
+    #[test]
+    #[ignore = "needs DejaVu Sans at /usr/share/fonts/truetype/dejavu"]
+    fn test_renders_band_with_outline() {
+        let font = TextFont::load(Some("/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf"), false).unwrap();
+        let mut canvas = RgbaImage::from_pixel(400, 300, Rgba([0, 0, 255, 255]));
+        let options = TextOverlayOptions {
+            preset: OverlayPreset::TopBand,
+            font_size: Some(24.0),
+            outline_width: 2.0,
+            ..Default::default()
+        };
+
+        let lines = render_text_overlay(&mut canvas, "Once upon a time there was a little fox", &font, &options).unwrap();
+        assert!(lines.len() >= 2);
+        // The band is white-ish and the bottom of the image is untouched
+        assert!(canvas.get_pixel(2, 2)[0] > 200);
+        assert_eq!(*canvas.get_pixel(200, 290), Rgba([0, 0, 255, 255]));
+        // Dark text pixels were drawn inside the band
+        assert!(canvas.enumerate_pixels().any(|(_, y, p)| y < 100 && p[0] < 80 && p[2] < 80));
+    }
+}
@@ -0,0 +1,162 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ColorType, DynamicImage};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::image_generator;
+use crate::error::{AppError, AppResult};
+
+/// Requested sizes are rounded up to one of these, so the cache holds a few files per image
+/// instead of one per pixel width the UI ever asked for.
+const SIZE_BUCKETS: &[u32] = &[64, 128, 256, 512, 1024];
+const JPEG_QUALITY: u8 = 82;
+
+fn get_thumbnail_dir() -> PathBuf {
+    image_generator::get_app_data_dir().join("thumbnails")
+}
+
+/// Smallest bucket that covers `size`, or the largest bucket.
+fn bucket(size: u32) -> u32 {
+    SIZE_BUCKETS
+        .iter()
+        .copied()
+        .find(|b| *b >= size)
+        .unwrap_or(SIZE_BUCKETS[SIZE_BUCKETS.len() - 1])
+}
+
+/// Cache file names start with a hash of the source path, so every thumbnail of one image
+/// can be found (and dropped) without an index.
+fn path_key(path: &Path) -> String {
+    let digest = Sha256::digest(path.to_string_lossy().as_bytes());
+    digest[..12].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn modified_millis(path: &Path) -> AppResult<u128> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis())
+}
+
+/// Images with transparency become lossless WebP; everything else JPEG.
+fn encode(image: &DynamicImage) -> AppResult<(Vec<u8>, &'static str)> {
+    let encode_error = |e: image::ImageError| AppError::internal(format!("缩略图编码失败: {}", e));
+    let mut bytes = Vec::new();
+    if image.color().has_alpha() {
+        let rgba = image.to_rgba8();
+        WebPEncoder::new_lossless(&mut bytes)
+            .encode(rgba.as_raw(), rgba.width(), rgba.height(), ColorType::Rgba8)
+            .map_err(encode_error)?;
+        Ok((bytes, "webp"))
+    } else {
+        let rgb = image.to_rgb8();
+        JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+            .encode(rgb.as_raw(), rgb.width(), rgb.height(), ColorType::Rgb8)
+            .map_err(encode_error)?;
+        Ok((bytes, "jpg"))
+    }
+}
+
+fn thumbnail_in(cache_dir: &Path, source: &Path, size: u32) -> AppResult<PathBuf> {
+    let size = bucket(size);
+    let key = path_key(source);
+    let stem = format!("{}_{}_{}", key, modified_millis(source)?, size);
+    for ext in ["jpg", "webp"] {
+        let cached = cache_dir.join(format!("{}.{}", stem, ext));
+        if cached.exists() {
+            return Ok(cached);
+        }
+    }
+
+    let image = image::open(source)
+        .map_err(|e| AppError::invalid_input(format!("无法读取图片 {}: {}", source.display(), e)))?;
+    // Never upscale: a small original is its own thumbnail, just re-encoded
+    let image = if image.width() > size || image.height() > size {
+        image.thumbnail(size, size)
+    } else {
+        image
+    };
+    let (bytes, ext) = encode(&image)?;
+
+    // Thumbnails of an older version of the file are dead weight now
+    invalidate_in(cache_dir, &key, Some(size));
+    fs::create_dir_all(cache_dir)?;
+    let target = cache_dir.join(format!("{}.{}", stem, ext));
+    let partial = target.with_extension("part");
+    fs::write(&partial, bytes)?;
+    fs::rename(&partial, &target)?;
+    Ok(target)
+}
+
+/// Removes cached thumbnails for the path `key`, only those of `size` when given.
+fn invalidate_in(cache_dir: &Path, key: &str, size: Option<u32>) {
+    let Ok(entries) = fs::read_dir(cache_dir) else {
+        return;
+    };
+    let prefix = format!("{}_", key);
+    let size_suffix = size.map(|s| format!("_{}", s));
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let stem = name.split('.').next().unwrap_or_default();
+        if stem.starts_with(&prefix) && size_suffix.as_ref().is_none_or(|s| stem.ends_with(s.as_str())) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Path of a cached thumbnail at most `size` px on its longer side, generated on first use.
+/// The cache is keyed by source path and modification time, so edited files get new ones.
+pub fn thumbnail(source: &Path, size: u32) -> AppResult<PathBuf> {
+    thumbnail_in(&get_thumbnail_dir(), source, size)
+}
+
+/// Drops every cached thumbnail of `source`; called when the file is deleted or replaced.
+pub fn invalidate(source: &str) {
+    invalidate_in(&get_thumbnail_dir(), &path_key(Path::new(source)), None);
+}
+
+#[tauri::command]
+pub async fn get_thumbnail(path: String, size: u32) -> AppResult<String> {
+    tokio::task::spawn_blocking(move || {
+        thumbnail(Path::new(&path), size).map(|p| p.to_string_lossy().to_string())
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("缩略图任务异常: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caches_by_path_and_mtime() {
+        let dir = std::env::temp_dir().join(format!("huiben_thumbs_{}", std::process::id()));
+        let cache = dir.join("cache");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("big.png");
+        image::RgbImage::from_pixel(1200, 600, image::Rgb([10, 200, 30])).save(&source).unwrap();
+
+        assert_eq!(bucket(100), 128);
+        assert_eq!(bucket(4000), 1024);
+
+        let thumb = thumbnail_in(&cache, &source, 200).unwrap();
+        assert_eq!(thumb.extension().unwrap(), "jpg");
+        assert_eq!(image::image_dimensions(&thumb).unwrap(), (256, 128));
+        // Served from the cache the second time
+        let modified = fs::metadata(&thumb).unwrap().modified().unwrap();
+        assert_eq!(thumbnail_in(&cache, &source, 256).unwrap(), thumb);
+        assert_eq!(fs::metadata(&thumb).unwrap().modified().unwrap(), modified);
+
+        // Transparent images keep their alpha channel
+        let logo = dir.join("logo.png");
+        image::RgbaImage::from_pixel(300, 300, image::Rgba([0, 0, 0, 0])).save(&logo).unwrap();
+        assert_eq!(thumbnail_in(&cache, &logo, 64).unwrap().extension().unwrap(), "webp");
+
+        invalidate_in(&cache, &path_key(&source), None);
+        assert!(!thumb.exists());
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
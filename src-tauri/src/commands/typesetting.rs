@@ -0,0 +1,254 @@
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::image_generator;
+use crate::error::{AppError, AppResult, ErrorCode};
+
+/// Fonts with Chinese glyphs found on stock Windows, macOS and Linux installs, in order of
+/// preference. Single-face `.ttf`/`.otf` files come first because PDF export cannot embed
+/// collections.
+const SYSTEM_CJK_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "C:\\Windows\\Fonts\\simkai.ttf",
+    "C:\\Windows\\Fonts\\Deng.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/System/Library/Fonts/PingFang.ttc",
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+];
+
+/// Punctuation that must not start a line; it stays with the character before it.
+const NO_LINE_START: &str = "，。、；：？！）」』》〉】〕”’…·,.;:?!)]}%";
+/// Opening brackets and quotes that must not end a line; they move down with the next word.
+const NO_LINE_END: &str = "（「『《〈【〔“‘([{";
+
+/// A loaded font plus its raw bytes, shared by PDF/EPUB export and text overlays.
+pub struct TextFont {
+    font: FontVec,
+    bytes: Vec<u8>,
+    path: PathBuf,
+}
+
+impl TextFont {
+    /// Loads `path`, or the first font found in the app's `fonts` folder or among common system
+    /// CJK fonts. With `single_face`, font collections (`.ttc`) are skipped or rejected.
+    pub fn load(path: Option<&str>, single_face: bool) -> AppResult<Self> {
+        if let Some(path) = path.filter(|p| !p.is_empty()) {
+            let font = Self::load_file(Path::new(path))?;
+            if single_face && font.is_collection() {
+                return Err(AppError::invalid_input("暂不支持字体集合(.ttc)，请选择 .ttf 或 .otf 字体"));
+            }
+            return Ok(font);
+        }
+
+        for candidate in font_candidates() {
+            match Self::load_file(&candidate) {
+                Ok(font) if !(single_face && font.is_collection()) => return Ok(font),
+                _ => continue,
+            }
+        }
+        Err(AppError::new(
+            ErrorCode::ConfigMissing,
+            "未找到可用的中文字体，请在导出选项中指定字体文件",
+        ))
+    }
+
+    fn load_file(path: &Path) -> AppResult<Self> {
+        let bytes = fs::read(path)?;
+        let font = FontVec::try_from_vec(bytes.clone())
+            .map_err(|_| AppError::invalid_input(format!("无法解析字体文件: {}", path.display())))?;
+        Ok(TextFont {
+            font,
+            bytes,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn font(&self) -> &FontVec {
+        &self.font
+    }
+
+    pub fn is_collection(&self) -> bool {
+        self.bytes.starts_with(b"ttcf")
+    }
+
+    /// Horizontal advance of `c` at `size` (same unit as `size`, e.g. pt or px).
+    pub fn advance(&self, c: char, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        scaled.h_advance(self.font.glyph_id(c))
+    }
+
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.advance(c, size)).sum()
+    }
+
+    /// Distance from the top of a line to its baseline.
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.font.as_scaled(PxScale::from(size)).ascent()
+    }
+
+    /// Natural line height (ascent + descent + line gap) at `size`.
+    pub fn line_height(&self, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        scaled.ascent() - scaled.descent() + scaled.line_gap()
+    }
+
+    pub fn wrap(&self, text: &str, size: f32, max_width: f32) -> Vec<String> {
+        wrap_text(text, max_width, |c| self.advance(c, size))
+    }
+
+    /// Characters of `text` the font has no glyph for; they would print as blank boxes.
+    pub fn missing_glyphs(&self, text: &str) -> Vec<char> {
+        let mut missing: Vec<char> = text
+            .chars()
+            .filter(|c| !c.is_whitespace() && self.font.glyph_id(*c).0 == 0)
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        missing
+    }
+}
+
+fn font_candidates() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    // Fonts the user dropped into the app's data folder win over system fonts
+    if let Ok(entries) = fs::read_dir(image_generator::get_app_data_dir().join("fonts")) {
+        let mut bundled: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| matches!(e.to_ascii_lowercase().as_str(), "ttf" | "otf" | "ttc"))
+            })
+            .collect();
+        bundled.sort();
+        candidates.extend(bundled);
+    }
+    candidates.extend(SYSTEM_CJK_FONTS.iter().map(PathBuf::from));
+    candidates
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{2E80}'..='\u{2FDF}'
+        | '\u{3000}'..='\u{303F}'
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{3100}'..='\u{31BF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF00}'..='\u{FFEF}'
+        | '\u{20000}'..='\u{2FA1F}')
+}
+
+/// Splits a paragraph into pieces that may not be broken: latin words, single CJK characters
+/// and spaces, with kinsoku punctuation glued to its neighbour.
+fn break_units(paragraph: &str) -> Vec<String> {
+    let mut units: Vec<String> = Vec::new();
+    let mut word = String::new();
+    for c in paragraph.chars() {
+        let is_word_char = !c.is_whitespace()
+            && !is_cjk(c)
+            && !NO_LINE_START.contains(c)
+            && !NO_LINE_END.contains(c);
+        if is_word_char {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            units.push(std::mem::take(&mut word));
+        }
+        units.push(if c.is_whitespace() { " ".to_string() } else { c.to_string() });
+    }
+    if !word.is_empty() {
+        units.push(word);
+    }
+
+    let mut glued: Vec<String> = Vec::with_capacity(units.len());
+    let mut carry = String::new();
+    for unit in units {
+        let starts_closing = unit.chars().next().is_some_and(|c| NO_LINE_START.contains(c));
+        match glued.last_mut() {
+            Some(prev) if starts_closing && carry.is_empty() && prev != " " => prev.push_str(&unit),
+            _ if unit.chars().all(|c| NO_LINE_END.contains(c)) => carry.push_str(&unit),
+            _ => glued.push(std::mem::take(&mut carry) + &unit),
+        }
+    }
+    if !carry.is_empty() {
+        glued.push(carry);
+    }
+    glued
+}
+
+/// Greedy line breaking for mixed Chinese/latin text. `advance` gives the width of a
+/// character in the same unit as `max_width`. Explicit `\n` starts a new line; a word wider
+/// than the line is split between characters.
+pub fn wrap_text(text: &str, max_width: f32, advance: impl Fn(char) -> f32) -> Vec<String> {
+    let width = |s: &str| s.chars().map(&advance).sum::<f32>();
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut line_width = 0.0;
+        for unit in break_units(paragraph.trim_end_matches('\r')) {
+            let unit_width = width(&unit);
+            if line_width + unit_width > max_width && !line.trim().is_empty() {
+                lines.push(line.trim_end().to_string());
+                line.clear();
+                line_width = 0.0;
+            }
+            if line.is_empty() && unit == " " {
+                continue;
+            }
+            if unit_width > max_width {
+                for c in unit.chars() {
+                    let w = advance(c);
+                    if line_width + w > max_width && !line.is_empty() {
+                        lines.push(std::mem::take(&mut line));
+                        line_width = 0.0;
+                    }
+                    line.push(c);
+                    line_width += w;
+                }
+                continue;
+            }
+            line.push_str(&unit);
+            line_width += unit_width;
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wraps_cjk_and_latin_with_kinsoku() {
+        let wrap = |text: &str, width: f32| wrap_text(text, width, |_| 1.0);
+
+        assert_eq!(wrap("小狐狸在森林里", 3.0), vec!["小狐狸", "在森林", "里"]);
+        // The full stop never opens a line; it takes the last character down with it
+        assert_eq!(wrap("小狐狸笑了。", 5.0), vec!["小狐狸笑", "了。"]);
+        assert_eq!(wrap("他说「你好」", 3.0), vec!["他说", "「你", "好」"]);
+        assert_eq!(wrap("hello world", 8.0), vec!["hello", "world"]);
+        assert_eq!(wrap("第一行\n\n第三行", 10.0), vec!["第一行", "", "第三行"]);
+        assert_eq!(wrap("abcdefgh", 3.0), vec!["abc", "def", "gh"]);
+    }
+}
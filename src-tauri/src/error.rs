@@ -0,0 +1,226 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::commands::providers::{FieldError, ProviderError, ProviderErrorKind};
+
+/// Stable error codes shared by Tauri commands and the HTTP API.
+///
+/// Clients branch on these; `AppError.message` is display text and may change wording or language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// A required argument is missing or malformed.
+    InvalidInput,
+    /// Generation params were rejected by the provider's capabilities; see `field_errors`.
+    ValidationFailed,
+    NotFound,
+    /// The target is in a state that does not allow the operation (e.g. cancelling a finished task).
+    Conflict,
+    UnsupportedModel,
+    ConfigMissing,
+    ApiKeyMissing,
+    Cancelled,
+    ProviderRateLimited,
+    ProviderUnavailable,
+    ProviderAuth,
+    ContentBlocked,
+    ProviderRejected,
+    ProviderFailed,
+    /// Reading or writing local files failed.
+    Io,
+    /// The encrypted config could not be decrypted (e.g. the key file was replaced).
+    Crypto,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::UnsupportedModel => "unsupported_model",
+            ErrorCode::ConfigMissing => "config_missing",
+            ErrorCode::ApiKeyMissing => "api_key_missing",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::ProviderRateLimited => "provider_rate_limited",
+            ErrorCode::ProviderUnavailable => "provider_unavailable",
+            ErrorCode::ProviderAuth => "provider_auth",
+            ErrorCode::ContentBlocked => "content_blocked",
+            ErrorCode::ProviderRejected => "provider_rejected",
+            ErrorCode::ProviderFailed => "provider_failed",
+            ErrorCode::Io => "io",
+            ErrorCode::Crypto => "crypto",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    /// Stable lookup key (`errors.<code>`) for clients that show their own translated text.
+    pub fn message_key(&self) -> String {
+        format!("errors.{}", self.as_str())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidInput | ErrorCode::UnsupportedModel => StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationFailed | ErrorCode::ProviderRejected => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict | ErrorCode::Cancelled => StatusCode::CONFLICT,
+            ErrorCode::ConfigMissing | ErrorCode::ApiKeyMissing => StatusCode::PRECONDITION_FAILED,
+            ErrorCode::ProviderRateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::ProviderUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::ProviderAuth | ErrorCode::ProviderFailed => StatusCode::BAD_GATEWAY,
+            ErrorCode::ContentBlocked => StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+            ErrorCode::Io | ErrorCode::Crypto | ErrorCode::Internal => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl From<ProviderErrorKind> for ErrorCode {
+    fn from(kind: ProviderErrorKind) -> Self {
+        match kind {
+            ProviderErrorKind::RateLimited => ErrorCode::ProviderRateLimited,
+            ProviderErrorKind::Transient => ErrorCode::ProviderUnavailable,
+            ProviderErrorKind::Auth => ErrorCode::ProviderAuth,
+            ProviderErrorKind::ContentPolicy => ErrorCode::ContentBlocked,
+            ProviderErrorKind::InvalidRequest => ErrorCode::ProviderRejected,
+            ProviderErrorKind::Other => ErrorCode::ProviderFailed,
+        }
+    }
+}
+
+/// Error returned by every command. Serialized as `{ code, message, message_key, field_errors? }`
+/// both to the webview and as the body of HTTP error responses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppError {
+    pub code: ErrorCode,
+    /// Chinese, human-readable description with the specifics (paths, provider replies).
+    pub message: String,
+    /// `code.message_key()`; clients localize with it and may show `message` as the detail.
+    #[serde(default, alias = "messageKey")]
+    pub message_key: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", alias = "fieldErrors")]
+    pub field_errors: Vec<FieldError>,
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        AppError {
+            code,
+            message: message.into(),
+            message_key: code.message_key(),
+            field_errors: Vec::new(),
+        }
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidInput, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn validation(field_errors: Vec<FieldError>) -> Self {
+        let details: Vec<String> = field_errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect();
+        AppError {
+            code: ErrorCode::ValidationFailed,
+            message: format!("参数校验失败: {}", details.join("; ")),
+            message_key: ErrorCode::ValidationFailed.message_key(),
+            field_errors,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.code.status()
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        let code = if e.kind() == std::io::ErrorKind::NotFound {
+            ErrorCode::NotFound
+        } else {
+            ErrorCode::Io
+        };
+        AppError::new(code, format!("文件读写失败: {}", e))
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::internal(format!("JSON解析失败: {}", e))
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for AppError {
+    fn from(e: std::sync::PoisonError<T>) -> Self {
+        AppError::internal(e.to_string())
+    }
+}
+
+impl From<ProviderError> for AppError {
+    fn from(e: ProviderError) -> Self {
+        AppError::new(e.kind().into(), e.to_string())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        (self.status(), axum::Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_stable_code() {
+        let err = AppError::not_found("任务不存在");
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "code": "not_found", "message": "任务不存在", "message_key": "errors.not_found" })
+        );
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+
+        let code: ErrorCode = serde_json::from_value(serde_json::json!(ErrorCode::ContentBlocked.as_str())).unwrap();
+        assert_eq!(code, ErrorCode::ContentBlocked);
+    }
+
+    #[test]
+    fn test_provider_errors_keep_their_class() {
+        let err: AppError = ProviderError::RateLimited {
+            retry_after: None,
+            message: "429".to_string(),
+        }
+        .into();
+        assert_eq!(err.code, ErrorCode::ProviderRateLimited);
+        assert_eq!(err.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -1,7 +1,9 @@
 mod api;
 mod commands;
+mod error;
 
 use api::create_api_router;
+use commands::batch::run_batch_generation;
 use commands::character_binding::{
     add_tag_to_reference, bind_character_reference, delete_reference_image, get_all_bindings,
     get_all_tags, get_bindings_for_prompt, get_character_binding, get_references_by_type,
@@ -9,17 +11,35 @@ use commands::character_binding::{
     save_reference_image, search_reference_images, unbind_character,
 };
 use commands::image_generator::{
-    generate_image, get_default_api_config, get_default_generation_config,
+    cancel_generation, generate_image, get_default_api_config, get_default_generation_config,
     get_generation_progress, load_api_config, load_generation_config,
-    save_api_config, save_generation_config, test_api_connection,
+    save_api_config, save_generation_config, subscribe_generation_events, test_api_connection,
+    GENERATION_PROGRESS_EVENT,
+};
+use commands::epub_export::export_project_epub;
+use commands::gallery::{
+    empty_gallery_trash, list_gallery, list_gallery_tags, restore_gallery_items, set_gallery_favorite,
+    set_gallery_rating, set_gallery_tags, trash_gallery_items,
+};
+use commands::image_metadata::{import_image_metadata, ImageMetadata};
+use commands::history::{delete_history_entry, get_history_entry, list_history, rerun_generation};
+use commands::pdf_export::export_project_pdf;
+use commands::project::{
+    add_page, bind_project_character, create_project, delete_page, delete_project, generate_page,
+    get_project, list_projects, rename_project, reorder_pages, unbind_project_character, update_page,
+    update_project_settings,
 };
 use commands::prompt_parser::{parse_prompt, test_parse};
+use commands::text_overlay::overlay_text;
+use commands::thumbnails::get_thumbnail;
+use commands::providers::list_image_providers;
+use error::AppResult;
 use std::net::SocketAddr;
 use tauri::{
     image::Image,
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
-    Manager, Window, WindowEvent,
+    Emitter, Manager, Window, WindowEvent,
 };
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut};
 
@@ -29,35 +49,24 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-async fn save_image_to_file(image_url: String, file_path: String) -> Result<String, String> {
-    if image_url.starts_with("data:image") {
-        let base64_data = image_url
-            .split(',')
-            .nth(1)
-            .ok_or("Invalid base64 data")?;
-        let decoded = base64::Engine::decode(
-            &base64::engine::general_purpose::STANDARD,
-            base64_data,
-        )
-        .map_err(|e| e.to_string())?;
-        std::fs::write(&file_path, decoded).map_err(|e| e.to_string())?;
-        Ok(file_path)
-    } else if image_url.starts_with("http://") || image_url.starts_with("https://") {
-        let response = reqwest::get(&image_url)
-            .await
-            .map_err(|e| e.to_string())?;
-        let bytes = response.bytes().await.map_err(|e| e.to_string())?;
-        std::fs::write(&file_path, bytes).map_err(|e| e.to_string())?;
-        Ok(file_path)
-    } else {
-        Err("Unsupported image format".to_string())
+async fn save_image_to_file(
+    image_url: String,
+    file_path: String,
+    metadata: Option<ImageMetadata>,
+) -> AppResult<String> {
+    let mut bytes = commands::image_files::fetch_image_bytes(&image_url).await?;
+    if let Some(metadata) = metadata {
+        bytes = commands::image_metadata::embed_metadata(bytes, &metadata.completed())?;
     }
+    std::fs::write(&file_path, bytes)?;
+    Ok(file_path)
 }
 
 #[tokio::main]
 pub async fn main() {
     let _ = load_bindings_from_file();
     commands::image_generator::load_config_from_file();
+    commands::generation_queue::restore_queue();
     commands::character_binding::load_tags_from_file();
 
     let api_router = create_api_router();
@@ -140,6 +149,21 @@ pub async fn main() {
                 }
             })?;
 
+            // Forward generation progress to the frontend
+            let event_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut events = subscribe_generation_events();
+                loop {
+                    match events.recv().await {
+                        Ok(event) => {
+                            let _ = event_handle.emit(GENERATION_PROGRESS_EVENT, &event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
             if let Some(window) = app.get_webview_window("main") {
                 let window_clone = window.clone();
                 window.on_window_event(move |event| {
@@ -170,15 +194,48 @@ pub async fn main() {
             get_all_tags,
             get_references_by_type,
             generate_image,
+            cancel_generation,
+            run_batch_generation,
+            list_history,
+            get_history_entry,
+            delete_history_entry,
+            rerun_generation,
+            list_gallery,
+            list_gallery_tags,
+            set_gallery_favorite,
+            set_gallery_rating,
+            set_gallery_tags,
+            trash_gallery_items,
+            restore_gallery_items,
+            empty_gallery_trash,
+            create_project,
+            list_projects,
+            get_project,
+            rename_project,
+            update_project_settings,
+            delete_project,
+            add_page,
+            update_page,
+            delete_page,
+            reorder_pages,
+            bind_project_character,
+            unbind_project_character,
+            generate_page,
+            export_project_pdf,
+            export_project_epub,
+            overlay_text,
+            import_image_metadata,
             get_generation_progress,
             save_api_config,
             load_api_config,
             get_default_api_config,
             test_api_connection,
+            list_image_providers,
             save_generation_config,
             load_generation_config,
             get_default_generation_config,
             save_image_to_file,
+            get_thumbnail,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
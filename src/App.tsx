@@ -89,6 +89,7 @@ function MainApp() {
   const [activeTab, setActiveTab] = useState<'upload' | 'library'>('upload');
   const [referenceImageLibrary, setReferenceImageLibrary] = useState<CharacterBinding[]>([]);
   const dragCounter = useRef(0);
+  const importInputRef = useRef<HTMLInputElement>(null);
 
   const [selectedModel, setSelectedModel] = useState<'seedream' | 'banana_pro'>('seedream');
   const [imageSize, setImageSize] = useState<{ width: number; height: number }>({
@@ -107,6 +108,7 @@ function MainApp() {
   const [generating, setGenerating] = useState(false);
   const [generationProgress, setGenerationProgress] = useState(0);
   const [generationResult, setGenerationResult] = useState<ImageGenerationResult | null>(null);
+  const [generationParams, setGenerationParams] = useState<ImageGenerationParams | null>(null);
   const [configModalVisible, setConfigModalVisible] = useState(false);
   const [helpModalVisible, setHelpModalVisible] = useState(false);
   const [referenceModalVisible, setReferenceModalVisible] = useState(false);
@@ -230,6 +232,30 @@ function MainApp() {
     }
   };
 
+  const handleImportImage = (e: React.ChangeEvent<HTMLInputElement>) => {
+    const file = e.target.files?.[0];
+    e.target.value = '';
+    if (!file) return;
+    const reader = new FileReader();
+    reader.onload = async event => {
+      try {
+        const imported = await api.importImageMetadata(event.target?.result as string);
+        const { metadata } = imported;
+        setPrompt(metadata.prompt);
+        if (metadata.model === 'seedream' || metadata.model === 'banana_pro') {
+          setSelectedModel(metadata.model);
+        }
+        if (metadata.width > 0 && metadata.height > 0) {
+          setSeedreamSize(`${metadata.width}x${metadata.height}`);
+        }
+        message.success('已从图片导入生成信息');
+      } catch (error) {
+        message.error(`导入失败: ${error}`);
+      }
+    };
+    reader.readAsDataURL(file);
+  };
+
   const handleFileChange = (e: React.ChangeEvent<HTMLInputElement>) => {
     const file = e.target.files?.[0];
     if (file) {
@@ -431,6 +457,7 @@ function MainApp() {
       clearInterval(progressInterval);
       setGenerationProgress(100);
       setGenerationResult(result);
+      setGenerationParams(params);
 
       if (result.success) {
         message.success('图片生成成功！');
@@ -586,7 +613,18 @@ function MainApp() {
 
   const handleSaveImage = async (imageUrl: string) => {
     try {
-      const saved = await api.saveImageDialog(imageUrl);
+      const metadata = generationParams
+        ? {
+            prompt: generationParams.prompt,
+            model: generationParams.model,
+            width: generationParams.width,
+            height: generationParams.height,
+            characterBindings: generationParams.characterBindings,
+            seed: generationResult?.seed,
+            taskId: generationResult?.task_id,
+          }
+        : undefined;
+      const saved = await api.saveImageDialog(imageUrl, metadata);
       if (saved) {
         message.success('图片已保存到: ' + saved);
       }
@@ -660,6 +698,20 @@ function MainApp() {
                 >
                   开始解析
                 </Button>
+                <Button
+                  onClick={() => importInputRef.current?.click()}
+                  size="large"
+                  icon={<PictureOutlined />}
+                >
+                  从图片导入
+                </Button>
+                <input
+                  ref={importInputRef}
+                  type="file"
+                  accept="image/png,image/jpeg"
+                  style={{ display: 'none' }}
+                  onChange={handleImportImage}
+                />
               </div>
               <div className="example-prompts">
                 <Text type="secondary" className="example-label">
@@ -708,7 +760,7 @@ function MainApp() {
                                   {binding?.referenceImagePath &&
                                   binding.referenceImagePath.trim() !== '' ? (
                                     <img
-                                      src={api.getImageUrl(binding.referenceImagePath || '')}
+                                      src={api.getImageUrl(binding.referenceImagePath || '', 128)}
                                       alt={char.name}
                                       style={{
                                         width: '100%',
@@ -1151,7 +1203,8 @@ function MainApp() {
                               }}
                             >
                               <Image
-                                src={api.getImageUrl(item.referenceImagePath || '')}
+                                src={api.getImageUrl(item.referenceImagePath || '', 256)}
+                                preview={{ src: api.getImageUrl(item.referenceImagePath || '') }}
                                 alt={item.characterName}
                                 style={{
                                   width: '100%',
@@ -1469,7 +1522,8 @@ function MainApp() {
                           style={{ height: 150, overflow: 'hidden', borderRadius: '4px 4px 0 0' }}
                         >
                           <Image
-                            src={api.getImageUrl(item.referenceImagePath || '')}
+                            src={api.getImageUrl(item.referenceImagePath || '', 256)}
+                            preview={{ src: api.getImageUrl(item.referenceImagePath || '') }}
                             alt={item.characterName}
                             style={{ width: '100%', height: '100%', objectFit: 'cover' }}
                             fallback="data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg=="
@@ -4,8 +4,32 @@ import type {
   CharacterBinding,
   ImageGenerationParams,
   ImageGenerationResult,
+  GenerationEvent,
+  ImageMetadata,
+  ImportedImage,
   APIConfig,
   GenerationConfig,
+  AppErrorPayload,
+  ErrorCode,
+  FieldError,
+  BatchManifest,
+  HistoryEntry,
+  HistoryFilter,
+  HistoryPage,
+  GalleryItem,
+  GalleryQuery,
+  GalleryPage,
+  Project,
+  ProjectSummary,
+  PageUpdate,
+  PageGenerationResult,
+  PdfExportOptions,
+  PdfExportReport,
+  EpubExportOptions,
+  EpubExportReport,
+  ProjectSettings,
+  TextOverlayOptions,
+  TextOverlayResult,
 } from './types';
 
 const API_BASE = '';
@@ -14,6 +38,70 @@ function isTauri(): boolean {
   return typeof window !== 'undefined' && '__TAURI__' in window;
 }
 
+export type Locale = 'zh-CN' | 'en';
+
+/** Summary per `message_key`; `AppError.message` carries the specifics. */
+const ERROR_MESSAGES: Record<string, Record<Locale, string>> = {
+  'errors.invalid_input': { 'zh-CN': '输入无效', en: 'Invalid input' },
+  'errors.validation_failed': { 'zh-CN': '参数校验失败', en: 'Some parameters are not supported' },
+  'errors.not_found': { 'zh-CN': '未找到', en: 'Not found' },
+  'errors.conflict': { 'zh-CN': '当前状态不允许此操作', en: 'Not allowed in the current state' },
+  'errors.unsupported_model': { 'zh-CN': '不支持的模型', en: 'Unsupported model' },
+  'errors.config_missing': { 'zh-CN': '请先完成配置', en: 'Configuration is missing' },
+  'errors.api_key_missing': { 'zh-CN': '请先配置API Key', en: 'API key is missing' },
+  'errors.cancelled': { 'zh-CN': '任务已取消', en: 'Cancelled' },
+  'errors.provider_rate_limited': { 'zh-CN': '请求过于频繁，请稍后重试', en: 'Rate limited, try again later' },
+  'errors.provider_unavailable': { 'zh-CN': '服务暂时不可用', en: 'Service unavailable' },
+  'errors.provider_auth': { 'zh-CN': 'API Key 无效或已过期', en: 'API key is invalid or expired' },
+  'errors.content_blocked': { 'zh-CN': '内容被安全策略拦截', en: 'Blocked by content policy' },
+  'errors.provider_rejected': { 'zh-CN': '服务拒绝了请求', en: 'The provider rejected the request' },
+  'errors.provider_failed': { 'zh-CN': '生成失败', en: 'Generation failed' },
+  'errors.io': { 'zh-CN': '文件读写失败', en: 'File access failed' },
+  'errors.crypto': { 'zh-CN': '配置解密失败', en: 'Could not decrypt the configuration' },
+  'errors.internal': { 'zh-CN': '内部错误', en: 'Internal error' },
+};
+
+/** Error thrown by every call below; branch on `code`, show `localized()` or `message`. */
+export class AppError extends Error {
+  constructor(
+    public readonly code: ErrorCode,
+    message: string,
+    public readonly fieldErrors: FieldError[] = [],
+    public readonly messageKey: string = `errors.${code}`
+  ) {
+    super(message);
+    this.name = 'AppError';
+  }
+
+  /** Translated summary for `locale`, falling back to `message`. */
+  localized(locale: Locale): string {
+    return ERROR_MESSAGES[this.messageKey]?.[locale] ?? this.message;
+  }
+}
+
+function toAppError(payload: unknown, fallback: string): AppError {
+  const data = payload as Partial<AppErrorPayload> & Partial<ImageGenerationResult>;
+  if (data && typeof data === 'object') {
+    if (data.code) {
+      return new AppError(data.code, data.message ?? fallback, data.field_errors, data.message_key);
+    }
+    // /api/generate answers failures with the result itself
+    if (data.error_code) {
+      return new AppError(data.error_code, data.error ?? fallback, data.field_errors);
+    }
+  }
+  return new AppError('internal', typeof payload === 'string' ? payload : fallback);
+}
+
+async function invoke<T>(command: string, args?: Record<string, unknown>): Promise<T> {
+  const core = await import('@tauri-apps/api/core');
+  try {
+    return await core.invoke<T>(command, args);
+  } catch (error) {
+    throw toAppError(error, String(error));
+  }
+}
+
 async function fetchApi<T>(endpoint: string, body?: unknown): Promise<T> {
   const url = `${API_BASE}${endpoint}`;
   const isGet = !body;
@@ -34,11 +122,12 @@ async function fetchApi<T>(endpoint: string, body?: unknown): Promise<T> {
   } catch (error) {
     const axiosError = error as AxiosError;
     if (axiosError.response) {
-      throw new Error(
+      throw toAppError(
+        axiosError.response.data,
         `API Error: ${axiosError.response.status} - ${JSON.stringify(axiosError.response.data)}`
       );
     } else if (axiosError.request) {
-      throw new Error(`API Error: Network Error - ${axiosError.message}`);
+      throw new AppError('provider_unavailable', `API Error: Network Error - ${axiosError.message}`);
     }
     throw error;
   }
@@ -46,7 +135,6 @@ async function fetchApi<T>(endpoint: string, body?: unknown): Promise<T> {
 
 export async function parsePrompt(prompt: string): Promise<ParsedPrompt> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<ParsedPrompt>('parse_prompt', { prompt });
   }
   return fetchApi<ParsedPrompt>('/api/parse', { prompt });
@@ -54,7 +142,6 @@ export async function parsePrompt(prompt: string): Promise<ParsedPrompt> {
 
 export async function getBindingsForPrompt(characters: string[]): Promise<CharacterBinding[]> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     const res = await invoke<CharacterBinding[]>('get_bindings_for_prompt', { characters });
     return res.map(normalizeCharacterBinding);
   }
@@ -66,7 +153,6 @@ export async function getBindingsForPrompt(characters: string[]): Promise<Charac
 
 export async function getAllBindings(): Promise<CharacterBinding[]> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     const res = await invoke<CharacterBinding[]>('get_all_bindings');
     return res.map(normalizeCharacterBinding);
   }
@@ -80,7 +166,6 @@ export async function saveReferenceImage(
   imageType: string
 ): Promise<CharacterBinding> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     const res = await invoke<CharacterBinding>('save_reference_image', {
       characterName,
       imageData,
@@ -102,7 +187,6 @@ export async function bindCharacterReference(
   imageType: string
 ): Promise<CharacterBinding> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     const res = await invoke<CharacterBinding>('bind_character_reference', {
       characterName,
       referenceImagePath,
@@ -120,16 +204,52 @@ export async function bindCharacterReference(
 
 export async function unbindCharacter(characterName: string): Promise<boolean> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<boolean>('unbind_character', { characterName });
   }
   return fetchApi<boolean>('/api/unbind', { characterName });
 }
 
-export async function generateImage(params: ImageGenerationParams): Promise<ImageGenerationResult> {
+export async function generateImage(
+  params: ImageGenerationParams,
+  onProgress?: (event: GenerationEvent) => void
+): Promise<ImageGenerationResult> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
-    return invoke<ImageGenerationResult>('generate_image', { params });
+    const { listen } = await import('@tauri-apps/api/event');
+
+    // generate_image returns the task id right away; the result arrives as an event.
+    // Listen first and buffer, since a fast task can finish before invoke resolves.
+    const buffered: GenerationEvent[] = [];
+    let taskId: string | null = null;
+    let settle: ((result: ImageGenerationResult) => void) | null = null;
+
+    const handle = (event: GenerationEvent) => {
+      onProgress?.(event);
+      if (event.result) {
+        settle?.(event.result);
+      }
+    };
+
+    const unlisten = await listen<GenerationEvent>('generation-progress', ({ payload }) => {
+      if (taskId === null) {
+        buffered.push(payload);
+      } else if (payload.task_id === taskId) {
+        handle(payload);
+      }
+    });
+
+    try {
+      return await new Promise<ImageGenerationResult>((resolve, reject) => {
+        settle = resolve;
+        invoke<{ task_id: string }>('generate_image', { params })
+          .then(started => {
+            taskId = started.task_id;
+            buffered.filter(e => e.task_id === taskId).forEach(handle);
+          })
+          .catch(reject);
+      });
+    } finally {
+      unlisten();
+    }
   }
 
   const body = {
@@ -154,9 +274,226 @@ export async function generateImage(params: ImageGenerationParams): Promise<Imag
   return fetchApi<ImageGenerationResult>('/api/generate', body);
 }
 
+export async function cancelGeneration(taskId: string): Promise<boolean> {
+  if (isTauri()) {
+    return invoke<boolean>('cancel_generation', { taskId });
+  }
+  return fetchApi<boolean>('/api/generate/cancel', { taskId });
+}
+
+/** Generates every row of a CSV/JSONL prompt file; resolves once the whole batch is done. */
+export async function runBatchGeneration(filePath: string, outputDir?: string): Promise<BatchManifest> {
+  if (isTauri()) {
+    return invoke<BatchManifest>('run_batch_generation', { filePath, outputDir });
+  }
+  return fetchApi<BatchManifest>('/api/batch', { filePath, outputDir });
+}
+
+export async function listHistory(filter: HistoryFilter = {}): Promise<HistoryPage> {
+  if (isTauri()) {
+    return invoke<HistoryPage>('list_history', { filter });
+  }
+  return fetchApi<HistoryPage>('/api/history', filter);
+}
+
+export async function getHistoryEntry(id: string): Promise<HistoryEntry> {
+  if (isTauri()) {
+    return invoke<HistoryEntry>('get_history_entry', { id });
+  }
+  return fetchApi<HistoryEntry>('/api/history/get', { id });
+}
+
+export async function deleteHistoryEntry(id: string): Promise<boolean> {
+  if (isTauri()) {
+    return invoke<boolean>('delete_history_entry', { id });
+  }
+  return fetchApi<boolean>('/api/history/delete', { id });
+}
+
+/** Runs a past generation again with the same prompt, bindings and parameters. */
+export async function rerunGeneration(
+  id: string,
+  onProgress?: (event: GenerationEvent) => void
+): Promise<ImageGenerationResult> {
+  if (isTauri()) {
+    const entry = await getHistoryEntry(id);
+    // Inline reference images are only kept as `sha256:` hashes in the history
+    const images = entry.params.images?.filter((image) => !image.startsWith('sha256:'));
+    return generateImage({ ...entry.params, images, priority: 'interactive' }, onProgress);
+  }
+  return fetchApi<ImageGenerationResult>('/api/history/rerun', { id });
+}
+
+export async function listGallery(query: GalleryQuery = {}): Promise<GalleryPage> {
+  if (isTauri()) {
+    return invoke<GalleryPage>('list_gallery', { query });
+  }
+  return fetchApi<GalleryPage>('/api/gallery', query);
+}
+
+export async function listGalleryTags(): Promise<string[]> {
+  if (isTauri()) {
+    return invoke<string[]>('list_gallery_tags');
+  }
+  return fetchApi<string[]>('/api/gallery/tags');
+}
+
+export async function setGalleryFavorite(id: string, favorite: boolean): Promise<GalleryItem> {
+  if (isTauri()) {
+    return invoke<GalleryItem>('set_gallery_favorite', { id, favorite });
+  }
+  return fetchApi<GalleryItem>('/api/gallery/favorite', { id, favorite });
+}
+
+export async function setGalleryRating(id: string, rating: number): Promise<GalleryItem> {
+  if (isTauri()) {
+    return invoke<GalleryItem>('set_gallery_rating', { id, rating });
+  }
+  return fetchApi<GalleryItem>('/api/gallery/rating', { id, rating });
+}
+
+export async function setGalleryTags(id: string, tags: string[]): Promise<GalleryItem> {
+  if (isTauri()) {
+    return invoke<GalleryItem>('set_gallery_tags', { id, tags });
+  }
+  return fetchApi<GalleryItem>('/api/gallery/tags/set', { id, tags });
+}
+
+export async function trashGalleryItems(ids: string[]): Promise<number> {
+  if (isTauri()) {
+    return invoke<number>('trash_gallery_items', { ids });
+  }
+  return fetchApi<number>('/api/gallery/trash', { ids });
+}
+
+export async function restoreGalleryItems(ids: string[]): Promise<number> {
+  if (isTauri()) {
+    return invoke<number>('restore_gallery_items', { ids });
+  }
+  return fetchApi<number>('/api/gallery/restore', { ids });
+}
+
+export async function emptyGalleryTrash(): Promise<number> {
+  if (isTauri()) {
+    return invoke<number>('empty_gallery_trash');
+  }
+  return fetchApi<number>('/api/gallery/trash/empty', {});
+}
+
+export async function listProjects(): Promise<ProjectSummary[]> {
+  if (isTauri()) {
+    return invoke<ProjectSummary[]>('list_projects');
+  }
+  return fetchApi<ProjectSummary[]>('/api/projects');
+}
+
+export async function createProject(name: string): Promise<Project> {
+  if (isTauri()) {
+    return invoke<Project>('create_project', { name });
+  }
+  return fetchApi<Project>('/api/projects/create', { name });
+}
+
+export async function getProject(projectId: string): Promise<Project> {
+  if (isTauri()) {
+    return invoke<Project>('get_project', { projectId });
+  }
+  return fetchApi<Project>('/api/projects/get', { projectId });
+}
+
+export async function updateProjectSettings(projectId: string, settings: ProjectSettings): Promise<Project> {
+  if (isTauri()) {
+    return invoke<Project>('update_project_settings', { projectId, settings });
+  }
+  return fetchApi<Project>('/api/projects/settings', { projectId, settings });
+}
+
+export async function deleteProject(projectId: string): Promise<boolean> {
+  if (isTauri()) {
+    return invoke<boolean>('delete_project', { projectId });
+  }
+  return fetchApi<boolean>('/api/projects/delete', { projectId });
+}
+
+export async function addPage(projectId: string, text: string, prompt: string, index?: number): Promise<Project> {
+  if (isTauri()) {
+    return invoke<Project>('add_page', { projectId, text, prompt, index });
+  }
+  return fetchApi<Project>('/api/projects/pages/add', { projectId, text, prompt, index });
+}
+
+export async function updatePage(projectId: string, pageId: string, update: PageUpdate): Promise<Project> {
+  if (isTauri()) {
+    return invoke<Project>('update_page', { projectId, pageId, update });
+  }
+  return fetchApi<Project>('/api/projects/pages/update', { projectId, pageId, update });
+}
+
+export async function deletePage(projectId: string, pageId: string): Promise<Project> {
+  if (isTauri()) {
+    return invoke<Project>('delete_page', { projectId, pageId });
+  }
+  return fetchApi<Project>('/api/projects/pages/delete', { projectId, pageId });
+}
+
+export async function reorderPages(projectId: string, pageIds: string[]): Promise<Project> {
+  if (isTauri()) {
+    return invoke<Project>('reorder_pages', { projectId, pageIds });
+  }
+  return fetchApi<Project>('/api/projects/pages/reorder', { projectId, pageIds });
+}
+
+export async function generatePage(projectId: string, pageId: string): Promise<PageGenerationResult> {
+  if (isTauri()) {
+    return invoke<PageGenerationResult>('generate_page', { projectId, pageId });
+  }
+  return fetchApi<PageGenerationResult>('/api/projects/pages/generate', { projectId, pageId });
+}
+
+export async function exportProjectPdf(
+  projectId: string,
+  options?: PdfExportOptions,
+  outputPath?: string
+): Promise<PdfExportReport> {
+  if (isTauri()) {
+    return invoke<PdfExportReport>('export_project_pdf', { projectId, outputPath, options });
+  }
+  return fetchApi<PdfExportReport>('/api/projects/export/pdf', { projectId, outputPath, options });
+}
+
+export async function exportProjectEpub(
+  projectId: string,
+  options?: EpubExportOptions,
+  outputPath?: string
+): Promise<EpubExportReport> {
+  if (isTauri()) {
+    return invoke<EpubExportReport>('export_project_epub', { projectId, outputPath, options });
+  }
+  return fetchApi<EpubExportReport>('/api/projects/export/epub', { projectId, outputPath, options });
+}
+
+export async function overlayText(
+  image: string,
+  text: string,
+  options?: TextOverlayOptions,
+  outputPath?: string
+): Promise<TextOverlayResult> {
+  if (isTauri()) {
+    return invoke<TextOverlayResult>('overlay_text', { image, text, options, outputPath });
+  }
+  return fetchApi<TextOverlayResult>('/api/overlay-text', { image, text, options, outputPath });
+}
+
+/** Reads embedded generation info (ours, A1111 or ComfyUI) from an image path, URL or data URL. */
+export async function importImageMetadata(image: string): Promise<ImportedImage> {
+  if (isTauri()) {
+    return invoke<ImportedImage>('import_image_metadata', { image });
+  }
+  return fetchApi<ImportedImage>('/api/image-metadata/import', { image });
+}
+
 export async function saveApiConfig(config: APIConfig): Promise<boolean> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<boolean>('save_api_config', { config });
   }
   return fetchApi<boolean>('/api/config/save', {
@@ -173,7 +510,6 @@ export async function saveApiConfig(config: APIConfig): Promise<boolean> {
 
 export async function loadApiConfig(): Promise<APIConfig> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<APIConfig>('load_api_config');
   }
   const result = await fetchApi<{
@@ -188,7 +524,6 @@ export async function loadApiConfig(): Promise<APIConfig> {
 
 export async function getDefaultApiConfig(): Promise<APIConfig> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<APIConfig>('get_default_api_config');
   }
   const result = await fetchApi<{
@@ -207,7 +542,6 @@ export async function testApiConnection(
   apiKey?: string
 ): Promise<boolean> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<boolean>('test_api_connection', { model, baseUrl, apiKey });
   }
   return fetchApi<boolean>('/api/test-connection', { model, baseUrl, apiKey });
@@ -215,7 +549,6 @@ export async function testApiConnection(
 
 export async function saveGenerationConfig(config: GenerationConfig): Promise<boolean> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<boolean>('save_generation_config', { config });
   }
   return fetchApi<boolean>('/api/generation-config/save', config);
@@ -223,41 +556,48 @@ export async function saveGenerationConfig(config: GenerationConfig): Promise<bo
 
 export async function loadGenerationConfig(): Promise<GenerationConfig> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<GenerationConfig>('load_generation_config');
   }
   return fetchApi<GenerationConfig>('/api/generation-config/load');
 }
 
-export function getImageUrl(path: string | undefined | null): string {
+/** URL of a local image; with `size`, a cached thumbnail at most that many px wide/high. */
+export function getImageUrl(path: string | undefined | null, size?: number): string {
   if (!path || path.trim() === '') return '';
   if (path.startsWith('http') || path.startsWith('data:')) return path;
 
   let cleanPath = path.replace(/^file:\/\//, '').replace(/^file:/, '');
   cleanPath = cleanPath.replace(/\//g, '\\');
 
+  const query = `path=${encodeURIComponent(cleanPath)}${size ? `&size=${size}` : ''}`;
   if (isTauri()) {
-    return `http://127.0.0.1:8888/api/image?path=${encodeURIComponent(cleanPath)}`;
+    return `http://127.0.0.1:8888/api/image?${query}`;
   }
-  return `${API_BASE}/api/image?path=${encodeURIComponent(cleanPath)}`;
+  return `${API_BASE}/api/image?${query}`;
 }
 
-export async function saveImageToFile(imageUrl: string, filePath: string): Promise<string> {
+export async function saveImageToFile(
+  imageUrl: string,
+  filePath: string,
+  metadata?: ImageMetadata
+): Promise<string> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
-    return invoke<string>('save_image_to_file', { imageUrl, filePath });
+    return invoke<string>('save_image_to_file', { imageUrl, filePath, metadata });
   }
   throw new Error('Save image only supported in Tauri app');
 }
 
-export async function saveImageDialog(imageUrl: string): Promise<string | null> {
+export async function saveImageDialog(
+  imageUrl: string,
+  metadata?: ImageMetadata
+): Promise<string | null> {
   if (isTauri()) {
     const { save } = await import('@tauri-apps/plugin-dialog');
     const filePath = await save({
       filters: [{ name: 'Images', extensions: ['png', 'jpg', 'jpeg', 'webp'] }],
     });
     if (filePath) {
-      return saveImageToFile(imageUrl, filePath);
+      return saveImageToFile(imageUrl, filePath, metadata);
     }
     return null;
   }
@@ -344,7 +684,6 @@ export interface ReferenceImageQuery {
 
 export async function getReferenceImages(query?: ReferenceImageQuery): Promise<CharacterBinding[]> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     const res = await invoke<CharacterBinding[]>('get_reference_images', { query });
     return res.map(normalizeCharacterBinding);
   }
@@ -358,7 +697,6 @@ export async function getReferenceImages(query?: ReferenceImageQuery): Promise<C
 
 export async function searchReferenceImages(keyword: string): Promise<CharacterBinding[]> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     const res = await invoke<CharacterBinding[]>('search_reference_images', { keyword });
     return res.map(normalizeCharacterBinding);
   }
@@ -370,7 +708,6 @@ export async function searchReferenceImages(keyword: string): Promise<CharacterB
 
 export async function addTagToReference(characterName: string, tag: string): Promise<boolean> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<boolean>('add_tag_to_reference', { characterName, tag });
   }
   return fetchApi<boolean>('/api/reference-images/add-tag', { characterName, tag });
@@ -378,7 +715,6 @@ export async function addTagToReference(characterName: string, tag: string): Pro
 
 export async function removeTagFromReference(characterName: string, tag: string): Promise<boolean> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<boolean>('remove_tag_from_reference', { characterName, tag });
   }
   return fetchApi<boolean>('/api/reference-images/remove-tag', { characterName, tag });
@@ -386,7 +722,6 @@ export async function removeTagFromReference(characterName: string, tag: string)
 
 export async function getAllTags(): Promise<string[]> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<string[]>('get_all_tags');
   }
   return fetchApi<string[]>('/api/reference-images/tags');
@@ -394,7 +729,6 @@ export async function getAllTags(): Promise<string[]> {
 
 export async function getReferencesByType(imageType: string): Promise<CharacterBinding[]> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     const res = await invoke<CharacterBinding[]>('get_references_by_type', { imageType });
     return res.map(normalizeCharacterBinding);
   }
@@ -406,7 +740,6 @@ export async function getReferencesByType(imageType: string): Promise<CharacterB
 
 export async function deleteReferenceImage(characterName: string): Promise<boolean> {
   if (isTauri()) {
-    const { invoke } = await import('@tauri-apps/api/core');
     return invoke<boolean>('delete_reference_image', { characterName });
   }
   return fetchApi<boolean>('/api/reference-images/delete', { characterName });
@@ -51,13 +51,99 @@ export interface ImageGenerationParams {
   response_format?: 'url' | 'b64_json';
   watermark?: boolean;
   images?: string[];
+  negative_prompt?: string;
+  /** Reuse a result's `seed` to reproduce it; generated when omitted. */
+  seed?: number;
+  priority?: JobPriority;
+}
+
+/** Generation details embedded into saved PNG/JPEG files. */
+export interface ImageMetadata {
+  prompt: string;
+  negativePrompt?: string;
+  model: string;
+  width: number;
+  height: number;
+  seed?: number;
+  characterBindings?: CharacterBindingInfo[];
+  taskId?: string;
+}
+
+export type MetadataSource = 'huiben' | 'a1111' | 'comfyui';
+
+/** Generation info read back from a PNG/JPEG by `importImageMetadata`. */
+export interface ImportedImage {
+  source: MetadataSource;
+  metadata: {
+    prompt: string;
+    negative_prompt?: string;
+    segments: PromptSegment[];
+    /** As recorded; may be a checkpoint name rather than one of our providers. */
+    model: string;
+    width: number;
+    height: number;
+    seed?: number;
+    character_bindings: CharacterBindingInfo[];
+    created_at: string;
+    task_id?: string;
+  };
+  /** Current defaults with the imported prompt, size, seed and model applied. */
+  params: ImageGenerationParams;
+  parsed: ParsedPrompt;
+}
+
+export type ErrorCode =
+  | 'invalid_input'
+  | 'validation_failed'
+  | 'not_found'
+  | 'conflict'
+  | 'unsupported_model'
+  | 'config_missing'
+  | 'api_key_missing'
+  | 'cancelled'
+  | 'provider_rate_limited'
+  | 'provider_unavailable'
+  | 'provider_auth'
+  | 'content_blocked'
+  | 'provider_rejected'
+  | 'provider_failed'
+  | 'io'
+  | 'crypto'
+  | 'internal';
+
+export interface FieldError {
+  field: string;
+  message: string;
+}
+
+/** Error body of Tauri commands and non-2xx HTTP responses. */
+export interface AppErrorPayload {
+  code: ErrorCode;
+  message: string;
+  /** `errors.<code>`, for client-side translation. */
+  message_key?: string;
+  field_errors?: FieldError[];
+}
+
+/** Local copy of a generated image; provider URLs expire. */
+export interface ArchivedImage {
+  /** Original URL; absent for inline `data:` images. */
+  url?: string;
+  /** `outputs/<sha256>.<ext>`; absent when the download failed. */
+  path?: string;
 }
 
 export interface ImageGenerationResult {
   success: boolean;
   images: string[];
+  /** Downloaded copies of `images`, same order. */
+  outputs?: ArchivedImage[];
   error?: string;
-  taskId?: string;
+  error_code?: ErrorCode;
+  field_errors?: FieldError[];
+  task_id: string;
+  /** Seed sent to the provider; absent when the provider does not support seeds. */
+  seed?: number;
 }
 
 export interface APIConfig {
@@ -71,13 +157,35 @@ export interface APIConfig {
   };
 }
 
+export type GenerationStage =
+  | 'queued'
+  | 'uploading_refs'
+  | 'calling_model'
+  | 'downloading'
+  | 'done'
+  | 'failed'
+  | 'cancelled';
+
+export interface GenerationEvent {
+  task_id: string;
+  stage: GenerationStage;
+  progress: number;
+  message?: string;
+  /** 1-based place among jobs waiting for the same provider, on `queued` events. */
+  queue_position?: number;
+  result?: ImageGenerationResult;
+}
+
 export interface GenerationProgress {
   taskId: string;
   status: 'pending' | 'processing' | 'completed' | 'failed';
   progress: number;
   message?: string;
+  queue_position?: number;
 }
 
+export type JobPriority = 'interactive' | 'batch';
+
 export interface GenerationConfig {
   model: 'seedream' | 'banana_pro';
   width: number;
@@ -89,3 +197,240 @@ export interface GenerationConfig {
   response_format?: 'url' | 'b64_json';
   watermark?: boolean;
 }
+
+export interface BatchItem {
+  row: number;
+  prompt: string;
+  model?: string;
+  task_id?: string;
+  success: boolean;
+  /** Relative to `output_dir`. */
+  files: string[];
+  error?: string;
+  error_code?: ErrorCode;
+}
+
+export interface HistoryEntry {
+  /** Task id of the generation. */
+  id: string;
+  created_at: string;
+  duration_ms: number;
+  model: string;
+  prompt: string;
+  segments: PromptSegment[];
+  character_bindings: CharacterBindingInfo[];
+  params: ImageGenerationParams;
+  success: boolean;
+  outputs: ArchivedImage[];
+  error?: string;
+  error_code?: ErrorCode;
+  effective_size?: string;
+}
+
+/** All fields optional, combined with AND. */
+export interface HistoryFilter {
+  query?: string;
+  model?: string;
+  character?: string;
+  success?: boolean;
+  /** RFC 3339 or `YYYY-MM-DD`. */
+  since?: string;
+  until?: string;
+  limit?: number;
+  offset?: number;
+}
+
+export interface HistoryPage {
+  total: number;
+  /** Newest first. */
+  entries: HistoryEntry[];
+}
+
+/** An archived output; `id` is its content hash. */
+export interface GalleryItem {
+  id: string;
+  path: string;
+  url?: string;
+  task_id: string;
+  prompt: string;
+  model: string;
+  created_at: string;
+  characters: string[];
+  size?: string;
+  tags: string[];
+  favorite: boolean;
+  /** 0 (unrated) to 5. */
+  rating: number;
+  trashed_at?: string;
+}
+
+/** All fields optional, combined with AND. */
+export interface GalleryQuery {
+  search?: string;
+  model?: string;
+  character?: string;
+  tag?: string;
+  /** RFC 3339 or `YYYY-MM-DD`. */
+  since?: string;
+  until?: string;
+  favorite?: boolean;
+  minRating?: number;
+  /** List the trash instead of the gallery. */
+  trashed?: boolean;
+  limit?: number;
+  offset?: number;
+}
+
+export interface GalleryPage {
+  total: number;
+  /** Newest first. */
+  items: GalleryItem[];
+}
+
+export interface BatchManifest {
+  source: string;
+  output_dir: string;
+  started_at: string;
+  finished_at: string;
+  total: number;
+  succeeded: number;
+  failed: number;
+  items: BatchItem[];
+}
+
+export interface ProjectPage {
+  id: string;
+  text: string;
+  prompt: string;
+  /** Overrides the saved generation config for this page. */
+  params?: GenerationConfig;
+  negative_prompt?: string;
+  /** Pinned seed so the page reproduces; absent draws a new one each time. */
+  seed?: number;
+  /** Seed of the latest generation, to pin when it came out well. */
+  last_seed?: number;
+  image?: string;
+  images: string[];
+}
+
+export interface Project {
+  id: string;
+  name: string;
+  created_at: string;
+  updated_at: string;
+  pages: ProjectPage[];
+  /** Project-scoped bindings, keyed by character name. */
+  bindings: Record<string, CharacterBinding>;
+  settings: ProjectSettings;
+}
+
+/** Book metadata written into exports. */
+export interface ProjectSettings {
+  author: string;
+  /** BCP 47 tag, e.g. `zh-CN`. */
+  language: string;
+  description: string;
+}
+
+export interface ProjectSummary {
+  id: string;
+  name: string;
+  updated_at: string;
+  page_count: number;
+  cover?: string;
+}
+
+export interface PageUpdate {
+  text?: string;
+  prompt?: string;
+  params?: GenerationConfig;
+  /** Empty clears it. */
+  negative_prompt?: string;
+  /** `null` unpins the seed. */
+  seed?: number | null;
+  image?: string;
+}
+
+export interface PageGenerationResult {
+  page: ProjectPage;
+  result: ImageGenerationResult;
+}
+
+export interface PdfExportOptions {
+  /** `A4`, `A5`, `B5`, `letter`, `square` or a custom `WxH` in mm. */
+  pageSize?: string;
+  bleedMm?: number;
+  marginMm?: number;
+  minDpi?: number;
+  strictDpi?: boolean;
+  fontPath?: string;
+  /** pt */
+  fontSize?: number;
+  lineSpacing?: number;
+}
+
+export interface ExportWarning {
+  page: number;
+  page_id: string;
+  message: string;
+}
+
+export interface PdfExportReport {
+  path: string;
+  pages: number;
+  warnings: ExportWarning[];
+}
+
+export type OverlayPreset = 'top_band' | 'bottom_band' | 'speech_bubble';
+
+/** Speech bubble placement as fractions (0-1) of the image size. */
+export interface BubblePlacement {
+  x?: number;
+  y?: number;
+  width?: number;
+  tailX?: number;
+  tailY?: number;
+}
+
+/** Colors are `#RRGGBB` or `#RRGGBBAA`; sizes are in image pixels. */
+export interface TextOverlayOptions {
+  preset?: OverlayPreset;
+  fontPath?: string;
+  fontSize?: number;
+  color?: string;
+  lineSpacing?: number;
+  align?: 'left' | 'center' | 'right';
+  /** Multiple of the font size. */
+  padding?: number;
+  /** Empty for no band/bubble fill. */
+  boxColor?: string;
+  outlineColor?: string;
+  outlineWidth?: number;
+  /** Empty disables the shadow. */
+  shadowColor?: string;
+  shadowOffsetX?: number;
+  shadowOffsetY?: number;
+  shadowBlur?: number;
+  bubble?: BubblePlacement;
+}
+
+export interface TextOverlayResult {
+  path: string;
+  lines: string[];
+  missing_glyphs: string[];
+}
+
+export interface EpubExportOptions {
+  /** Page viewport in px. */
+  width?: number;
+  /** Follows the first image's aspect ratio when omitted. */
+  height?: number;
+  /** px */
+  fontSize?: number;
+}
+
+export interface EpubExportReport {
+  path: string;
+  pages: number;
+  warnings: ExportWarning[];
+}